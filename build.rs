@@ -1,42 +1,75 @@
 use std::env;
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
 const PUBLIC_SERVER_LIST_URL: &str = "https://publist.mumble.info/v1/list";
+const BUNDLED_SERVER_LIST: &str = "public_servers.xml";
+
+fn fetch_server_list() -> Result<String, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let resp = client
+        .get(PUBLIC_SERVER_LIST_URL)
+        .send()
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("status {}", resp.status()));
+    }
+    resp.text().map_err(|e| e.to_string())
+}
 
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let dest_path = Path::new(&out_dir).join("public_server_list.rs");
+    let selected_xml_path = Path::new(&out_dir).join("public_server_list.xml");
     let cached_xml_path = Path::new(&out_dir).join("public_servers.xml");
 
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", BUNDLED_SERVER_LIST);
+    println!("cargo:rerun-if-env-changed=MUMBLE_OFFLINE_BUILD");
 
-    // Attempt to fetch the server list
-    println!("--- Mumble Build Script: Attempting to fetch public server list...");
-    let server_xml = match reqwest::blocking::get(PUBLIC_SERVER_LIST_URL) {
-        Ok(resp) => {
-            if resp.status().is_success() {
-                let xml = resp.text().unwrap_or_default();
-                println!("--- Mumble Build Script: Successfully fetched and cached server list.");
-                fs::write(&cached_xml_path, &xml).expect("Unable to write cached XML");
-                xml
-            } else {
-                println!("--- Mumble Build Script: Fetch failed with status: {}", resp.status());
-                // Fetch failed, try to use cache
-                fs::read_to_string(&cached_xml_path).expect("Failed to fetch public server list and no cached version was available. Please check your internet connection.")
-            }
+    // The embedded list is only a fallback for the runtime fetch in `public`,
+    // so a build must never fail just because the network is unavailable.
+    let fetched = if env::var_os("MUMBLE_OFFLINE_BUILD").is_some() {
+        Err("MUMBLE_OFFLINE_BUILD is set".to_string())
+    } else {
+        println!("--- Mumble Build Script: Attempting to fetch public server list...");
+        fetch_server_list()
+    };
+
+    let server_xml = match fetched {
+        Ok(xml) => {
+            println!("--- Mumble Build Script: Successfully fetched and cached server list.");
+            fs::write(&cached_xml_path, &xml).expect("Unable to write cached XML");
+            xml
         }
         Err(e) => {
-            println!("--- Mumble Build Script: Fetch failed with error: {}", e);
-            // If fetching fails, try to use the cached version
-            fs::read_to_string(&cached_xml_path).expect("Failed to fetch public server list and no cached version was available. Please check your internet connection.")
+            println!("--- Mumble Build Script: Fetch skipped or failed: {}", e);
+            match fs::read_to_string(&cached_xml_path) {
+                Ok(xml) => {
+                    println!("--- Mumble Build Script: Using previously cached server list.");
+                    xml
+                }
+                Err(_) => {
+                    println!(
+                        "--- Mumble Build Script: Using bundled {}.",
+                        BUNDLED_SERVER_LIST
+                    );
+                    fs::read_to_string(BUNDLED_SERVER_LIST)
+                        .expect("Bundled public server list is missing")
+                }
+            }
         }
     };
 
+    fs::write(&selected_xml_path, server_xml).unwrap();
     let rust_code = format!(
-        "const PUBLIC_SERVER_LIST_XML: &str = r#\"{}\"#;",
-        server_xml
+        "const PUBLIC_SERVER_LIST_XML: &str = include_str!({:?});",
+        selected_xml_path
     );
 
     fs::write(&dest_path, rust_code).unwrap();
-}
\ No newline at end of file
+}
@@ -1,52 +1,535 @@
 use anyhow::Result;
+use clap::{Parser, Subcommand};
+use crossterm::event::EventStream;
+use futures_util::{future, StreamExt};
 use mumble::{
-    embed, lan, public,
-    ui::client::{LocalServerState, ServerCommand, Tui},
+    connection::{self, ClientSession, ConnectionEvent},
+    embed,
+    identity::{ClientIdentity, IdentitySeed, IdentityStore},
+    lan,
+    local::{self, LocalServerSettings},
+    log_buffer::{self, LogBuffer, SharedLogBuffer},
+    mumble_url::MumbleUrl,
+    ping::{self, PingReply},
+    proto::{self, Message},
+    public,
+    server_book::ServerBook,
+    ui::client::{AppState, LocalServerState, ServerCommand, SessionCommand, Tui},
+    ui::settings::ClientSettings,
+    ui::tabs::{self, TabId},
 };
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::{
     sync::{mpsc, oneshot},
     task,
+    time::MissedTickBehavior,
 };
 
+type ConnectResult = (ClientSession, mpsc::UnboundedReceiver<ConnectionEvent>);
+
+/// How many public servers are pinged at the same time.
+const PING_CONCURRENCY: usize = 32;
+/// How often state that doesn't notify the main loop is checked for changes.
+const RENDER_TICK: Duration = Duration::from_millis(250);
+/// How often the server in the detail view is pinged again.
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Waits for an optional task; never completes if there is none.
+async fn join_task<T>(handle: &mut Option<task::JoinHandle<T>>) -> Result<T, task::JoinError> {
+    match handle {
+        Some(handle) => handle.await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the first of the connection attempts to finish; never
+/// completes if there are none.
+async fn join_connect(
+    handles: &mut HashMap<TabId, task::JoinHandle<Result<ConnectResult>>>,
+) -> (TabId, Result<Result<ConnectResult>, task::JoinError>) {
+    if handles.is_empty() {
+        return std::future::pending().await;
+    }
+    let joins = handles
+        .iter_mut()
+        .map(|(&tab, handle)| Box::pin(async move { (tab, handle.await) }));
+    let (result, _, _) = future::select_all(joins).await;
+    handles.remove(&result.0);
+    result
+}
+
+/// Receives the next event of any session; never completes if there are
+/// none. `None` means the session's events ended.
+async fn recv_event(sessions: &mut HashMap<TabId, ConnectResult>) -> (TabId, Option<ConnectionEvent>) {
+    if sessions.is_empty() {
+        return std::future::pending().await;
+    }
+    let receives = sessions
+        .iter_mut()
+        .map(|(&tab, (_, events))| Box::pin(async move { (tab, events.recv().await) }));
+    future::select_all(receives).await.0
+}
+
+/// Sends `message` on the session of a tab, reporting failures in its log.
+fn send_to_server(
+    sessions: &HashMap<TabId, ConnectResult>,
+    app_state: &mut AppState,
+    tab: TabId,
+    message: Message,
+) {
+    if !tabs::may_send(app_state.voice_tab(), tab, &message) {
+        app_state.log_to(tab, "[ERROR] Voice is only transmitted on the shown tab.".to_string());
+        return;
+    }
+    let result = match sessions.get(&tab) {
+        Some((session, _)) => session.send(message),
+        None => Err(anyhow::anyhow!("Not connected to a server")),
+    };
+    if let Err(e) = result {
+        app_state.log_to(tab, format!("[ERROR] {}", e));
+    }
+}
+
+/// Waits for the server started last to listen; never completes while
+/// none is starting. `false` means it failed to start.
+async fn wait_ready(ready: &mut Option<oneshot::Receiver<()>>) -> bool {
+    match ready {
+        Some(ready) => ready.await.is_ok(),
+        None => std::future::pending().await,
+    }
+}
+
 async fn start_server_task(
-    log_buffer: Arc<Mutex<Vec<String>>>,
-) -> (task::JoinHandle<Result<()>>, oneshot::Sender<()>) {
+    settings: LocalServerSettings,
+    log_buffer: SharedLogBuffer,
+) -> (task::JoinHandle<Result<()>>, oneshot::Sender<()>, oneshot::Receiver<()>) {
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
-    let handle = task::spawn(embed::run_embedded_server(log_buffer, shutdown_rx));
-    (handle, shutdown_tx)
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let handle = task::spawn(embed::run_embedded_server(
+        settings,
+        log_buffer,
+        ready_tx,
+        shutdown_rx,
+    ));
+    (handle, shutdown_tx, ready_rx)
+}
+
+fn spawn_public_refresh(list: &public::PublicList) -> task::JoinHandle<Result<public::ServerList>> {
+    let list = list.clone();
+    task::spawn(async move { list.refresh().await })
+}
+
+fn spawn_probe(host: String, port: u16, tx: mpsc::Sender<(String, u16, ping::Probe)>) {
+    task::spawn(async move {
+        let probe = ping::probe(&host, port, ping::DEFAULT_PING_TIMEOUT).await;
+        tx.send((host, port, probe)).await.ok();
+    });
+}
+
+fn spawn_public_pings(
+    targets: Vec<(String, u16)>,
+    tx: mpsc::Sender<(String, u16, Result<PingReply>)>,
+) {
+    task::spawn(ping::ping_many(
+        targets,
+        PING_CONCURRENCY,
+        ping::DEFAULT_PING_TIMEOUT,
+        tx,
+    ));
+}
+
+/// Mumble terminal client
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Args {
+    /// Connect to a mumble://[user[:password]@]host[:port][/Channel/Sub] URL
+    #[arg(value_parser = MumbleUrl::parse)]
+    url: Option<MumbleUrl>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the certificates used to identify you to servers
+    #[command(subcommand)]
+    Identity(IdentityCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum IdentityCommand {
+    /// List stored identities and their certificate hashes
+    List,
+    /// Generate a new self-signed identity
+    Generate { name: String },
+    /// Derive a reproducible identity from a seed or passphrase
+    ///
+    /// The same name and seed always give the same certificate hash, so the
+    /// identity can be recreated on another machine or registered in advance.
+    #[command(group(clap::ArgGroup::new("seed").required(true)))]
+    Derive {
+        name: String,
+        /// 32-byte seed as 64 hex characters, like mumble-server --key-from-hash
+        #[arg(long, group = "seed")]
+        key_from_hash: Option<String>,
+        /// Passphrase stretched into a seed with Argon2id
+        #[arg(long, group = "seed", env = "MUMBLE_IDENTITY_PASSPHRASE")]
+        passphrase: Option<String>,
+        /// Only print the certificate hash, don't store the identity
+        #[arg(long)]
+        print_only: bool,
+    },
+    /// Import a PKCS#12 bundle, e.g. a backup made by the official client
+    Import {
+        name: String,
+        file: PathBuf,
+        #[arg(long, default_value = "")]
+        password: String,
+    },
+    /// Export an identity as a PKCS#12 bundle
+    Export {
+        name: String,
+        file: PathBuf,
+        #[arg(long, default_value = "")]
+        password: String,
+    },
+    /// Delete a stored identity
+    Remove { name: String },
+    /// Print the SHA-1 certificate hash servers register users by
+    Hash { name: String },
+    /// Use an identity by default, or for one server with --server host[:port]
+    Use {
+        name: String,
+        #[arg(long)]
+        server: Option<String>,
+    },
+    /// Stop using a per-server identity for host[:port]
+    Forget { server: String },
+}
+
+fn run_identity_command(command: IdentityCommand) -> Result<()> {
+    let store = IdentityStore::default();
+    match command {
+        IdentityCommand::List => {
+            let default = store.default_name()?;
+            for name in store.list()? {
+                let identity = store.load(&name)?;
+                let marker = if default.as_deref() == Some(name.as_str()) {
+                    "*"
+                } else {
+                    " "
+                };
+                println!(
+                    "{} {:<20} {} {}",
+                    marker,
+                    name,
+                    identity.hash(),
+                    identity.subject()?
+                );
+            }
+            for (server, name) in store.server_selections()? {
+                println!("  {} -> {}", server, name);
+            }
+        }
+        IdentityCommand::Generate { name } => {
+            let identity = store.generate(&name)?;
+            println!("Generated identity '{}' ({})", name, identity.hash());
+        }
+        IdentityCommand::Derive {
+            name,
+            key_from_hash,
+            passphrase,
+            print_only,
+        } => {
+            let seed = match (key_from_hash, passphrase) {
+                (Some(hash), _) => IdentitySeed::from_hex(&hash)?,
+                (None, Some(passphrase)) => IdentitySeed::Passphrase(passphrase),
+                (None, None) => unreachable!("clap requires one seed argument"),
+            };
+            if print_only {
+                println!("{}", ClientIdentity::derive(&name, &seed)?.hash());
+            } else {
+                let identity = store.derive(&name, &seed)?;
+                println!("Derived identity '{}' ({})", name, identity.hash());
+            }
+        }
+        IdentityCommand::Import {
+            name,
+            file,
+            password,
+        } => {
+            let identity = store.import(&name, &file, &password)?;
+            println!("Imported identity '{}' ({})", name, identity.hash());
+        }
+        IdentityCommand::Export {
+            name,
+            file,
+            password,
+        } => {
+            store.export(&name, &file, &password)?;
+            println!("Exported identity '{}' to {}", name, file.display());
+        }
+        IdentityCommand::Remove { name } => {
+            store.remove(&name)?;
+            println!("Removed identity '{}'", name);
+        }
+        IdentityCommand::Hash { name } => {
+            println!("{}", store.load(&name)?.hash());
+        }
+        IdentityCommand::Use { name, server: None } => {
+            store.set_default(&name)?;
+            println!("Using identity '{}' by default", name);
+        }
+        IdentityCommand::Use {
+            name,
+            server: Some(server),
+        } => {
+            let (host, port) = connection::parse_address(&server)?;
+            store.set_for_server(&host, port, Some(&name))?;
+            println!("Using identity '{}' for {}:{}", name, host, port);
+        }
+        IdentityCommand::Forget { server } => {
+            let (host, port) = connection::parse_address(&server)?;
+            store.set_for_server(&host, port, None)?;
+            println!("{}:{} uses the default identity again", host, port);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    // Before the runtime starts any threads; see detect_local_offset.
+    log_buffer::detect_local_offset();
+    run()
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn run() -> Result<()> {
+    let args = Args::parse();
+    match args.command {
+        Some(Command::Identity(command)) => run_identity_command(command),
+        None => run_tui(args.url).await,
+    }
+}
+
+async fn run_tui(url: Option<MumbleUrl>) -> Result<()> {
     let lan_servers = lan::discover_servers().await;
-    let public_servers = match public::fetch_servers().await {
-        Ok(servers) => servers,
-        Err(e) => {
-            eprintln!("Failed to parse embedded public server list: {}", e);
-            vec![]
-        }
-    };
+    // Start from the cache (or the bundled list) so the UI comes up immediately,
+    // and fetch a fresh copy in the background if that is out of date.
+    let public_list = public::PublicList::default();
+    let public_servers = public_list.load_cached();
+    let mut refresh_handle: Option<task::JoinHandle<Result<public::ServerList>>> = None;
+    if public_servers.source.is_stale() {
+        refresh_handle = Some(spawn_public_refresh(&public_list));
+    }
     let (command_tx, mut command_rx) = mpsc::channel(10);
-    let server_log_buffer = Arc::new(Mutex::new(Vec::new()));
+    let server_log_buffer = LogBuffer::shared(log_buffer::DEFAULT_CAPACITY);
+    let local_settings_path = LocalServerSettings::default_path();
+    let local_settings = LocalServerSettings::load_from(&local_settings_path)?;
+    let server_book = ServerBook::load_from(&ServerBook::default_path())?;
+    let client_settings = ClientSettings::load_from(&ClientSettings::default_path())?;
 
     let mut tui = Tui::new(
         lan_servers,
         public_servers,
+        local_settings,
+        server_book,
+        client_settings,
         Arc::clone(&server_log_buffer),
-        command_tx,
+        command_tx.clone(),
     )?;
+    if let Some(url) = &url {
+        command_tx.try_send(tui.app_state.open_url(url)).ok();
+    }
+    tui.app_state.public_list_refreshing = refresh_handle.is_some();
+    let (ping_tx, mut ping_rx) = mpsc::channel(PING_CONCURRENCY);
+    spawn_public_pings(tui.app_state.public_ping_targets(), ping_tx.clone());
+    let (probe_tx, mut probe_rx) = mpsc::channel(4);
+
+    // Each tab has at most one connection attempt and one session.
+    let mut connecting: HashMap<TabId, task::JoinHandle<Result<ConnectResult>>> = HashMap::new();
+    let mut sessions: HashMap<TabId, ConnectResult> = HashMap::new();
 
     let mut server_handle: Option<task::JoinHandle<Result<()>>> = None;
     let mut shutdown_tx: Option<oneshot::Sender<()>> = None;
     let mut stopping_handle: Option<task::JoinHandle<Result<()>>> = None;
+    let mut server_ready: Option<oneshot::Receiver<()>> = None;
+
+    let mut terminal_events = EventStream::new();
+    let mut render_tick = tokio::time::interval(RENDER_TICK);
+    render_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut probe_tick = tokio::time::interval(PROBE_INTERVAL);
+    probe_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut seen_server_logs = 0;
+    let mut was_talking = false;
+    let mut dirty = true;
 
     loop {
-        // --- New: Check for server task completion/panic ---
-        if let Some(handle) = &server_handle {
-            if handle.is_finished() {
-                let handle = server_handle.take().unwrap();
-                match handle.await {
+        if dirty {
+            tui.draw()?;
+            dirty = false;
+        }
+
+        tokio::select! {
+            event = terminal_events.next() => match event {
+                Some(Ok(event)) => {
+                    if tui.handle_event(event) {
+                        break; // Exit loop if 'q' is pressed
+                    }
+                }
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+
+            // Don't process new commands while a stop is pending
+            Some(command) = command_rx.recv(), if stopping_handle.is_none() => {
+            match command {
+                ServerCommand::Start => {
+                    if server_handle.is_none() {
+                        tui.app_state.local_server_state = LocalServerState::Starting;
+                        tui.app_state.log("[CMD] Starting server...".to_string());
+                        tui.draw()?; // Redraw to show "Starting"
+
+                        let (handle, tx, ready) = start_server_task(
+                            tui.app_state.local_server_settings.clone(),
+                            Arc::clone(&server_log_buffer),
+                        )
+                        .await;
+                        server_handle = Some(handle);
+                        shutdown_tx = Some(tx);
+                        server_ready = Some(ready);
+                    }
+                }
+                ServerCommand::Stop => {
+                    if let Some(tx) = shutdown_tx.take() {
+                        tui.app_state.local_server_state = LocalServerState::Stopping;
+                        tui.app_state.log("[CMD] Stopping server...".to_string());
+                        tx.send(()).ok();
+                        stopping_handle = server_handle.take();
+                    }
+                }
+                ServerCommand::Restart => {
+                    if let Some(tx) = shutdown_tx.take() {
+                        tui.app_state.local_server_state = LocalServerState::Restarting;
+                        tui.app_state.log("[CMD] Restarting server...".to_string());
+                        tx.send(()).ok();
+                        stopping_handle = server_handle.take();
+                    }
+                }
+                ServerCommand::Session(tab, SessionCommand::Connect(info)) => {
+                    if let Some((old, _)) = sessions.remove(&tab) {
+                        old.disconnect().await;
+                        tui.app_state.clear_connection(tab);
+                    }
+                    tui.app_state.log_to(tab, format!(
+                        "[CMD] Connecting to {}:{} as {}...",
+                        info.host, info.port, info.username
+                    ));
+                    let handle = task::spawn(connection::connect_to_server(info));
+                    if let Some(old) = connecting.insert(tab, handle) {
+                        old.abort();
+                    }
+                }
+                ServerCommand::RefreshPublicServers => {
+                    if refresh_handle.is_none() {
+                        tui.app_state.log("[CMD] Refreshing public server list...".to_string());
+                        tui.app_state.public_list_refreshing = true;
+                        refresh_handle = Some(spawn_public_refresh(&public_list));
+                    }
+                }
+                ServerCommand::Session(tab, SessionCommand::JoinChannel(channel_id)) => {
+                    let state = proto::UserState {
+                        channel_id: Some(channel_id),
+                        ..Default::default()
+                    };
+                    send_to_server(&sessions, &mut tui.app_state, tab, state.into());
+                }
+                ServerCommand::Session(tab, SessionCommand::MoveUser { session, channel_id }) => {
+                    let state = proto::UserState {
+                        session: Some(session),
+                        channel_id: Some(channel_id),
+                        ..Default::default()
+                    };
+                    send_to_server(&sessions, &mut tui.app_state, tab, state.into());
+                }
+                ServerCommand::Session(tab, SessionCommand::SendTextMessage(message)) => {
+                    send_to_server(&sessions, &mut tui.app_state, tab, message.into());
+                }
+                ServerCommand::Session(tab, SessionCommand::Send(message)) => {
+                    send_to_server(&sessions, &mut tui.app_state, tab, *message);
+                }
+                ServerCommand::ProbeServer { host, port } => {
+                    spawn_probe(host, port, probe_tx.clone());
+                }
+                ServerCommand::Session(tab, SessionCommand::Disconnect) => {
+                    if let Some(handle) = connecting.remove(&tab) {
+                        handle.abort();
+                    }
+                    if let Some((old, _)) = sessions.remove(&tab) {
+                        tui.app_state.log_to(tab, "[CMD] Disconnecting...".to_string());
+                        old.disconnect().await;
+                        tui.app_state.clear_connection(tab);
+                        tui.app_state.log_to(tab, "[INFO] Disconnected.".to_string());
+                    }
+                }
+                ServerCommand::RegenerateLocalCertificate => {
+                    // A starting server could read the new key with the old
+                    // certificate while they are swapped.
+                    let starting = matches!(
+                        tui.app_state.local_server_state,
+                        LocalServerState::Starting | LocalServerState::Restarting
+                    );
+                    let result = if starting {
+                        Err(anyhow::anyhow!("the local server is starting, try again once it runs"))
+                    } else {
+                        local::regenerate_certificate(&local::local_server_dir())
+                    };
+                    match result {
+                        Ok(fingerprint) => {
+                            tui.app_state.log(format!(
+                                "[INFO] New local server certificate, SHA-1 fingerprint {}.",
+                                fingerprint
+                            ));
+                            if server_handle.is_some() {
+                                tui.app_state.log(
+                                    "[INFO] Restart the local server to use it.".to_string(),
+                                );
+                            }
+                        }
+                        Err(e) => tui.app_state.log(format!(
+                            "[ERROR] Failed to generate certificate: {:#}",
+                            e
+                        )),
+                    }
+                }
+                ServerCommand::UpdateLocalServerSettings(settings) => {
+                    match settings.save_to(&local_settings_path) {
+                        Ok(()) => tui.app_state.log(format!(
+                            "[INFO] Local server settings saved to {}.",
+                            local_settings_path.display()
+                        )),
+                        Err(e) => tui.app_state.log(format!(
+                            "[ERROR] Failed to save local server settings: {:#}",
+                            e
+                        )),
+                    }
+                    if server_handle.is_some() {
+                        tui.app_state.log(
+                            "[INFO] Restart the local server to apply the new settings."
+                                .to_string(),
+                        );
+                    }
+                }
+            }
+            }
+
+            result = join_task(&mut server_handle) => {
+                server_handle = None;
+                match result {
                     Ok(Ok(())) => {
                         // Server stopped gracefully, this is handled by the shutdown logic
                     }
@@ -62,15 +545,19 @@ async fn main() -> Result<()> {
                     }
                 }
             }
-        }
 
-        // --- Step 1: Check for and finalize any pending shutdowns ---
-        if let Some(handle) = &stopping_handle {
-            if handle.is_finished() {
-                let handle = stopping_handle.take().unwrap();
-                handle.await??; // Await is now non-blocking and cleans up the task
+            result = join_task(&mut stopping_handle) => {
+                stopping_handle = None;
+                let failure = match result {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(format!("[ERROR] Server task failed while stopping: {}", e)),
+                    Err(e) => Some(format!("[ERROR] Server task panicked while stopping: {:?}", e)),
+                };
 
-                if tui.app_state.local_server_state == LocalServerState::Stopping {
+                if let Some(message) = failure {
+                    tui.app_state.log(message);
+                    tui.app_state.local_server_state = LocalServerState::Stopped;
+                } else if tui.app_state.local_server_state == LocalServerState::Stopping {
                     tui.app_state.local_server_state = LocalServerState::Stopped;
                     tui.app_state.log("[INFO] Server stopped.".to_string());
                 } else if tui.app_state.local_server_state == LocalServerState::Restarting {
@@ -78,72 +565,120 @@ async fn main() -> Result<()> {
                     tui.app_state.local_server_state = LocalServerState::Starting;
                     tui.draw()?; // Redraw to show "Starting"
 
-                    let (handle_new, tx_new) =
-                        start_server_task(Arc::clone(&server_log_buffer)).await;
+                    let (handle_new, tx_new, ready) = start_server_task(
+                        tui.app_state.local_server_settings.clone(),
+                        Arc::clone(&server_log_buffer),
+                    )
+                    .await;
                     server_handle = Some(handle_new);
                     shutdown_tx = Some(tx_new);
+                    server_ready = Some(ready);
+                }
+            }
+
+            // A server that fails to start is reported when its task ends.
+            ready = wait_ready(&mut server_ready) => {
+                server_ready = None;
+                if ready && tui.app_state.local_server_state == LocalServerState::Starting {
                     tui.app_state.local_server_state = LocalServerState::Running;
-                    tui.app_state
-                        .log("[INFO] Server restarted successfully.".to_string());
+                    tui.app_state.log("[INFO] Server started successfully.".to_string());
                 }
             }
-        }
 
-        // --- Step 2: Handle commands from the TUI ---
-        if stopping_handle.is_none() {
-            // Don't process new commands while a stop is pending
-            if let Ok(command) = command_rx.try_recv() {
-                match command {
-                    ServerCommand::Start => {
-                        if server_handle.is_none() {
-                            tui.app_state.local_server_state = LocalServerState::Starting;
-                            tui.app_state.log("[CMD] Starting server...".to_string());
-                            tui.draw()?; // Redraw to show "Starting"
-
-                            let (handle, tx) =
-                                start_server_task(Arc::clone(&server_log_buffer)).await;
-                            server_handle = Some(handle);
-                            shutdown_tx = Some(tx);
-                            tui.app_state.local_server_state = LocalServerState::Running;
-                            tui.app_state
-                                .log("[INFO] Server started successfully.".to_string());
-                        }
+            result = join_task(&mut refresh_handle) => {
+                refresh_handle = None;
+                match result {
+                    Ok(Ok(list)) => {
+                        tui.app_state.log(format!(
+                            "[INFO] Public server list refreshed ({} servers).",
+                            list.servers.len()
+                        ));
+                        tui.app_state.set_public_servers(list);
+                        spawn_public_pings(tui.app_state.public_ping_targets(), ping_tx.clone());
                     }
-                    ServerCommand::Stop => {
-                        if let Some(tx) = shutdown_tx.take() {
-                            tui.app_state.local_server_state = LocalServerState::Stopping;
-                            tui.app_state.log("[CMD] Stopping server...".to_string());
-                            tx.send(()).ok();
-                            stopping_handle = server_handle.take();
-                        }
+                    Ok(Err(e)) => {
+                        tui.app_state.public_list_refreshing = false;
+                        tui.app_state
+                            .log(format!("[ERROR] Public server list refresh failed: {}", e));
                     }
-                    ServerCommand::Restart => {
-                        if let Some(tx) = shutdown_tx.take() {
-                            tui.app_state.local_server_state = LocalServerState::Restarting;
-                            tui.app_state.log("[CMD] Restarting server...".to_string());
-                            tx.send(()).ok();
-                            stopping_handle = server_handle.take();
-                        }
+                    Err(e) => {
+                        tui.app_state.public_list_refreshing = false;
+                        tui.app_state
+                            .log(format!("[ERROR] Public server list refresh panicked: {:?}", e));
+                    }
+                }
+            }
+
+            (tab, result) = join_connect(&mut connecting) => match result {
+                Ok(Ok((session, events))) => {
+                    tui.app_state.set_connection(tab, &session);
+                    sessions.insert(tab, (session, events));
+                }
+                Ok(Err(e)) => tui.app_state.connect_failed(tab, e),
+                Err(e) => tui.app_state.connect_aborted(tab, format!("{:?}", e)),
+            },
+
+            (tab, event) = recv_event(&mut sessions) => match event {
+                Some(event) => {
+                    let disconnected = matches!(event, ConnectionEvent::Disconnected(_));
+                    if let Some(command) = tui.app_state.handle_connection_event(tab, event) {
+                        command_tx.try_send(command).ok();
                     }
-                    ServerCommand::Connect(info) => {
-                        tui.app_state.log(format!("[CMD] Connecting to {}:{}...", info.host, info.port));
-                        let _ = mumble::connection::connect_to_server(info).await;
+                    if disconnected {
+                        sessions.remove(&tab);
+                        tui.app_state.clear_connection(tab);
                     }
                 }
+                None => {
+                    sessions.remove(&tab);
+                    tui.app_state.clear_connection(tab);
+                }
+            },
+
+            Some((host, port, result)) = ping_rx.recv() => {
+                if let Ok(reply) = result {
+                    tui.app_state.apply_public_ping(&host, port, &reply);
+                }
             }
-        }
 
-        // --- Step 3: Draw the UI and handle input ---
-        tui.draw()?;
-        if tui.handle_events()? {
-            break; // Exit loop if 'q' is pressed
-        }
+            Some((host, port, probe)) = probe_rx.recv() => {
+                tui.app_state.apply_probe(&host, port, probe);
+            }
+
+            _ = probe_tick.tick() => {
+                if let Some((host, port)) = tui.app_state.detail_probe_target() {
+                    spawn_probe(host, port, probe_tx.clone());
+                }
+                continue;
+            }
 
-        // Give the OS a little time to breathe
-        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            _ = render_tick.tick() => {
+                for (id, command) in tui.app_state.reconnect_due(Instant::now()) {
+                    if command_tx.try_send(command).is_ok() {
+                        tui.app_state.reconnect_sent(id);
+                    }
+                }
+                // The server log is written by the logger, which can't wake
+                // us up, and talking indicators expire on their own, so
+                // check both on a timer and only redraw if something changed.
+                // The reconnect countdown always needs a redraw.
+                let total = server_log_buffer.lock().unwrap().total();
+                let talking = tui.app_state.anyone_talking();
+                let reconnecting = tui.app_state.reconnecting();
+                if total == seen_server_logs && !talking && !was_talking && !reconnecting {
+                    continue;
+                }
+                seen_server_logs = total;
+                was_talking = talking;
+            }
+        }
+        dirty = true;
     }
 
     // --- Final cleanup on exit ---
+    for (_, (session, _)) in sessions.drain() {
+        session.disconnect().await;
+    }
     if let Some(tx) = shutdown_tx.take() {
         tx.send(()).ok();
         if let Some(handle) = server_handle.take() {
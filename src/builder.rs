@@ -0,0 +1,100 @@
+use crate::cert;
+use crate::config::MetaParams;
+use crate::db;
+use crate::server::{Meta, ServerHandle};
+use anyhow::{anyhow, Result};
+use log::info;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rusqlite::Connection;
+use tokio_rustls::TlsAcceptor;
+
+/// Used when `sslCert` is not configured.
+pub const DEFAULT_SSL_CERT: &str = "mumble-server.pem";
+/// Used when `sslKey` is not configured.
+pub const DEFAULT_SSL_KEY: &str = "mumble-server.key";
+
+/// Opens the database configured by `dbDriver`/`database`.
+pub async fn open_database(params: &MetaParams) -> Result<Connection> {
+    match params.db_driver.as_str() {
+        "QSQLITE" => {
+            let conn = Connection::open(&params.database).await?;
+            if params.sqlite_wal == 1 {
+                conn.call(|conn| conn.pragma_update(None, "journal_mode", "WAL").map_err(Into::into)).await?;
+            }
+            Ok(conn)
+        }
+        _ => Err(anyhow!("Unsupported database driver: {}", params.db_driver)),
+    }
+}
+
+/// Starts a server from explicit [`MetaParams`].
+///
+/// The binary, the client's embedded server and the tests all go through
+/// this, so TLS, database and boot handling stay in one place.
+pub struct ServerBuilder {
+    params: MetaParams,
+    db_connection: Option<Connection>,
+    acceptor: Option<TlsAcceptor>,
+}
+
+impl ServerBuilder {
+    pub fn new(params: MetaParams) -> Self {
+        Self {
+            params,
+            db_connection: None,
+            acceptor: None,
+        }
+    }
+
+    /// Uses an already open database instead of opening `params.database`.
+    pub fn db_connection(mut self, db_connection: Connection) -> Self {
+        self.db_connection = Some(db_connection);
+        self
+    }
+
+    /// Uses `acceptor` instead of loading `sslCert`/`sslKey`.
+    pub fn tls_acceptor(mut self, acceptor: TlsAcceptor) -> Self {
+        self.acceptor = Some(acceptor);
+        self
+    }
+
+    /// Loads TLS and the database, boots the configured servers and starts
+    /// listening on `params.port` (0 picks a free port).
+    pub async fn start(self) -> Result<ServerHandle> {
+        let mut params = self.params;
+        let acceptor = match self.acceptor {
+            Some(acceptor) => acceptor,
+            None => {
+                if params.ssl_cert.is_empty() {
+                    params.ssl_cert = DEFAULT_SSL_CERT.to_string();
+                }
+                if params.ssl_key.is_empty() {
+                    params.ssl_key = DEFAULT_SSL_KEY.to_string();
+                }
+                let tls_config = cert::server_tls_config(&params)?;
+                info!("SSL/TLS initialized.");
+                TlsAcceptor::from(Arc::new(tls_config))
+            }
+        };
+
+        let db_connection = match self.db_connection {
+            Some(db_connection) => db_connection,
+            None => open_database(&params).await?,
+        };
+        db::initialize_database(&db_connection).await?;
+
+        let port = params.port;
+        let mut meta = Meta::new(params, db_connection);
+        meta.boot_all(true).await?;
+        // Only one virtual server is run for now.
+        let server = meta
+            .servers
+            .values()
+            .next()
+            .ok_or_else(|| anyhow!("No servers booted, nothing to start."))?;
+
+        let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+        server.spawn(listener, acceptor)
+    }
+}
@@ -0,0 +1,405 @@
+use crate::config::MetaParams;
+use crate::identity;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use rcgen::{CertificateParams, DnType, KeyPair, RsaKeySize};
+use rustls::pki_types::{
+    CertificateDer, PrivateKeyDer, PrivatePkcs1KeyDer, PrivatePkcs8KeyDer, PrivateSec1KeyDer,
+};
+use rustls::ServerConfig;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::fmt;
+use time::{Duration, OffsetDateTime};
+use x509_cert::der::Decode;
+use x509_cert::Certificate;
+
+/// Key types `--generate-cert` can create.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[clap(rename_all = "kebab_case")]
+pub enum KeyAlgorithm {
+    Rsa2048,
+    Rsa4096,
+    #[default]
+    EcdsaP256,
+    EcdsaP384,
+    Ed25519,
+}
+
+impl KeyAlgorithm {
+    fn generate_key_pair(self) -> Result<KeyPair> {
+        Ok(match self {
+            KeyAlgorithm::Rsa2048 => {
+                KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_2048)?
+            }
+            KeyAlgorithm::Rsa4096 => {
+                KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_4096)?
+            }
+            KeyAlgorithm::EcdsaP256 => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256)?,
+            KeyAlgorithm::EcdsaP384 => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P384_SHA384)?,
+            KeyAlgorithm::Ed25519 => KeyPair::generate_for(&rcgen::PKCS_ED25519)?,
+        })
+    }
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            KeyAlgorithm::Rsa2048 => "RSA-2048",
+            KeyAlgorithm::Rsa4096 => "RSA-4096",
+            KeyAlgorithm::EcdsaP256 => "ECDSA P-256",
+            KeyAlgorithm::EcdsaP384 => "ECDSA P-384",
+            KeyAlgorithm::Ed25519 => "Ed25519",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What goes into a generated self-signed certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertOptions {
+    pub algorithm: KeyAlgorithm,
+    /// Host names and IP addresses the certificate is valid for.
+    pub subject_alt_names: Vec<String>,
+    /// Defaults to the first subject alternative name.
+    pub common_name: Option<String>,
+    /// Valid from now for this many days; `None` keeps rcgen's fixed
+    /// 1975–4096 range, which keeps seeded certificates reproducible.
+    pub validity_days: Option<u32>,
+    /// Derive an Ed25519 key from this seed instead of generating one.
+    pub seed: Option<[u8; 32]>,
+}
+
+impl Default for CertOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: KeyAlgorithm::default(),
+            subject_alt_names: vec!["localhost".to_string()],
+            common_name: None,
+            validity_days: None,
+            seed: None,
+        }
+    }
+}
+
+/// A self-signed certificate and its key, PEM encoded.
+pub struct GeneratedCert {
+    pub cert_pem: String,
+    pub key_pem: String,
+    pub cert_der: Vec<u8>,
+}
+
+pub fn generate_self_signed(options: &CertOptions) -> Result<GeneratedCert> {
+    if options.subject_alt_names.is_empty() {
+        bail!("A certificate needs at least one host name or IP address.");
+    }
+    let key_pair = match options.seed {
+        Some(seed) if options.algorithm == KeyAlgorithm::Ed25519 => {
+            identity::ed25519_key_pair(&seed)?
+        }
+        Some(_) => bail!("Keys derived from a hash are always Ed25519; use --key-algorithm ed25519."),
+        None => options.algorithm.generate_key_pair()?,
+    };
+
+    let mut params = CertificateParams::new(options.subject_alt_names.clone())?;
+    let common_name = options
+        .common_name
+        .clone()
+        .unwrap_or_else(|| options.subject_alt_names[0].clone());
+    params.distinguished_name.push(DnType::CommonName, common_name);
+    if let Some(days) = options.validity_days {
+        let now = OffsetDateTime::now_utc();
+        params.not_before = now;
+        params.not_after = now + Duration::days(days.into());
+    }
+
+    let cert = params.self_signed(&key_pair)?;
+    Ok(GeneratedCert {
+        cert_pem: cert.pem(),
+        key_pem: key_pair.serialize_pem(),
+        cert_der: cert.der().to_vec(),
+    })
+}
+
+fn colon_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// SHA-1 fingerprint in the `AB:CD:...` form `openssl x509 -fingerprint` prints.
+pub fn sha1_fingerprint(der: &[u8]) -> String {
+    colon_hex(&Sha1::digest(der))
+}
+
+/// SHA-256 fingerprint in the `AB:CD:...` form `openssl x509 -fingerprint` prints.
+pub fn sha256_fingerprint(der: &[u8]) -> String {
+    colon_hex(&Sha256::digest(der))
+}
+
+/// What a certificate says about itself, for showing to users.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateSummary {
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+impl CertificateSummary {
+    pub fn is_self_signed(&self) -> bool {
+        self.subject == self.issuer
+    }
+}
+
+pub fn summarize(der: &[u8]) -> Result<CertificateSummary> {
+    let cert = Certificate::from_der(der).context("Not a valid X.509 certificate")?;
+    let tbs = &cert.tbs_certificate;
+    Ok(CertificateSummary {
+        subject: tbs.subject.to_string(),
+        issuer: tbs.issuer.to_string(),
+        not_before: tbs.validity.not_before.to_string(),
+        not_after: tbs.validity.not_after.to_string(),
+        sha1: sha1_fingerprint(der),
+        sha256: sha256_fingerprint(der),
+    })
+}
+
+fn read_pem_file(path: &str, what: &str) -> Result<Vec<pem::Pem>> {
+    let data = std::fs::read(path).with_context(|| format!("Cannot read {} file '{}'", what, path))?;
+    pem::parse_many(&data).map_err(|e| anyhow!("{} file '{}' is not valid PEM: {}", what, path, e))
+}
+
+/// Reads every certificate in a PEM file, in file order.
+pub fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let certs: Vec<_> = read_pem_file(path, "Certificate")?
+        .into_iter()
+        .filter(|p| p.tag() == "CERTIFICATE")
+        .map(|p| CertificateDer::from(p.into_contents()))
+        .collect();
+    if certs.is_empty() {
+        bail!("Certificate file '{}' contains no CERTIFICATE block", path);
+    }
+    Ok(certs)
+}
+
+/// Reads the first private key in a PEM file.
+///
+/// PKCS#8 (`PRIVATE KEY`), PKCS#1 (`RSA PRIVATE KEY`) and SEC1
+/// (`EC PRIVATE KEY`) keys are accepted, as are PKCS#8 keys encrypted with
+/// `passphrase` (`ENCRYPTED PRIVATE KEY`).
+pub fn load_private_key(path: &str, passphrase: Option<&str>) -> Result<PrivateKeyDer<'static>> {
+    let blocks = read_pem_file(path, "Key")?;
+    for block in &blocks {
+        let der = block.contents().to_vec();
+        let legacy_encrypted = block
+            .headers()
+            .get("Proc-Type")
+            .is_some_and(|v| v.contains("ENCRYPTED"));
+        match block.tag() {
+            "RSA PRIVATE KEY" | "EC PRIVATE KEY" if legacy_encrypted => bail!(
+                "Key file '{}' uses legacy OpenSSL PEM encryption, which is not supported. \
+                 Convert it with `openssl pkcs8 -topk8 -in {} -out new.key`.",
+                path,
+                path
+            ),
+            "PRIVATE KEY" => return Ok(PrivatePkcs8KeyDer::from(der).into()),
+            "RSA PRIVATE KEY" => return Ok(PrivatePkcs1KeyDer::from(der).into()),
+            "EC PRIVATE KEY" => return Ok(PrivateSec1KeyDer::from(der).into()),
+            "ENCRYPTED PRIVATE KEY" => {
+                let passphrase = passphrase.filter(|p| !p.is_empty()).ok_or_else(|| {
+                    anyhow!("Key file '{}' is encrypted but sslPassPhrase is not set", path)
+                })?;
+                let info = pkcs8::EncryptedPrivateKeyInfo::try_from(der.as_slice())
+                    .map_err(|e| anyhow!("Key file '{}' has a malformed encrypted key: {}", path, e))?;
+                let decrypted = info.decrypt(passphrase).map_err(|e| {
+                    anyhow!("Cannot decrypt key file '{}' (wrong sslPassPhrase?): {}", path, e)
+                })?;
+                return Ok(PrivatePkcs8KeyDer::from(decrypted.as_bytes().to_vec()).into());
+            }
+            _ => {}
+        }
+    }
+    let found: Vec<_> = blocks.iter().map(|b| b.tag()).collect();
+    if found.is_empty() {
+        bail!("Key file '{}' contains no PEM blocks", path);
+    }
+    bail!(
+        "Key file '{}' contains no supported private key (found: {})",
+        path,
+        found.join(", ")
+    )
+}
+
+/// Builds the server's TLS config from `sslCert`, `sslKey`, `sslCA` and
+/// `sslPassPhrase`.
+///
+/// Certificates from `sslCA` are appended to the chain after those in
+/// `sslCert`, like Murmur does with intermediate certificates.
+pub fn server_tls_config(params: &MetaParams) -> Result<ServerConfig> {
+    let mut chain = load_certs(&params.ssl_cert)?;
+    if !params.ssl_ca.is_empty() {
+        for cert in load_certs(&params.ssl_ca)? {
+            if !chain.contains(&cert) {
+                chain.push(cert);
+            }
+        }
+    }
+    let passphrase = Some(params.ssl_passphrase.as_str());
+    let key = load_private_key(&params.ssl_key, passphrase)?;
+    ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|e| {
+            anyhow!(
+                "Cannot use certificate '{}' with key '{}': {}",
+                params.ssl_cert,
+                params.ssl_key,
+                e
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::{pem::PemObject, PrivateKeyDer};
+
+    #[test]
+    fn test_generates_every_algorithm() {
+        for algorithm in KeyAlgorithm::value_variants() {
+            let options = CertOptions {
+                algorithm: *algorithm,
+                subject_alt_names: vec!["mumble.example.org".into(), "192.0.2.7".into()],
+                validity_days: Some(30),
+                ..Default::default()
+            };
+            let generated = generate_self_signed(&options).unwrap();
+            let key = PrivateKeyDer::from_pem_slice(generated.key_pem.as_bytes()).unwrap();
+            rustls::crypto::aws_lc_rs::sign::any_supported_type(&key)
+                .unwrap_or_else(|e| panic!("{} key not usable: {}", algorithm, e));
+            assert_eq!(sha1_fingerprint(&generated.cert_der).len(), 20 * 3 - 1);
+            assert_eq!(sha256_fingerprint(&generated.cert_der).len(), 32 * 3 - 1);
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let options = CertOptions {
+            subject_alt_names: vec!["voice.example.org".into()],
+            ..Default::default()
+        };
+        let generated = generate_self_signed(&options).unwrap();
+        let summary = summarize(&generated.cert_der).unwrap();
+        assert_eq!(summary.subject, "CN=voice.example.org");
+        assert!(summary.is_self_signed());
+        assert!(summary.not_before.starts_with("1975-01-01"));
+        assert_eq!(summary.sha1, sha1_fingerprint(&generated.cert_der));
+        assert!(summarize(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_seeded_cert_is_reproducible() {
+        let options = CertOptions {
+            algorithm: KeyAlgorithm::Ed25519,
+            seed: Some([7; 32]),
+            ..Default::default()
+        };
+        let first = generate_self_signed(&options).unwrap();
+        let second = generate_self_signed(&options).unwrap();
+        assert_eq!(first.key_pem, second.key_pem);
+        assert_eq!(
+            sha1_fingerprint(&first.cert_der),
+            sha1_fingerprint(&second.cert_der)
+        );
+
+        let rsa = CertOptions {
+            algorithm: KeyAlgorithm::Rsa2048,
+            ..options
+        };
+        assert!(generate_self_signed(&rsa).is_err());
+    }
+
+    fn write(dir: &std::path::Path, name: &str, tag: &str, der: &[u8]) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, pem::encode(&pem::Pem::new(tag, der))).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_loads_every_key_format() {
+        let dir = tempfile::tempdir().unwrap();
+        for (algorithm, tag) in [
+            (KeyAlgorithm::Rsa2048, "RSA PRIVATE KEY"),
+            (KeyAlgorithm::EcdsaP256, "EC PRIVATE KEY"),
+        ] {
+            let generated = generate_self_signed(&CertOptions {
+                algorithm,
+                ..Default::default()
+            })
+            .unwrap();
+            let pkcs8_pem = pem::parse(&generated.key_pem).unwrap();
+            // The inner key of a PKCS#8 document is the PKCS#1 / SEC1 key.
+            let info = pkcs8::PrivateKeyInfo::try_from(pkcs8_pem.contents()).unwrap();
+            let path = write(dir.path(), "traditional.key", tag, info.private_key);
+            let key = load_private_key(&path, None).unwrap();
+            rustls::crypto::aws_lc_rs::sign::any_supported_type(&key).unwrap();
+
+            let params = MetaParams {
+                ssl_cert: write(dir.path(), "cert.pem", "CERTIFICATE", &generated.cert_der),
+                ssl_key: path,
+                ..Default::default()
+            };
+            server_tls_config(&params).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encrypted_key_and_ca_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let generated = generate_self_signed(&CertOptions::default()).unwrap();
+        let intermediate = generate_self_signed(&CertOptions::default()).unwrap();
+
+        let pkcs8_pem = pem::parse(&generated.key_pem).unwrap();
+        let info = pkcs8::PrivateKeyInfo::try_from(pkcs8_pem.contents()).unwrap();
+        let pbes2 =
+            pkcs8::pkcs5::pbes2::Parameters::pbkdf2_sha256_aes256cbc(2048, &[1; 16], &[2; 16])
+                .unwrap();
+        let encrypted = info.encrypt_with_params(pbes2, "hunter2").unwrap();
+
+        let mut params = MetaParams {
+            ssl_cert: write(dir.path(), "cert.pem", "CERTIFICATE", &generated.cert_der),
+            ssl_key: write(dir.path(), "enc.key", "ENCRYPTED PRIVATE KEY", encrypted.as_bytes()),
+            ssl_ca: write(dir.path(), "ca.pem", "CERTIFICATE", &intermediate.cert_der),
+            ..Default::default()
+        };
+        let err = server_tls_config(&params).unwrap_err().to_string();
+        assert!(err.contains("enc.key") && err.contains("sslPassPhrase"), "{}", err);
+
+        params.ssl_passphrase = "wrong".to_string();
+        let err = server_tls_config(&params).unwrap_err().to_string();
+        assert!(err.contains("Cannot decrypt"), "{}", err);
+
+        params.ssl_passphrase = "hunter2".to_string();
+        server_tls_config(&params).unwrap();
+
+        let mut chain = load_certs(&params.ssl_cert).unwrap();
+        chain.extend(load_certs(&params.ssl_ca).unwrap());
+        assert_eq!(chain.len(), 2);
+
+        let err = load_private_key(&params.ssl_cert, None).unwrap_err().to_string();
+        assert!(err.contains("found: CERTIFICATE"), "{}", err);
+    }
+
+    #[test]
+    fn test_fingerprint_format() {
+        assert_eq!(
+            sha1_fingerprint(b""),
+            "DA:39:A3:EE:5E:6B:4B:0D:32:55:BF:EF:95:60:18:90:AF:D8:07:09"
+        );
+    }
+}
@@ -1,6 +1,7 @@
+use crate::cert::KeyAlgorithm;
 use crate::config::{read_config, MetaParams};
 use clap::{Parser, ValueEnum};
-use log::info;
+use log::{info, LevelFilter};
 
 #[derive(ValueEnum, Debug, Clone)]
 #[clap(rename_all = "kebab_case")]
@@ -12,6 +13,18 @@ pub enum LogLevel {
     Trace,
 }
 
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
 /// Mumble server (murmur)
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -160,6 +173,12 @@ struct Args {
     ssl_cert: Option<String>,
     #[arg(long, env = "MUMBLE_SSL_KEY")]
     ssl_key: Option<String>,
+    /// PEM file with intermediate certificates to send after sslCert
+    #[arg(long, env = "MUMBLE_SSL_CA")]
+    ssl_ca: Option<String>,
+    /// Passphrase for an encrypted sslKey
+    #[arg(long, env = "MUMBLE_SSL_PASSPHRASE")]
+    ssl_passphrase: Option<String>,
 
     /// Enable the terminal user interface
     #[arg(long)]
@@ -176,6 +195,22 @@ struct Args {
     /// Generate key from a SHA256 hash (hex string)
     #[arg(long)]
     key_from_hash: Option<String>,
+
+    /// Key type for --generate-cert (ed25519 when --key-from-hash is used)
+    #[arg(long, value_enum)]
+    key_algorithm: Option<KeyAlgorithm>,
+
+    /// Host name or IP address for the generated certificate (repeatable)
+    #[arg(long = "cert-san", value_name = "NAME")]
+    cert_sans: Vec<String>,
+
+    /// Common name for the generated certificate (defaults to the first --cert-san)
+    #[arg(long)]
+    cert_cn: Option<String>,
+
+    /// Validity of the generated certificate in days, starting now
+    #[arg(long)]
+    cert_days: Option<u32>,
 }
 
 pub struct Config {
@@ -184,6 +219,10 @@ pub struct Config {
     pub generate_cert: bool,
     pub generate_keys: bool,
     pub key_from_hash: Option<String>,
+    pub key_algorithm: Option<KeyAlgorithm>,
+    pub cert_sans: Vec<String>,
+    pub cert_cn: Option<String>,
+    pub cert_days: Option<u32>,
     pub logging: Option<LogLevel>,
 }
 
@@ -193,6 +232,10 @@ pub fn load_and_merge_config() -> Config {
     let generate_cert = args.generate_cert;
     let generate_keys = args.generate_keys;
     let key_from_hash = args.key_from_hash;
+    let key_algorithm = args.key_algorithm;
+    let cert_sans = args.cert_sans;
+    let cert_cn = args.cert_cn;
+    let cert_days = args.cert_days;
     let logging = args.logging;
 
     // Load configuration from file, or use defaults if file is not found or invalid
@@ -409,6 +452,12 @@ pub fn load_and_merge_config() -> Config {
     if let Some(val) = args.ssl_key {
         params.ssl_key = val;
     }
+    if let Some(val) = args.ssl_ca {
+        params.ssl_ca = val;
+    }
+    if let Some(val) = args.ssl_passphrase {
+        params.ssl_passphrase = val;
+    }
 
     Config {
         params,
@@ -416,6 +465,10 @@ pub fn load_and_merge_config() -> Config {
         generate_cert,
         generate_keys,
         key_from_hash,
+        key_algorithm,
+        cert_sans,
+        cert_cn,
+        cert_days,
         logging,
     }
 }
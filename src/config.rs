@@ -99,6 +99,8 @@ pub struct MetaParams {
     pub abs_settings_file_path: String,
     pub ssl_cert: String,
     pub ssl_key: String,
+    pub ssl_ca: String,
+    pub ssl_passphrase: String,
 }
 
 impl Default for MetaParams {
@@ -172,6 +174,8 @@ impl Default for MetaParams {
             abs_settings_file_path: "".to_string(),
             ssl_cert: "".to_string(),
             ssl_key: "".to_string(),
+            ssl_ca: "".to_string(),
+            ssl_passphrase: "".to_string(),
         }
     }
 }
@@ -229,6 +233,8 @@ pub fn read_config(file_path: &str) -> Result<MetaParams> {
     params.welcome_text = get_string(&config_parser, "welcometext", params.welcome_text);
     params.ssl_cert = get_string(&config_parser, "sslCert", params.ssl_cert);
     params.ssl_key = get_string(&config_parser, "sslKey", params.ssl_key);
+    params.ssl_ca = get_string(&config_parser, "sslCA", params.ssl_ca);
+    params.ssl_passphrase = get_string(&config_parser, "sslPassPhrase", params.ssl_passphrase);
     params.database = get_string(&config_parser, "database", params.database);
     params.db_driver = get_string(&config_parser, "dbDriver", params.db_driver);
     params.db_username = get_string(&config_parser, "dbUsername", params.db_username);
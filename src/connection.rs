@@ -1,8 +1,982 @@
+use crate::cert::{self, CertificateSummary};
+pub use crate::identity::ClientIdentity;
+use crate::identity::IdentityStore;
+use crate::proto::{self, Message, RejectType};
 use crate::ui::client::ConnectionInfo;
-use anyhow::Result;
+use crate::known_hosts::{HostCheck, KnownHosts};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsConnector;
 
-pub async fn connect_to_server(info: ConnectionInfo) -> Result<()> {
-    println!("[CONNECTION] Attempting to connect to {}:{}", info.host, info.port);
-    // In the future, this will contain the actual Mumble connection logic.
-    Ok(())
+/// Port used when a server address doesn't name one.
+pub const DEFAULT_PORT: u16 = 64738;
+/// The official client pings every 5 seconds; servers drop us after 30.
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+/// A user counts as talking for this long after their last voice packet.
+pub const TALKING_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Splits `host[:port]`, also accepting `[v6-address]:port`.
+pub fn parse_address(address: &str) -> Result<(String, u16)> {
+    let address = address.trim();
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            let port = port
+                .parse()
+                .with_context(|| format!("Invalid port in '{}'", address))?;
+            (host, port)
+        }
+        _ => (address, DEFAULT_PORT),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(anyhow!("No host given in '{}'", address));
+    }
+    Ok((host.to_string(), port))
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: Option<String>,
+    pub tokens: Vec<String>,
+    /// Identity to present; a temporary one is generated if `None`.
+    pub identity: Option<ClientIdentity>,
+    /// Where certificates without a CA signature are remembered, the user's
+    /// store unless set otherwise; `None` accepts any certificate.
+    pub known_hosts: Option<PathBuf>,
+}
+
+impl From<ConnectionInfo> for ConnectOptions {
+    fn from(info: ConnectionInfo) -> Self {
+        Self {
+            host: info.host,
+            port: info.port,
+            username: info.username,
+            password: info.password,
+            tokens: info.tokens,
+            identity: None,
+            known_hosts: Some(KnownHosts::default_path()),
+        }
+    }
+}
+
+/// The server refused our `Authenticate` message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub reject_type: RejectType,
+    pub reason: String,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Server rejected connection ({:?}): {}", self.reject_type, self.reason)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// The server presented a different certificate than the one we know it by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateChanged {
+    pub host: String,
+    pub port: u16,
+    /// SHA-256 fingerprint in the known hosts store.
+    pub old: String,
+    /// SHA-256 fingerprint of the certificate presented now.
+    pub new: String,
+}
+
+impl fmt::Display for CertificateChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The certificate of {}:{} changed from {} to {}",
+            self.host, self.port, self.old, self.new
+        )
+    }
+}
+
+impl std::error::Error for CertificateChanged {}
+
+/// Why a session ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// We disconnected.
+    Local,
+    /// The server removed us; the `UserLeft` event before says who and why.
+    Kicked,
+    /// The connection broke.
+    Lost(String),
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => write!(f, "Disconnected by user"),
+            Self::Kicked => write!(f, "Removed from server"),
+            Self::Lost(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelInfo {
+    pub id: u32,
+    pub parent: Option<u32>,
+    pub name: String,
+    pub description: String,
+    pub position: i32,
+    pub temporary: bool,
+    pub max_users: u32,
+    pub links: BTreeSet<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserInfo {
+    pub session: u32,
+    pub name: String,
+    /// Registered user id, if the user is registered on the server.
+    pub user_id: Option<u32>,
+    pub channel_id: u32,
+    pub mute: bool,
+    pub deaf: bool,
+    pub suppress: bool,
+    pub self_mute: bool,
+    pub self_deaf: bool,
+    pub priority_speaker: bool,
+    pub recording: bool,
+    pub comment: String,
+    /// SHA-1 hash of the user's certificate.
+    pub hash: String,
+    pub listening: BTreeSet<u32>,
+    /// When the last voice packet from this user arrived.
+    pub last_voice: Option<Instant>,
+}
+
+impl UserInfo {
+    pub fn is_talking(&self) -> bool {
+        self.last_voice
+            .is_some_and(|at| at.elapsed() < TALKING_TIMEOUT)
+    }
+}
+
+/// Something that changed on the server, for the UI to react to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// The handshake finished and the model holds the full server state.
+    Connected { session: u32, welcome_text: String },
+    ChannelChanged(u32),
+    ChannelRemoved(u32),
+    UserJoined(u32),
+    UserChanged(u32),
+    /// A user went to another channel, by themselves or moved by `actor`.
+    UserMoved {
+        session: u32,
+        from: u32,
+        actor: Option<u32>,
+    },
+    UserLeft {
+        session: u32,
+        name: String,
+        actor: Option<u32>,
+        reason: Option<String>,
+        ban: bool,
+    },
+    TextMessage(proto::TextMessage),
+    PermissionDenied(String),
+    Disconnected(DisconnectReason),
+}
+
+/// Live view of the server's channels and users.
+#[derive(Debug, Clone, Default)]
+pub struct ServerModel {
+    pub channels: BTreeMap<u32, ChannelInfo>,
+    pub users: BTreeMap<u32, UserInfo>,
+    /// Our own session id, known once `ServerSync` arrived.
+    pub session: Option<u32>,
+    pub welcome_text: String,
+    pub max_bandwidth: Option<u32>,
+    pub permissions: u64,
+    pub server_version: Option<String>,
+    pub server_release: Option<String>,
+    pub config: Option<proto::ServerConfig>,
+    /// Round trip time of the last TCP ping.
+    pub tcp_ping: Option<Duration>,
+}
+
+impl ServerModel {
+    pub fn is_synced(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn own_user(&self) -> Option<&UserInfo> {
+        self.session.and_then(|s| self.users.get(&s))
+    }
+
+    /// Direct subchannels of `parent`, in display order.
+    pub fn children(&self, parent: u32) -> Vec<&ChannelInfo> {
+        let mut children: Vec<&ChannelInfo> = self
+            .channels
+            .values()
+            .filter(|c| c.parent == Some(parent) && c.id != parent)
+            .collect();
+        children.sort_by(|a, b| {
+            a.position
+                .cmp(&b.position)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+        children
+    }
+
+    /// Whether the root channel permissions from `ServerSync` include `perm`.
+    pub fn has_permission(&self, perm: u32) -> bool {
+        self.permissions & perm as u64 != 0
+    }
+
+    /// Whether anyone is currently talking, i.e. talking indicators may still
+    /// need to turn off.
+    pub fn anyone_talking(&self) -> bool {
+        self.users.values().any(|u| u.is_talking())
+    }
+
+    /// Users in `channel_id`, sorted by name.
+    pub fn channel_users(&self, channel_id: u32) -> Vec<&UserInfo> {
+        let mut users: Vec<&UserInfo> = self
+            .users
+            .values()
+            .filter(|u| u.channel_id == channel_id)
+            .collect();
+        users.sort_by_key(|u| u.name.to_lowercase());
+        users
+    }
+
+    /// Names of the channels from below the root down to `channel_id`.
+    ///
+    /// A server that sends a parent loop gets a truncated path rather than
+    /// hanging the client: no path is longer than there are channels.
+    pub fn channel_path(&self, channel_id: u32) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut current = self.channels.get(&channel_id);
+        while let Some(channel) = current.filter(|_| path.len() < self.channels.len()) {
+            let Some(parent) = channel.parent.filter(|&p| p != channel.id) else {
+                break;
+            };
+            path.push(channel.name.clone());
+            current = self.channels.get(&parent);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Finds a channel by the names below the root, preferring exact matches
+    /// over ones differing in case.
+    pub fn channel_by_path(&self, path: &[String]) -> Option<u32> {
+        let mut current = 0;
+        for name in path {
+            let children = self.children(current);
+            current = children
+                .iter()
+                .find(|c| c.name == *name)
+                .or_else(|| children.iter().find(|c| c.name.eq_ignore_ascii_case(name)))?
+                .id;
+        }
+        Some(current)
+    }
+
+    /// Applies a message from the server, returning what changed.
+    pub fn apply(&mut self, message: &Message) -> Option<ConnectionEvent> {
+        match message {
+            Message::Version(version) => {
+                self.server_version = Some(proto::format_version(version));
+                self.server_release = version.release.clone();
+                None
+            }
+            Message::ChannelState(state) => {
+                let id = state.channel_id?;
+                let channel = self.channels.entry(id).or_insert_with(|| ChannelInfo {
+                    id,
+                    ..Default::default()
+                });
+                if let Some(parent) = state.parent {
+                    channel.parent = Some(parent);
+                }
+                if let Some(name) = &state.name {
+                    channel.name = name.clone();
+                }
+                if let Some(description) = &state.description {
+                    channel.description = description.clone();
+                }
+                if let Some(position) = state.position {
+                    channel.position = position;
+                }
+                if let Some(temporary) = state.temporary {
+                    channel.temporary = temporary;
+                }
+                if let Some(max_users) = state.max_users {
+                    channel.max_users = max_users;
+                }
+                if !state.links.is_empty() {
+                    channel.links = state.links.iter().copied().collect();
+                }
+                channel.links.extend(state.links_add.iter().copied());
+                for link in &state.links_remove {
+                    channel.links.remove(link);
+                }
+                Some(ConnectionEvent::ChannelChanged(id))
+            }
+            Message::ChannelRemove(remove) => {
+                self.channels.remove(&remove.channel_id);
+                Some(ConnectionEvent::ChannelRemoved(remove.channel_id))
+            }
+            Message::UserState(state) => {
+                let session = state.session?;
+                let is_new = !self.users.contains_key(&session);
+                let user = self.users.entry(session).or_insert_with(|| UserInfo {
+                    session,
+                    ..Default::default()
+                });
+                let from = user.channel_id;
+                if let Some(name) = &state.name {
+                    user.name = name.clone();
+                }
+                if let Some(user_id) = state.user_id {
+                    user.user_id = Some(user_id);
+                }
+                if let Some(channel_id) = state.channel_id {
+                    user.channel_id = channel_id;
+                }
+                macro_rules! update_flags {
+                    ($($field:ident),*) => {
+                        $(if let Some(value) = state.$field {
+                            user.$field = value;
+                        })*
+                    };
+                }
+                update_flags!(mute, deaf, suppress, self_mute, self_deaf, priority_speaker, recording);
+                if let Some(comment) = &state.comment {
+                    user.comment = comment.clone();
+                }
+                if let Some(hash) = &state.hash {
+                    user.hash = hash.clone();
+                }
+                user.listening.extend(state.listening_channel_add.iter().copied());
+                for channel_id in &state.listening_channel_remove {
+                    user.listening.remove(channel_id);
+                }
+                Some(if is_new {
+                    ConnectionEvent::UserJoined(session)
+                } else if user.channel_id != from {
+                    ConnectionEvent::UserMoved {
+                        session,
+                        from,
+                        actor: state.actor,
+                    }
+                } else {
+                    ConnectionEvent::UserChanged(session)
+                })
+            }
+            Message::UserRemove(remove) => {
+                let user = self.users.remove(&remove.session);
+                Some(ConnectionEvent::UserLeft {
+                    session: remove.session,
+                    name: user.map(|u| u.name).unwrap_or_default(),
+                    actor: remove.actor,
+                    reason: remove.reason.clone(),
+                    ban: remove.ban.unwrap_or(false),
+                })
+            }
+            Message::ServerSync(sync) => {
+                let session = sync.session?;
+                self.session = Some(session);
+                self.welcome_text = sync.welcome_text.clone().unwrap_or_default();
+                self.max_bandwidth = sync.max_bandwidth;
+                self.permissions = sync.permissions.unwrap_or_default();
+                Some(ConnectionEvent::Connected {
+                    session,
+                    welcome_text: self.welcome_text.clone(),
+                })
+            }
+            Message::ServerConfig(config) => {
+                self.config = Some(config.clone());
+                None
+            }
+            Message::UdpTunnel(tunnel) => {
+                let session = proto::voice_sender(&tunnel.packet)?;
+                let user = self.users.get_mut(&session)?;
+                let was_talking = user.is_talking();
+                user.last_voice = Some(Instant::now());
+                (!was_talking).then_some(ConnectionEvent::UserChanged(session))
+            }
+            Message::TextMessage(text) => Some(ConnectionEvent::TextMessage(text.clone())),
+            Message::PermissionDenied(denied) => {
+                let reason = denied.reason.clone().unwrap_or_else(|| {
+                    match denied.r#type.and_then(|t| proto::DenyType::try_from(t).ok()) {
+                        Some(deny_type) => format!("{:?}", deny_type),
+                        None => "Permission denied".to_string(),
+                    }
+                });
+                Some(ConnectionEvent::PermissionDenied(reason))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl ServerModel {
+    /// A model of `channels` as `(id, parent, name)`, the root being its own
+    /// parent, and `users` as `(session, name, channel)`.
+    pub fn with(channels: &[(u32, u32, &str)], users: &[(u32, &str, u32)]) -> Self {
+        let mut model = Self::default();
+        for &(id, parent, name) in channels {
+            let channel = proto::ChannelState {
+                channel_id: Some(id),
+                parent: (parent != id).then_some(parent),
+                name: Some(name.to_string()),
+                ..Default::default()
+            };
+            model.apply(&channel.into());
+        }
+        for &(session, name, channel_id) in users {
+            let user = proto::UserState {
+                session: Some(session),
+                name: Some(name.to_string()),
+                channel_id: Some(channel_id),
+                ..Default::default()
+            };
+            model.apply(&user.into());
+        }
+        model
+    }
+}
+
+/// How the server's certificate came to be trusted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Trust {
+    /// Not checked, as the connection was made without a known hosts store.
+    #[default]
+    Unchecked,
+    /// Signed by a certificate authority for the server's name.
+    Authority,
+    /// The same certificate as on earlier connections.
+    KnownHost,
+    /// Never seen before and remembered from now on.
+    FirstUse,
+}
+
+/// What the TLS handshake negotiated, and who with.
+#[derive(Debug, Clone, Default)]
+pub struct TlsInfo {
+    pub protocol: String,
+    pub cipher: String,
+    /// The server's certificate first, then the ones it sent to vouch for it.
+    pub chain: Vec<CertificateSummary>,
+    pub trust: Trust,
+}
+
+impl TlsInfo {
+    fn from_connection(connection: &rustls::ClientConnection) -> Self {
+        let chain = connection
+            .peer_certificates()
+            .unwrap_or_default()
+            .iter()
+            .map(|der| {
+                cert::summarize(der).unwrap_or_else(|_| CertificateSummary {
+                    subject: "(unreadable certificate)".to_string(),
+                    issuer: String::new(),
+                    not_before: String::new(),
+                    not_after: String::new(),
+                    sha1: cert::sha1_fingerprint(der),
+                    sha256: cert::sha256_fingerprint(der),
+                })
+            })
+            .collect();
+        Self {
+            protocol: connection
+                .protocol_version()
+                .map(|v| format!("{:?}", v))
+                .unwrap_or_default(),
+            cipher: connection
+                .negotiated_cipher_suite()
+                .map(|s| format!("{:?}", s.suite()))
+                .unwrap_or_default(),
+            chain,
+            trust: Trust::Unchecked,
+        }
+    }
+}
+
+/// An authenticated connection to a Mumble server.
+pub struct ClientSession {
+    pub host: String,
+    pub port: u16,
+    pub tls: TlsInfo,
+    model: Arc<Mutex<ServerModel>>,
+    outgoing: mpsc::UnboundedSender<Message>,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl ClientSession {
+    pub fn model(&self) -> Arc<Mutex<ServerModel>> {
+        Arc::clone(&self.model)
+    }
+
+    pub fn send(&self, message: impl Into<Message>) -> Result<()> {
+        self.outgoing
+            .send(message.into())
+            .map_err(|_| anyhow!("Connection is closed"))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Closes the connection and waits for the session task to finish.
+    pub async fn disconnect(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        (&mut self.task).await.ok();
+    }
+}
+
+/// Accepts any server certificate while still checking handshake signatures.
+///
+/// Mumble servers are mostly self-signed, so rejecting unknown issuers would
+/// make most of them unreachable. [`check_trust`] decides afterwards, before
+/// anything is sent to the server.
+#[derive(Debug)]
+struct AcceptAnyServerCert {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for AcceptAnyServerCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::aws_lc_rs::default_provider())
+}
+
+fn tls_config(identity: ClientIdentity) -> Result<ClientConfig> {
+    let provider = crypto_provider();
+    let config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyServerCert { provider }))
+        .with_client_auth_cert(identity.cert_chain, identity.key)?;
+    Ok(config)
+}
+
+/// Accepts certificates signed by a certificate authority for the server's
+/// name, and otherwise the one the known hosts store has for the server,
+/// remembering it if the server is new.
+fn check_trust(
+    options: &ConnectOptions,
+    server_name: &ServerName<'_>,
+    connection: &rustls::ClientConnection,
+) -> Result<Trust> {
+    let Some(path) = &options.known_hosts else {
+        return Ok(Trust::Unchecked);
+    };
+    let Some((end_entity, intermediates)) = connection.peer_certificates().and_then(|c| c.split_first()) else {
+        bail!("The server sent no certificate");
+    };
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let authority = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), crypto_provider()).build()?;
+    if authority
+        .verify_server_cert(end_entity, intermediates, server_name, &[], UnixTime::now())
+        .is_ok()
+    {
+        return Ok(Trust::Authority);
+    }
+
+    let fingerprint = cert::sha256_fingerprint(end_entity);
+    let mut known_hosts = KnownHosts::load_from(path)?;
+    match known_hosts.check(&options.host, options.port, &fingerprint) {
+        HostCheck::Matches => Ok(Trust::KnownHost),
+        HostCheck::Unknown => {
+            info!(
+                "First connection to {}:{}, remembering certificate {}",
+                options.host, options.port, fingerprint
+            );
+            known_hosts.trust(&options.host, options.port, &fingerprint);
+            known_hosts.save_to(path)?;
+            Ok(Trust::FirstUse)
+        }
+        HostCheck::Changed { old } => Err(anyhow::Error::new(CertificateChanged {
+            host: options.host.clone(),
+            port: options.port,
+            old,
+            new: fingerprint,
+        })),
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Connects, authenticates and waits for `ServerSync`.
+///
+/// On success the session keeps running in the background; changes are
+/// applied to the session's model and reported on the returned receiver.
+pub async fn connect(
+    mut options: ConnectOptions,
+) -> Result<(ClientSession, mpsc::UnboundedReceiver<ConnectionEvent>)> {
+    let identity = match options.identity.take() {
+        Some(identity) => identity,
+        None => ClientIdentity::generate(&options.username)?,
+    };
+    let connector = TlsConnector::from(Arc::new(tls_config(identity)?));
+    let server_name = ServerName::try_from(options.host.clone())
+        .with_context(|| format!("Invalid server name {}", options.host))?;
+
+    let tcp = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        TcpStream::connect((options.host.as_str(), options.port)),
+    )
+    .await
+    .map_err(|_| anyhow!("Connecting to {}:{} timed out", options.host, options.port))?
+    .with_context(|| format!("Failed to connect to {}:{}", options.host, options.port))?;
+    tcp.set_nodelay(true).ok();
+    let tls = connector
+        .connect(server_name.clone(), tcp)
+        .await
+        .context("TLS handshake failed")?;
+    let mut tls_info = TlsInfo::from_connection(tls.get_ref().1);
+    tls_info.trust = check_trust(&options, &server_name, tls.get_ref().1)?;
+    let (mut reader, mut writer) = tokio::io::split(tls);
+
+    let version = proto::Version {
+        version_v1: Some(proto::version_v1()),
+        version_v2: Some(proto::version_v2()),
+        release: Some(format!("mumble-rs {}", env!("CARGO_PKG_VERSION"))),
+        os: Some(std::env::consts::OS.to_string()),
+        os_version: None,
+    };
+    proto::write_message(&mut writer, &version.into()).await?;
+    let authenticate = proto::Authenticate {
+        username: Some(options.username.clone()),
+        password: options.password.clone(),
+        tokens: options.tokens.clone(),
+        opus: Some(true),
+        ..Default::default()
+    };
+    proto::write_message(&mut writer, &authenticate.into()).await?;
+
+    let mut model = ServerModel::default();
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+        loop {
+            let message = proto::read_message(&mut reader).await?;
+            if let Message::Reject(reject) = &message {
+                return Err(anyhow::Error::new(Rejected {
+                    reject_type: reject
+                        .r#type
+                        .and_then(|t| RejectType::try_from(t).ok())
+                        .unwrap_or(RejectType::None),
+                    reason: reject.reason.clone().unwrap_or_default(),
+                }));
+            }
+            if let Some(ConnectionEvent::Connected { .. }) = model.apply(&message) {
+                return Ok(());
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("Server did not finish the handshake in time"))??;
+    info!(
+        "Connected to {}:{} as session {:?}",
+        options.host, options.port, model.session
+    );
+
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    event_tx
+        .send(ConnectionEvent::Connected {
+            session: model.session.unwrap_or_default(),
+            welcome_text: model.welcome_text.clone(),
+        })
+        .ok();
+    let model = Arc::new(Mutex::new(model));
+    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<Message>();
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+
+    // Reading a frame is not cancel safe, so it gets its own task.
+    let (incoming_tx, mut incoming_rx) = mpsc::unbounded_channel();
+    let reader_task = tokio::spawn(async move {
+        loop {
+            let result = proto::read_message(&mut reader).await;
+            let failed = result.is_err();
+            if incoming_tx.send(result).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let task_model = Arc::clone(&model);
+    let task = tokio::spawn(async move {
+        let mut ping_interval = tokio::time::interval(PING_INTERVAL);
+        let reason = loop {
+            tokio::select! {
+                incoming = incoming_rx.recv() => {
+                    let message = match incoming {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => break DisconnectReason::Lost(e.to_string()),
+                        None => break DisconnectReason::Lost("Connection closed".to_string()),
+                    };
+                    if let Message::Ping(ping) = &message {
+                        if let Some(sent) = ping.timestamp {
+                            let rtt = Duration::from_millis(now_millis().saturating_sub(sent));
+                            task_model.lock().unwrap().tcp_ping = Some(rtt);
+                        }
+                        continue;
+                    }
+                    let kicked = match &message {
+                        Message::UserRemove(remove) => {
+                            task_model.lock().unwrap().session == Some(remove.session)
+                        }
+                        _ => false,
+                    };
+                    let event = task_model.lock().unwrap().apply(&message);
+                    if let Some(event) = event {
+                        event_tx.send(event).ok();
+                    }
+                    if kicked {
+                        break DisconnectReason::Kicked;
+                    }
+                }
+                outgoing = outgoing_rx.recv() => {
+                    let Some(message) = outgoing else { break DisconnectReason::Local };
+                    if let Err(e) = proto::write_message(&mut writer, &message).await {
+                        break DisconnectReason::Lost(e.to_string());
+                    }
+                }
+                _ = ping_interval.tick() => {
+                    let ping = proto::Ping {
+                        timestamp: Some(now_millis()),
+                        ..Default::default()
+                    };
+                    if let Err(e) = proto::write_message(&mut writer, &ping.into()).await {
+                        break DisconnectReason::Lost(e.to_string());
+                    }
+                }
+                _ = &mut shutdown_rx => break DisconnectReason::Local,
+            }
+        };
+        match &reason {
+            DisconnectReason::Local => debug!("{}", reason),
+            _ => warn!("Disconnected: {}", reason),
+        }
+        reader_task.abort();
+        event_tx.send(ConnectionEvent::Disconnected(reason)).ok();
+    });
+
+    Ok((
+        ClientSession {
+            host: options.host,
+            port: options.port,
+            tls: tls_info,
+            model,
+            outgoing: outgoing_tx,
+            shutdown: Some(shutdown_tx),
+            task,
+        },
+        event_rx,
+    ))
+}
+
+/// Connects using the identity named in `info`, or else the one selected
+/// for the server in the user's [`IdentityStore`].
+pub async fn connect_to_server(
+    info: ConnectionInfo,
+) -> Result<(ClientSession, mpsc::UnboundedReceiver<ConnectionEvent>)> {
+    let store = IdentityStore::default();
+    let identity = match &info.identity {
+        Some(name) => store.load(name)?,
+        None => store.identity_for(&info.host, info.port)?,
+    };
+    let mut options = ConnectOptions::from(info);
+    options.identity = Some(identity);
+    connect(options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_tracks_users_and_channels() {
+        let mut model = ServerModel::default();
+        let channel = proto::ChannelState {
+            channel_id: Some(1),
+            parent: Some(0),
+            name: Some("Lobby".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            model.apply(&channel.into()),
+            Some(ConnectionEvent::ChannelChanged(1))
+        );
+
+        let user = proto::UserState {
+            session: Some(7),
+            name: Some("bob".to_string()),
+            channel_id: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(model.apply(&user.into()), Some(ConnectionEvent::UserJoined(7)));
+
+        let update = proto::UserState {
+            session: Some(7),
+            self_mute: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(model.apply(&update.into()), Some(ConnectionEvent::UserChanged(7)));
+        let bob = &model.users[&7];
+        assert!(bob.self_mute);
+        assert_eq!(bob.name, "bob");
+        assert_eq!(model.channel_users(1).len(), 1);
+        assert_eq!(model.children(0)[0].name, "Lobby");
+
+        let moved = proto::UserState {
+            session: Some(7),
+            actor: Some(3),
+            channel_id: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            model.apply(&moved.into()),
+            Some(ConnectionEvent::UserMoved {
+                session: 7,
+                from: 1,
+                actor: Some(3)
+            })
+        );
+
+        let remove = proto::UserRemove {
+            session: 7,
+            reason: Some("bye".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            model.apply(&remove.into()),
+            Some(ConnectionEvent::UserLeft { ref name, .. }) if name == "bob"
+        ));
+        assert!(model.users.is_empty());
+    }
+
+    #[test]
+    fn test_channel_paths() {
+        let mut model = ServerModel::default();
+        for (id, parent, name) in [(0, None, "Root"), (1, Some(0), "Gaming"), (2, Some(1), "Team A")] {
+            model.apply(
+                &proto::ChannelState {
+                    channel_id: Some(id),
+                    parent,
+                    name: Some(name.to_string()),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        assert_eq!(model.channel_path(2), ["Gaming", "Team A"]);
+        assert!(model.channel_path(0).is_empty());
+        let path = ["gaming".to_string(), "Team A".to_string()];
+        assert_eq!(model.channel_by_path(&path), Some(2));
+        assert_eq!(model.channel_by_path(&[]), Some(0));
+        assert_eq!(model.channel_by_path(&["Nope".to_string()]), None);
+
+        // Channels moved under each other must not hang the walk.
+        let looped = ServerModel::with(&[(0, 0, "Root"), (1, 2, "Ping"), (2, 1, "Pong")], &[]);
+        assert_eq!(looped.channel_path(1).len(), 3);
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(parse_address("example.com").unwrap(), ("example.com".to_string(), DEFAULT_PORT));
+        assert_eq!(parse_address("example.com:1234").unwrap(), ("example.com".to_string(), 1234));
+        assert_eq!(parse_address("[::1]:50000").unwrap(), ("::1".to_string(), 50000));
+        assert_eq!(parse_address("::1").unwrap(), ("::1".to_string(), DEFAULT_PORT));
+        assert!(parse_address("example.com:port").is_err());
+        assert!(parse_address(":1234").is_err());
+    }
+
+    #[test]
+    fn test_voice_packets_mark_users_talking() {
+        let mut model = ServerModel::default();
+        let user = proto::UserState {
+            session: Some(5),
+            name: Some("alice".to_string()),
+            ..Default::default()
+        };
+        model.apply(&user.into());
+        assert!(!model.anyone_talking());
+
+        let voice = proto::UdpTunnel {
+            packet: vec![0x80, 0x05, 0x01],
+        };
+        assert_eq!(
+            model.apply(&voice.clone().into()),
+            Some(ConnectionEvent::UserChanged(5))
+        );
+        assert!(model.users[&5].is_talking());
+        // Only the start of talking is reported.
+        assert_eq!(model.apply(&voice.into()), None);
+    }
 }
@@ -3,7 +3,11 @@ use tokio_rusqlite::Connection;
 pub async fn initialize_database(conn: &Connection) -> Result<(), tokio_rusqlite::Error> {
     conn.call(|conn| {
         conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS users (
+            "CREATE TABLE IF NOT EXISTS servers (
+                server_id INTEGER PRIMARY KEY,
+                boot INTEGER NOT NULL DEFAULT 1
+            );
+            CREATE TABLE IF NOT EXISTS users (
                 id INTEGER PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE
             );
@@ -12,7 +16,10 @@ pub async fn initialize_database(conn: &Connection) -> Result<(), tokio_rusqlite
                 name TEXT NOT NULL,
                 parent_id INTEGER,
                 FOREIGN KEY(parent_id) REFERENCES channels(id)
-            );",
+            );
+            -- Like murmur, a fresh database gets one virtual server that boots by default.
+            INSERT INTO servers (server_id, boot)
+                SELECT 1, 1 WHERE NOT EXISTS (SELECT 1 FROM servers);",
         )?;
         Ok(())
     }).await
@@ -0,0 +1,152 @@
+/// Renders Mumble's HTML as plain text: line-breaking tags become newlines,
+/// images become `[image]`, links keep their target, other tags are dropped
+/// and entities are decoded.
+pub fn to_plain_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&decode_entities(&rest[..start]));
+        let Some(len) = rest[start..].find('>') else {
+            // Not a tag after all.
+            out.push_str(&decode_entities(&rest[start..]));
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + len];
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let closing = tag.starts_with('/');
+        match name.as_str() {
+            "br" => out.push('\n'),
+            "p" | "div" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" if closing => {
+                out.push('\n')
+            }
+            "li" if !closing => out.push_str("\n• "),
+            "img" => out.push_str("[image]"),
+            "a" if !closing => {
+                if let Some(href) = attribute(tag, "href") {
+                    out.push_str(&format!("[{}] ", decode_entities(href)));
+                }
+            }
+            _ => {}
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(&decode_entities(rest));
+
+    // Collapse the blank lines left behind by nested block tags.
+    let lines: Vec<&str> = out.lines().map(str::trim_end).collect();
+    let mut text = String::new();
+    for line in lines {
+        if line.is_empty() && (text.is_empty() || text.ends_with("\n\n")) {
+            continue;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.trim_end().to_string()
+}
+
+/// Escapes plain text so it can be sent as a Mumble text message.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("<br>"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The value of `name="..."` (or single-quoted) inside a tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lower = tag.to_ascii_lowercase();
+    let pos = lower.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[pos..];
+    let quote = value.chars().next()?;
+    if quote == '"' || quote == '\'' {
+        let end = value[1..].find(quote)?;
+        Some(&value[1..1 + end])
+    } else {
+        value.split_whitespace().next()
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_plain_text() {
+        assert_eq!(to_plain_text("Hello <b>world</b> &amp; co"), "Hello world & co");
+        assert_eq!(to_plain_text("one<br/>two<br>three"), "one\ntwo\nthree");
+        assert_eq!(
+            to_plain_text("<p>First</p><p>Second &lt;3</p>"),
+            "First\nSecond <3"
+        );
+        assert_eq!(
+            to_plain_text(r#"see <a href="https://mumble.info">here</a>"#),
+            "see [https://mumble.info] here"
+        );
+        assert_eq!(
+            to_plain_text(r#"<img src="data:image/png;base64,AAAA"/>"#),
+            "[image]"
+        );
+        assert_eq!(to_plain_text("<ul><li>a</li><li>b</li></ul>"), "• a\n• b");
+        assert_eq!(to_plain_text("a < b &#65;&#x42; & c"), "a < b AB & c");
+    }
+
+    #[test]
+    fn test_escape_round_trip() {
+        let text = "if a < b && c > d\n\"quoted\"";
+        assert_eq!(to_plain_text(&escape(text)), text);
+    }
+}
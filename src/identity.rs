@@ -0,0 +1,510 @@
+use crate::paths;
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use ed25519_dalek::SigningKey;
+use p12_keystore::{Certificate, KeyStore, KeyStoreEntry, PrivateKeyChain};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use pkcs8::EncodePrivateKey;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const IDENTITY_EXTENSION: &str = "p12";
+const SELECTION_FILE: &str = "identities.json";
+/// Name used for the identity that is created on first connect.
+pub const DEFAULT_IDENTITY: &str = "default";
+
+/// Salt for passphrase-derived identities. Changing it (or the Argon2
+/// parameters below) changes every derived certificate.
+const PASSPHRASE_SALT: &[u8] = b"mumble-rs client identity v1";
+const PASSPHRASE_MEMORY_KIB: u32 = 19 * 1024;
+const PASSPHRASE_ITERATIONS: u32 = 2;
+
+/// Secret material a deterministic identity is derived from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentitySeed {
+    /// 32 raw bytes, used directly as the Ed25519 secret key.
+    Bytes([u8; 32]),
+    /// Stretched to 32 bytes with Argon2id.
+    Passphrase(String),
+}
+
+impl IdentitySeed {
+    /// Parses a SHA-256 style hex string, as accepted by `--key-from-hash`.
+    pub fn from_hex(hex_seed: &str) -> Result<Self> {
+        let bytes = hex::decode(hex_seed.trim())?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("SHA256 hash must be 32 bytes (64 hex characters) long."))?;
+        Ok(Self::Bytes(bytes))
+    }
+
+    pub fn secret_bytes(&self) -> Result<[u8; 32]> {
+        match self {
+            Self::Bytes(bytes) => Ok(*bytes),
+            Self::Passphrase(passphrase) => {
+                if passphrase.is_empty() {
+                    bail!("Passphrase must not be empty");
+                }
+                let params = Params::new(
+                    PASSPHRASE_MEMORY_KIB,
+                    PASSPHRASE_ITERATIONS,
+                    1,
+                    Some(32),
+                )
+                .map_err(|e| anyhow!("Invalid Argon2 parameters: {}", e))?;
+                let mut out = [0u8; 32];
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), PASSPHRASE_SALT, &mut out)
+                    .map_err(|e| anyhow!("Failed to derive key from passphrase: {}", e))?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Ed25519 key pair whose secret key is exactly `seed`.
+pub fn ed25519_key_pair(seed: &[u8; 32]) -> Result<rcgen::KeyPair> {
+    let pkcs8_der = SigningKey::from_bytes(seed)
+        .to_pkcs8_der()
+        .map_err(|e| anyhow!("Failed to create PKCS#8 DER from secret key: {}", e))?;
+    Ok(rcgen::KeyPair::from_der_and_sign_algo(
+        &PrivateKeyDer::Pkcs8(pkcs8_der.as_bytes().into()),
+        &rcgen::PKCS_ED25519,
+    )?)
+}
+
+/// Certificate and key presented to the server as the client's identity.
+#[derive(Debug)]
+pub struct ClientIdentity {
+    pub cert_chain: Vec<CertificateDer<'static>>,
+    pub key: PrivateKeyDer<'static>,
+}
+
+impl Clone for ClientIdentity {
+    fn clone(&self) -> Self {
+        Self {
+            cert_chain: self.cert_chain.clone(),
+            key: self.key.clone_key(),
+        }
+    }
+}
+
+impl ClientIdentity {
+    /// Creates a new self-signed identity with `name` as the common name.
+    pub fn generate(name: &str) -> Result<Self> {
+        Self::self_signed(name, &rcgen::KeyPair::generate()?)
+    }
+
+    /// Derives the same certificate for the same `name` and `seed` every time.
+    ///
+    /// The serial number and validity period are set here rather than left
+    /// to rcgen's defaults, and Ed25519 signatures are deterministic, so the
+    /// certificate hash is stable and can be registered on a server before
+    /// the first connection.
+    pub fn derive(name: &str, seed: &IdentitySeed) -> Result<Self> {
+        let key_pair = ed25519_key_pair(&seed.secret_bytes()?)?;
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+        // The first 20 bytes of the public key's SHA-256 as a positive
+        // number, as rcgen 0.14 picks by default.
+        let mut serial = Sha256::digest(key_pair.public_key_raw())[..20].to_vec();
+        serial[0] &= 0x7f;
+        params.serial_number = Some(rcgen::SerialNumber::from_slice(&serial));
+        params.not_before = rcgen::date_time_ymd(1975, 1, 1);
+        params.not_after = rcgen::date_time_ymd(4096, 1, 1);
+        Self::self_signed_with(params, name, &key_pair)
+    }
+
+    fn self_signed(name: &str, key_pair: &rcgen::KeyPair) -> Result<Self> {
+        let params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+        Self::self_signed_with(params, name, key_pair)
+    }
+
+    fn self_signed_with(
+        mut params: rcgen::CertificateParams,
+        name: &str,
+        key_pair: &rcgen::KeyPair,
+    ) -> Result<Self> {
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, name);
+        let cert = params.self_signed(key_pair)?;
+        Ok(Self {
+            cert_chain: vec![cert.der().clone()],
+            key: PrivatePkcs8KeyDer::from(key_pair.serialize_der()).into(),
+        })
+    }
+
+    /// SHA-1 of the leaf certificate in lower-case hex.
+    ///
+    /// This is what Mumble servers store to recognise registered users.
+    pub fn hash(&self) -> String {
+        let leaf = self
+            .cert_chain
+            .first()
+            .map(|c| c.as_ref())
+            .unwrap_or_default();
+        hex::encode(Sha1::digest(leaf))
+    }
+
+    /// Subject of the leaf certificate, e.g. `CN=alice`.
+    pub fn subject(&self) -> Result<String> {
+        let leaf = self
+            .cert_chain
+            .first()
+            .ok_or_else(|| anyhow!("Identity has no certificate"))?;
+        Ok(Certificate::from_der(leaf)?.subject().to_string())
+    }
+
+    /// Reads a PKCS#12 bundle such as the official client's certificate backup.
+    pub fn from_pkcs12(data: &[u8], password: &str) -> Result<Self> {
+        let store = KeyStore::from_pkcs12(data, password)
+            .map_err(|e| anyhow!("Failed to read PKCS#12 bundle: {}", e))?;
+        let (_, chain) = store
+            .private_key_chain()
+            .ok_or_else(|| anyhow!("PKCS#12 bundle contains no private key"))?;
+        if chain.chain().is_empty() {
+            bail!("PKCS#12 bundle contains no certificate for its key");
+        }
+        Ok(Self {
+            cert_chain: chain
+                .chain()
+                .iter()
+                .map(|c| CertificateDer::from(c.as_der().to_vec()))
+                .collect(),
+            key: PrivatePkcs8KeyDer::from(chain.key().to_vec()).into(),
+        })
+    }
+
+    /// Writes the identity as a PKCS#12 bundle the official client can import.
+    pub fn to_pkcs12(&self, alias: &str, password: &str) -> Result<Vec<u8>> {
+        let PrivateKeyDer::Pkcs8(key) = &self.key else {
+            bail!("Only PKCS#8 keys can be exported");
+        };
+        let chain = self
+            .cert_chain
+            .iter()
+            .map(|c| Certificate::from_der(c))
+            .collect::<Result<Vec<_>, _>>()?;
+        let local_key_id = Sha1::digest(self.cert_chain[0].as_ref());
+        let mut store = KeyStore::new();
+        store.add_entry(
+            alias,
+            KeyStoreEntry::PrivateKeyChain(PrivateKeyChain::new(
+                key.secret_pkcs8_der(),
+                local_key_id,
+                chain,
+            )),
+        );
+        store
+            .writer(password)
+            .write()
+            .map_err(|e| anyhow!("Failed to write PKCS#12 bundle: {}", e))
+    }
+}
+
+/// Which identity to use by default and for specific servers.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Selection {
+    #[serde(default)]
+    default: Option<String>,
+    /// Keyed by `host:port`.
+    #[serde(default)]
+    servers: BTreeMap<String, String>,
+}
+
+/// Client identities stored as PKCS#12 files in one directory.
+#[derive(Debug, Clone)]
+pub struct IdentityStore {
+    dir: PathBuf,
+}
+
+impl Default for IdentityStore {
+    fn default() -> Self {
+        Self::new(paths::config_dir().join("identities"))
+    }
+}
+
+impl IdentityStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn identity_path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '));
+        if !valid {
+            bail!("Invalid identity name '{}'", name);
+        }
+        Ok(self.dir.join(format!("{}.{}", name, IDENTITY_EXTENSION)))
+    }
+
+    /// Names of all stored identities, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context(format!("Failed to read {}", self.dir.display())),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(IDENTITY_EXTENSION) {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(stem.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.identity_path(name).is_ok_and(|p| p.exists())
+    }
+
+    pub fn load(&self, name: &str) -> Result<ClientIdentity> {
+        let path = self.identity_path(name)?;
+        let data = fs::read(&path).with_context(|| format!("No identity named '{}'", name))?;
+        ClientIdentity::from_pkcs12(&data, "")
+            .with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    /// Stores `identity` under `name`, refusing to replace an existing one.
+    pub fn save(&self, name: &str, identity: &ClientIdentity) -> Result<()> {
+        let path = self.identity_path(name)?;
+        if path.exists() {
+            bail!("An identity named '{}' already exists", name);
+        }
+        paths::write_private(&path, &identity.to_pkcs12(name, "")?)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.identity_path(name)?;
+        fs::remove_file(&path).with_context(|| format!("No identity named '{}'", name))?;
+        let mut selection = self.selection()?;
+        if selection.default.as_deref() == Some(name) {
+            selection.default = None;
+        }
+        selection.servers.retain(|_, v| v != name);
+        self.save_selection(&selection)
+    }
+
+    pub fn generate(&self, name: &str) -> Result<ClientIdentity> {
+        let identity = ClientIdentity::generate(name)?;
+        self.save(name, &identity)?;
+        Ok(identity)
+    }
+
+    /// Derives an identity from `seed` and stores it under `name`.
+    pub fn derive(&self, name: &str, seed: &IdentitySeed) -> Result<ClientIdentity> {
+        let identity = ClientIdentity::derive(name, seed)?;
+        self.save(name, &identity)?;
+        Ok(identity)
+    }
+
+    /// Imports a PKCS#12 backup (for example one made by the official client).
+    pub fn import(&self, name: &str, file: &Path, password: &str) -> Result<ClientIdentity> {
+        let data = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let identity = ClientIdentity::from_pkcs12(&data, password)?;
+        self.save(name, &identity)?;
+        Ok(identity)
+    }
+
+    pub fn export(&self, name: &str, file: &Path, password: &str) -> Result<()> {
+        let identity = self.load(name)?;
+        paths::write_private(file, &identity.to_pkcs12(name, password)?)
+    }
+
+    fn selection_path(&self) -> PathBuf {
+        self.dir.join(SELECTION_FILE)
+    }
+
+    fn selection(&self) -> Result<Selection> {
+        match fs::read_to_string(self.selection_path()) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Selection::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_selection(&self, selection: &Selection) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.selection_path(),
+            serde_json::to_string_pretty(selection)?,
+        )?;
+        Ok(())
+    }
+
+    /// The identity used for servers without their own selection.
+    pub fn default_name(&self) -> Result<Option<String>> {
+        Ok(self.selection()?.default)
+    }
+
+    pub fn set_default(&self, name: &str) -> Result<()> {
+        if !self.contains(name) {
+            bail!("No identity named '{}'", name);
+        }
+        let mut selection = self.selection()?;
+        selection.default = Some(name.to_string());
+        self.save_selection(&selection)
+    }
+
+    /// Uses `name` for `host:port`, or goes back to the default if `None`.
+    pub fn set_for_server(&self, host: &str, port: u16, name: Option<&str>) -> Result<()> {
+        let key = format!("{}:{}", host, port);
+        let mut selection = self.selection()?;
+        match name {
+            Some(name) => {
+                if !self.contains(name) {
+                    bail!("No identity named '{}'", name);
+                }
+                selection.servers.insert(key, name.to_string());
+            }
+            None => {
+                selection.servers.remove(&key);
+            }
+        }
+        self.save_selection(&selection)
+    }
+
+    /// Per-server identity selections as `(host:port, name)`.
+    pub fn server_selections(&self) -> Result<Vec<(String, String)>> {
+        Ok(self.selection()?.servers.into_iter().collect())
+    }
+
+    /// Name of the identity to present to `host:port`, if any is configured.
+    pub fn name_for(&self, host: &str, port: u16) -> Result<Option<String>> {
+        let mut selection = self.selection()?;
+        Ok(selection
+            .servers
+            .remove(&format!("{}:{}", host, port))
+            .or(selection.default))
+    }
+
+    /// Loads the identity for `host:port`.
+    ///
+    /// If nothing is configured yet a default identity is generated and kept,
+    /// so the user's registrations survive restarts.
+    pub fn identity_for(&self, host: &str, port: u16) -> Result<ClientIdentity> {
+        if let Some(name) = self.name_for(host, port)? {
+            return self.load(&name);
+        }
+        let identity = if self.contains(DEFAULT_IDENTITY) {
+            self.load(DEFAULT_IDENTITY)?
+        } else {
+            self.generate(DEFAULT_IDENTITY)?
+        };
+        self.set_default(DEFAULT_IDENTITY)?;
+        Ok(identity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pkcs12_round_trip_keeps_hash() {
+        let identity = ClientIdentity::generate("alice").unwrap();
+        assert_eq!(identity.hash().len(), 40);
+        assert_eq!(identity.subject().unwrap(), "CN=alice");
+
+        let bundle = identity.to_pkcs12("alice", "secret").unwrap();
+        let imported = ClientIdentity::from_pkcs12(&bundle, "secret").unwrap();
+        assert_eq!(imported.hash(), identity.hash());
+        assert_eq!(imported.key.secret_der(), identity.key.secret_der());
+        assert!(ClientIdentity::from_pkcs12(&bundle, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_derived_identity_is_reproducible() {
+        let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let seed = IdentitySeed::from_hex(hash).unwrap();
+        let first = ClientIdentity::derive("alice", &seed).unwrap();
+        let second = ClientIdentity::derive("alice", &seed).unwrap();
+        // Hashes registered on servers must survive upgrades of rcgen.
+        assert_eq!(first.hash(), "adb69a50a670927fb1cbb4305ee6dc60616a15c1");
+        assert_eq!(first.hash(), second.hash());
+        assert_eq!(first.cert_chain, second.cert_chain);
+        assert_ne!(
+            ClientIdentity::derive("bob", &seed).unwrap().hash(),
+            first.hash()
+        );
+
+        let passphrase = IdentitySeed::Passphrase("correct horse battery staple".into());
+        let derived = ClientIdentity::derive("alice", &passphrase).unwrap();
+        assert_eq!(derived.hash(), "af71345f828760f5e56d0e8017533ec453e17aca");
+        assert_eq!(
+            derived.hash(),
+            ClientIdentity::derive("alice", &passphrase).unwrap().hash()
+        );
+        assert_ne!(derived.hash(), first.hash());
+        let other = IdentitySeed::Passphrase("correct horse battery stapler".into());
+        assert_ne!(
+            ClientIdentity::derive("alice", &other).unwrap().hash(),
+            derived.hash()
+        );
+
+        // Stored and reloaded derived identities keep their hash.
+        let dir = tempfile::tempdir().unwrap();
+        let store = IdentityStore::new(dir.path());
+        store.derive("alice", &seed).unwrap();
+        assert_eq!(store.load("alice").unwrap().hash(), first.hash());
+
+        assert!(IdentitySeed::from_hex("abcd").is_err());
+        assert!(IdentitySeed::Passphrase(String::new()).secret_bytes().is_err());
+    }
+
+    #[test]
+    fn test_store_selects_identities() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = IdentityStore::new(dir.path());
+        assert!(store.list().unwrap().is_empty());
+
+        // The first connection creates and remembers a default identity.
+        let first = store.identity_for("example.org", 64738).unwrap();
+        assert_eq!(store.list().unwrap(), vec![DEFAULT_IDENTITY]);
+        assert_eq!(
+            store.identity_for("example.org", 64738).unwrap().hash(),
+            first.hash()
+        );
+
+        let work = store.generate("work").unwrap();
+        assert!(store.generate("work").is_err());
+        store
+            .set_for_server("example.org", 64738, Some("work"))
+            .unwrap();
+        assert_eq!(
+            store.identity_for("example.org", 64738).unwrap().hash(),
+            work.hash()
+        );
+        assert_eq!(
+            store.identity_for("other.org", 64738).unwrap().hash(),
+            first.hash()
+        );
+
+        let backup = dir.path().join("backup.p12");
+        store.export("work", &backup, "pw").unwrap();
+        store.remove("work").unwrap();
+        assert_eq!(
+            store.name_for("example.org", 64738).unwrap().as_deref(),
+            Some(DEFAULT_IDENTITY)
+        );
+        let restored = store.import("restored", &backup, "pw").unwrap();
+        assert_eq!(restored.hash(), work.hash());
+
+        assert!(store.generate("../escape").is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::paths;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const KNOWN_HOSTS_FILE: &str = "known_hosts.json";
+
+/// The certificate a server presented the first time we connected to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KnownHost {
+    pub host: String,
+    pub port: u16,
+    /// SHA-256 fingerprint of the server's certificate.
+    pub fingerprint: String,
+    /// Seconds since the Unix epoch.
+    pub first_seen: u64,
+}
+
+/// What the store says about a certificate a server presented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostCheck {
+    /// We have never connected to the server.
+    Unknown,
+    Matches,
+    /// The server presented a different certificate than before.
+    Changed { old: String },
+}
+
+/// Certificates of servers that aren't signed by a certificate authority,
+/// trusted on first use like SSH does.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KnownHosts {
+    pub hosts: Vec<KnownHost>,
+}
+
+impl KnownHosts {
+    pub fn default_path() -> PathBuf {
+        paths::config_dir().join(KNOWN_HOSTS_FILE)
+    }
+
+    /// Reads the store, starting empty if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        paths::read_json_or_default(path, "known hosts")
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        paths::write_private(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn get(&self, host: &str, port: u16) -> Option<&KnownHost> {
+        self.hosts
+            .iter()
+            .find(|h| h.host.eq_ignore_ascii_case(host) && h.port == port)
+    }
+
+    pub fn check(&self, host: &str, port: u16, fingerprint: &str) -> HostCheck {
+        match self.get(host, port) {
+            None => HostCheck::Unknown,
+            Some(known) if known.fingerprint.eq_ignore_ascii_case(fingerprint) => HostCheck::Matches,
+            Some(known) => HostCheck::Changed {
+                old: known.fingerprint.clone(),
+            },
+        }
+    }
+
+    /// Remembers `fingerprint` for `host:port`, replacing any earlier one.
+    pub fn trust(&mut self, host: &str, port: u16, fingerprint: &str) {
+        self.hosts
+            .retain(|h| !(h.host.eq_ignore_ascii_case(host) && h.port == port));
+        self.hosts.push(KnownHost {
+            host: host.to_string(),
+            port,
+            fingerprint: fingerprint.to_string(),
+            first_seen: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        });
+    }
+
+    /// Drops `known` unless the server has been trusted with another
+    /// certificate since.
+    pub fn forget(&mut self, known: &KnownHost) -> bool {
+        let before = self.hosts.len();
+        self.hosts.retain(|h| {
+            !(h.host.eq_ignore_ascii_case(&known.host)
+                && h.port == known.port
+                && h.fingerprint.eq_ignore_ascii_case(&known.fingerprint))
+        });
+        self.hosts.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trust_and_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(KNOWN_HOSTS_FILE);
+        let mut known = KnownHosts::load_from(&path).unwrap();
+        assert_eq!(known.check("voice.example.org", 64738, "AA:BB"), HostCheck::Unknown);
+
+        known.trust("voice.example.org", 64738, "AA:BB");
+        known.save_to(&path).unwrap();
+        let mut known = KnownHosts::load_from(&path).unwrap();
+        assert_eq!(known.check("Voice.Example.org", 64738, "aa:bb"), HostCheck::Matches);
+        assert_eq!(known.check("voice.example.org", 64739, "AA:BB"), HostCheck::Unknown);
+        assert_eq!(
+            known.check("voice.example.org", 64738, "CC:DD"),
+            HostCheck::Changed {
+                old: "AA:BB".to_string()
+            }
+        );
+
+        known.trust("voice.example.org", 64738, "CC:DD");
+        assert_eq!(known.hosts.len(), 1);
+        assert_eq!(known.check("voice.example.org", 64738, "CC:DD"), HostCheck::Matches);
+        let mut stale = known.hosts[0].clone();
+        stale.fingerprint = "AA:BB".to_string();
+        assert!(!known.forget(&stale));
+        assert!(known.forget(&known.hosts[0].clone()));
+        assert!(known.hosts.is_empty());
+    }
+}
@@ -4,15 +4,26 @@
 
 use mumble_sys::*;
 
+pub mod builder;
+pub mod cert;
 pub mod cli;
 pub mod config;
 pub mod connection;
 pub mod db;
 pub mod embed;
+pub mod html;
+pub mod identity;
+pub mod known_hosts;
 pub mod lan;
 pub mod local;
+pub mod log_buffer;
+pub mod mumble_url;
+pub mod paths;
+pub mod ping;
+pub mod proto;
 pub mod public;
 pub mod server;
+pub mod server_book;
 pub mod ui;
 
 #[no_mangle]
@@ -1,7 +1,17 @@
+use crate::cert::{self, CertOptions};
+use crate::config::MetaParams;
 use crate::lan::ServerInfo;
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
 use std::net::{IpAddr, Ipv4Addr, TcpStream};
+use std::path::{Path, PathBuf};
 
 const LOCAL_SERVER_ADDRESS: &str = "127.0.0.1:64738";
+const SETTINGS_FILE: &str = "local_server.json";
+const CERT_FILE: &str = "mumble-server.pem";
+const KEY_FILE: &str = "mumble-server.key";
 
 /// Attempts to detect a Mumble server running on the local machine.
 ///
@@ -19,3 +29,202 @@ pub fn detect_local_server() -> Option<ServerInfo> {
         None
     }
 }
+
+/// Where the embedded server keeps its database and certificate.
+pub fn local_server_dir() -> PathBuf {
+    paths::data_dir().join("local-server")
+}
+
+/// Makes sure `dir` holds a certificate for the embedded server, creating
+/// one on first use, and returns its SHA-1 fingerprint.
+///
+/// An existing certificate is always reused so clients that pinned it keep
+/// trusting the server.
+pub fn ensure_certificate(dir: &Path) -> Result<String> {
+    let cert_path = dir.join(CERT_FILE);
+    if cert_path.exists() && dir.join(KEY_FILE).exists() {
+        let certs = cert::load_certs(&cert_path.to_string_lossy())?;
+        return Ok(cert::sha1_fingerprint(&certs[0]));
+    }
+    regenerate_certificate(dir)
+}
+
+/// Replaces the embedded server's certificate and key with new ones and
+/// returns the new SHA-1 fingerprint.
+///
+/// Both are written to temporary files first and renamed into place one
+/// after the other. If the certificate can't be replaced, the old key is
+/// put back, so a failure leaves the old pair rather than a key that doesn't
+/// match its certificate. The pair is mismatched between the two renames,
+/// so this must not run while the server is starting.
+pub fn regenerate_certificate(dir: &Path) -> Result<String> {
+    let generated = cert::generate_self_signed(&CertOptions {
+        subject_alt_names: vec!["localhost".to_string(), "127.0.0.1".to_string()],
+        common_name: Some("Mumble Local Server".to_string()),
+        ..Default::default()
+    })?;
+    let (key_path, cert_path) = (dir.join(KEY_FILE), dir.join(CERT_FILE));
+    let (key_tmp, cert_tmp) = (sibling(&key_path, "tmp"), sibling(&cert_path, "tmp"));
+    let key_old = sibling(&key_path, "old");
+    paths::write_private(&key_tmp, generated.key_pem.as_bytes())?;
+    fs::write(&cert_tmp, &generated.cert_pem)
+        .with_context(|| format!("Failed to write {}", cert_tmp.display()))?;
+
+    let had_key = key_path.exists();
+    if had_key {
+        fs::rename(&key_path, &key_old)
+            .with_context(|| format!("Failed to replace {}", key_path.display()))?;
+    }
+    let swapped = fs::rename(&key_tmp, &key_path)
+        .with_context(|| format!("Failed to replace {}", key_path.display()))
+        .and_then(|()| {
+            fs::rename(&cert_tmp, &cert_path)
+                .with_context(|| format!("Failed to replace {}", cert_path.display()))
+        });
+    if let Err(e) = swapped {
+        if had_key {
+            fs::rename(&key_old, &key_path).ok();
+        } else {
+            fs::remove_file(&key_path).ok();
+        }
+        fs::remove_file(&key_tmp).ok();
+        fs::remove_file(&cert_tmp).ok();
+        return Err(e);
+    }
+    if had_key {
+        fs::remove_file(&key_old).ok();
+    }
+    Ok(cert::sha1_fingerprint(&generated.cert_der))
+}
+
+/// `path` with `.extension` appended to its file name.
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+/// User-editable settings of the server embedded in the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalServerSettings {
+    pub port: u16,
+    pub password: String,
+    pub welcome_text: String,
+    pub max_users: u32,
+}
+
+impl Default for LocalServerSettings {
+    fn default() -> Self {
+        let defaults = MetaParams::default();
+        Self {
+            port: defaults.port,
+            password: defaults.password,
+            welcome_text: defaults.welcome_text,
+            max_users: defaults.max_users,
+        }
+    }
+}
+
+impl LocalServerSettings {
+    pub fn default_path() -> PathBuf {
+        paths::config_dir().join(SETTINGS_FILE)
+    }
+
+    /// Reads the settings file, falling back to defaults if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        paths::read_json_or_default(path, "local server settings")
+    }
+
+    /// Writes the settings readable only by us, as they hold the server
+    /// password.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        paths::write_private(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Server parameters with the database and certificate kept in `dir`.
+    pub fn to_meta_params(&self, dir: &Path) -> MetaParams {
+        let in_dir = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        MetaParams {
+            port: self.port,
+            password: self.password.clone(),
+            welcome_text: self.welcome_text.clone(),
+            max_users: self.max_users,
+            database: in_dir("murmur.sqlite"),
+            ssl_cert: in_dir(CERT_FILE),
+            ssl_key: in_dir(KEY_FILE),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("local_server.json");
+        assert_eq!(
+            LocalServerSettings::load_from(&path).unwrap(),
+            LocalServerSettings::default()
+        );
+
+        let settings = LocalServerSettings {
+            port: 50000,
+            password: "secret".to_string(),
+            welcome_text: "Hi".to_string(),
+            max_users: 5,
+        };
+        settings.save_to(&path).unwrap();
+        assert_eq!(LocalServerSettings::load_from(&path).unwrap(), settings);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let params = settings.to_meta_params(dir.path());
+        assert_eq!(params.port, 50000);
+        assert_eq!(params.max_users, 5);
+        assert!(params.database.starts_with(dir.path().to_str().unwrap()));
+        assert!(params.ssl_cert.starts_with(dir.path().to_str().unwrap()));
+
+        // Missing fields fall back to defaults.
+        std::fs::write(&path, r#"{"port": 1234}"#).unwrap();
+        let partial = LocalServerSettings::load_from(&path).unwrap();
+        assert_eq!(partial.port, 1234);
+        assert_eq!(partial.max_users, LocalServerSettings::default().max_users);
+    }
+
+    #[test]
+    fn test_certificate_is_reused_until_regenerated() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = ensure_certificate(dir.path()).unwrap();
+        assert!(dir.path().join(CERT_FILE).exists());
+        assert!(dir.path().join(KEY_FILE).exists());
+        assert_eq!(ensure_certificate(dir.path()).unwrap(), first);
+
+        let second = regenerate_certificate(dir.path()).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(ensure_certificate(dir.path()).unwrap(), second);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_failed_regeneration_keeps_the_old_key() {
+        let dir = tempfile::tempdir().unwrap();
+        ensure_certificate(dir.path()).unwrap();
+        let key = std::fs::read(dir.path().join(KEY_FILE)).unwrap();
+        // A directory in place of the certificate makes its rename fail.
+        std::fs::remove_file(dir.path().join(CERT_FILE)).unwrap();
+        std::fs::create_dir_all(dir.path().join(CERT_FILE).join("x")).unwrap();
+
+        assert!(regenerate_certificate(dir.path()).is_err());
+        assert_eq!(std::fs::read(dir.path().join(KEY_FILE)).unwrap(), key);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
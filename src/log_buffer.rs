@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use log::{Level, LevelFilter};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use time::{OffsetDateTime, UtcOffset};
+
+/// How many records the embedded server keeps by default.
+pub const DEFAULT_CAPACITY: usize = 5000;
+
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+/// Looks up the local time zone's offset for [`local_time`], falling back
+/// to UTC. The lookup fails once other threads run, so call this first
+/// thing in `main`.
+pub fn detect_local_offset() {
+    LOCAL_OFFSET.get_or_init(|| UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC));
+}
+
+/// `timestamp` in the time zone found by [`detect_local_offset`].
+pub fn local_time(timestamp: SystemTime) -> OffsetDateTime {
+    let offset = LOCAL_OFFSET.get().copied().unwrap_or(UtcOffset::UTC);
+    OffsetDateTime::from(timestamp).to_offset(offset)
+}
+
+/// A log buffer shared between the logger and the UI.
+pub type SharedLogBuffer = Arc<Mutex<LogBuffer>>;
+
+/// One captured log line.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    /// Increases by one for every record pushed, so it survives eviction.
+    pub seq: u64,
+    pub timestamp: SystemTime,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl LogRecord {
+    /// `HH:MM:SS` in local time, for the log view.
+    pub fn time_of_day(&self) -> String {
+        let t = local_time(self.timestamp);
+        format!("{:02}:{:02}:{:02}", t.hour(), t.minute(), t.second())
+    }
+
+    /// The full line written when saving a log.
+    pub fn to_line(&self) -> String {
+        let t = OffsetDateTime::from(self.timestamp);
+        format!(
+            "{}-{:02}-{:02} {:02}:{:02}:{:02}Z {:<5} {}: {}",
+            t.year(),
+            u8::from(t.month()),
+            t.day(),
+            t.hour(),
+            t.minute(),
+            t.second(),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// Which records the log view shows.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    pub min_level: LevelFilter,
+    /// Case-insensitive substring of the target or message.
+    pub text: String,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            min_level: LevelFilter::Trace,
+            text: String::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        if record.level > self.min_level {
+            return false;
+        }
+        if self.text.is_empty() {
+            return true;
+        }
+        let needle = self.text.to_lowercase();
+        record.message.to_lowercase().contains(&needle)
+            || record.target.to_lowercase().contains(&needle)
+    }
+}
+
+/// Ring buffer of the most recent log records; the oldest are dropped once
+/// `capacity` is reached.
+#[derive(Debug)]
+pub struct LogBuffer {
+    records: VecDeque<LogRecord>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity.min(DEFAULT_CAPACITY)),
+            capacity: capacity.max(1),
+            next_seq: 0,
+        }
+    }
+
+    pub fn shared(capacity: usize) -> SharedLogBuffer {
+        Arc::new(Mutex::new(Self::new(capacity)))
+    }
+
+    pub fn push(&mut self, level: Level, target: &str, message: String) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(LogRecord {
+            seq: self.next_seq,
+            timestamp: SystemTime::now(),
+            level,
+            target: target.to_string(),
+            message,
+        });
+        self.next_seq += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Total number of records ever pushed; changes whenever the buffer does.
+    pub fn total(&self) -> u64 {
+        self.next_seq
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LogRecord> {
+        self.records.iter()
+    }
+
+    pub fn filtered<'a>(&'a self, filter: &'a LogFilter) -> impl Iterator<Item = &'a LogRecord> {
+        self.records.iter().filter(move |r| filter.matches(r))
+    }
+
+    /// Writes the records matching `filter` to `path`, returning how many.
+    pub fn save_to(&self, path: &Path, filter: &LogFilter) -> Result<usize> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file =
+            fs::File::create(path).with_context(|| format!("Failed to write {}", path.display()))?;
+        let mut count = 0;
+        for record in self.filtered(filter) {
+            writeln!(file, "{}", record.to_line())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oldest_records_are_dropped() {
+        let mut buffer = LogBuffer::new(3);
+        for i in 0..5 {
+            buffer.push(Level::Info, "test", format!("message {}", i));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.total(), 5);
+        let seqs: Vec<u64> = buffer.iter().map(|r| r.seq).collect();
+        assert_eq!(seqs, vec![2, 3, 4]);
+        assert_eq!(buffer.iter().next().unwrap().message, "message 2");
+    }
+
+    #[test]
+    fn test_filter_by_level_and_text() {
+        let mut buffer = LogBuffer::new(10);
+        buffer.push(Level::Debug, "mumble::server", "Accepted connection".to_string());
+        buffer.push(Level::Info, "mumble::server", "User alice connected".to_string());
+        buffer.push(Level::Error, "mumble::db", "Query failed".to_string());
+
+        let mut filter = LogFilter {
+            min_level: LevelFilter::Info,
+            ..Default::default()
+        };
+        assert_eq!(buffer.filtered(&filter).count(), 2);
+
+        filter.text = "ALICE".to_string();
+        let matched: Vec<_> = buffer.filtered(&filter).collect();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].level, Level::Info);
+
+        filter.text = "db".to_string();
+        assert_eq!(buffer.filtered(&filter).count(), 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.log");
+        assert_eq!(buffer.save_to(&path, &filter).unwrap(), 1);
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.trim_end().ends_with("ERROR mumble::db: Query failed"));
+    }
+}
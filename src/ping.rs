@@ -0,0 +1,195 @@
+use anyhow::{anyhow, Result};
+use futures_util::stream::{self, StreamExt};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::sync::mpsc;
+
+/// Size of the unauthenticated UDP ping reply: version, ident, users,
+/// max users and max bandwidth.
+const PING_REPLY_LEN: usize = 24;
+
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Reply to a Mumble UDP server ping, as used by the official server browser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingReply {
+    pub version: u32,
+    pub users: u32,
+    pub max_users: u32,
+    pub max_bandwidth: u32,
+    pub latency: Duration,
+}
+
+impl PingReply {
+    /// Formats the packed `0x00MMmmpp` version as `major.minor.patch`.
+    pub fn version_string(&self) -> String {
+        format!(
+            "{}.{}.{}",
+            (self.version >> 16) & 0xff,
+            (self.version >> 8) & 0xff,
+            self.version & 0xff
+        )
+    }
+}
+
+fn encode_request(ident: u64) -> [u8; 12] {
+    let mut buf = [0u8; 12];
+    buf[4..].copy_from_slice(&ident.to_be_bytes());
+    buf
+}
+
+fn decode_reply(buf: &[u8], ident: u64, latency: Duration) -> Option<PingReply> {
+    if buf.len() < PING_REPLY_LEN {
+        return None;
+    }
+    let word = |i: usize| u32::from_be_bytes(buf[i..i + 4].try_into().unwrap());
+    if u64::from_be_bytes(buf[4..12].try_into().unwrap()) != ident {
+        return None;
+    }
+    Some(PingReply {
+        version: word(0),
+        users: word(12),
+        max_users: word(16),
+        max_bandwidth: word(20),
+        latency,
+    })
+}
+
+/// Every address `host` resolves to.
+async fn resolve(host: &str, port: u16, timeout: Duration) -> Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = tokio::time::timeout(timeout, lookup_host((host, port)))
+        .await
+        .map_err(|_| anyhow!("Resolving {} timed out", host))??
+        .collect();
+    if addresses.is_empty() {
+        return Err(anyhow!("No address found for {}", host));
+    }
+    Ok(addresses)
+}
+
+/// Sends a single UDP ping to a Mumble server and waits for the reply.
+pub async fn ping(host: &str, port: u16, timeout: Duration) -> Result<PingReply> {
+    let addr = resolve(host, port, timeout).await?[0];
+    ping_address(addr, timeout).await
+}
+
+/// What a server's detail view shows beyond the server list.
+#[derive(Debug, Clone, Default)]
+pub struct Probe {
+    pub addresses: Vec<IpAddr>,
+    pub reply: Option<PingReply>,
+    /// Why resolving or pinging failed.
+    pub error: Option<String>,
+}
+
+/// Resolves `host` and pings the first address.
+pub async fn probe(host: &str, port: u16, timeout: Duration) -> Probe {
+    let addresses = match resolve(host, port, timeout).await {
+        Ok(addresses) => addresses,
+        Err(e) => {
+            return Probe {
+                error: Some(format!("{:#}", e)),
+                ..Default::default()
+            }
+        }
+    };
+    let result = ping_address(addresses[0], timeout).await;
+    let mut ips: Vec<IpAddr> = addresses.iter().map(|a| a.ip()).collect();
+    ips.dedup();
+    Probe {
+        addresses: ips,
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        reply: result.ok(),
+    }
+}
+
+async fn ping_address(addr: SocketAddr, timeout: Duration) -> Result<PingReply> {
+    let bind_addr = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(addr).await?;
+
+    let ident = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let started = Instant::now();
+    socket.send(&encode_request(ident)).await?;
+
+    let mut buf = [0u8; 64];
+    tokio::time::timeout(timeout, async {
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            if let Some(reply) = decode_reply(&buf[..len], ident, started.elapsed()) {
+                return Ok(reply);
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("Ping to {} timed out", addr))?
+}
+
+/// Pings every target with bounded concurrency, sending each result as it
+/// arrives. Stops early if the receiver is dropped.
+pub async fn ping_many(
+    targets: Vec<(String, u16)>,
+    concurrency: usize,
+    timeout: Duration,
+    tx: mpsc::Sender<(String, u16, Result<PingReply>)>,
+) {
+    let mut results = stream::iter(targets)
+        .map(|(host, port)| async move {
+            let result = ping(&host, port, timeout).await;
+            (host, port, result)
+        })
+        .buffer_unordered(concurrency.max(1));
+    while let Some(result) = results.next().await {
+        if tx.send(result).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_reply() {
+        let mut buf = [0u8; 24];
+        buf[0..4].copy_from_slice(&0x0001_0204u32.to_be_bytes());
+        buf[4..12].copy_from_slice(&42u64.to_be_bytes());
+        buf[12..16].copy_from_slice(&3u32.to_be_bytes());
+        buf[16..20].copy_from_slice(&100u32.to_be_bytes());
+        buf[20..24].copy_from_slice(&72000u32.to_be_bytes());
+
+        let reply = decode_reply(&buf, 42, Duration::from_millis(5)).unwrap();
+        assert_eq!(reply.version_string(), "1.2.4");
+        assert_eq!(reply.users, 3);
+        assert_eq!(reply.max_users, 100);
+        assert_eq!(reply.max_bandwidth, 72000);
+        assert!(decode_reply(&buf, 43, Duration::ZERO).is_none());
+        assert!(decode_reply(&buf[..20], 42, Duration::ZERO).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ping_local_stub() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 12];
+            let (_, peer) = server.recv_from(&mut buf).await.unwrap();
+            let mut reply = [0u8; 24];
+            reply[0..4].copy_from_slice(&0x0001_0500u32.to_be_bytes());
+            reply[4..12].copy_from_slice(&buf[4..12]);
+            reply[12..16].copy_from_slice(&7u32.to_be_bytes());
+            reply[16..20].copy_from_slice(&50u32.to_be_bytes());
+            server.send_to(&reply, peer).await.unwrap();
+        });
+
+        let reply = ping("127.0.0.1", port, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(reply.version_string(), "1.5.0");
+        assert_eq!(reply.users, 7);
+        assert_eq!(reply.max_users, 50);
+    }
+}
@@ -0,0 +1,694 @@
+//! Mumble control channel messages and TCP framing.
+//!
+//! The message definitions mirror `Mumble.proto` (proto2) and are written by
+//! hand with `prost` derives so the build does not need `protoc`.
+
+use anyhow::{anyhow, Result};
+use prost::Message as _;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Protocol version we announce, `1.5.0`.
+pub const VERSION_MAJOR: u64 = 1;
+pub const VERSION_MINOR: u64 = 5;
+pub const VERSION_PATCH: u64 = 0;
+
+/// Largest control message we accept, matching the official server.
+pub const MAX_MESSAGE_LEN: usize = 8 * 1024 * 1024 - 1;
+
+/// Channel permission bits as used in `ServerSync.permissions` and
+/// `PermissionQuery.permissions`.
+pub mod permissions {
+    pub const WRITE: u32 = 0x1;
+    pub const TRAVERSE: u32 = 0x2;
+    pub const ENTER: u32 = 0x4;
+    pub const SPEAK: u32 = 0x8;
+    pub const MUTE_DEAFEN: u32 = 0x10;
+    pub const MOVE: u32 = 0x20;
+    pub const MAKE_CHANNEL: u32 = 0x40;
+    pub const LINK_CHANNEL: u32 = 0x80;
+    pub const WHISPER: u32 = 0x100;
+    pub const TEXT_MESSAGE: u32 = 0x200;
+    pub const MAKE_TEMP_CHANNEL: u32 = 0x400;
+    pub const LISTEN: u32 = 0x800;
+    pub const KICK: u32 = 0x10000;
+    pub const BAN: u32 = 0x20000;
+    pub const REGISTER: u32 = 0x40000;
+    pub const SELF_REGISTER: u32 = 0x80000;
+
+    /// What every authenticated user may do on the root channel by default.
+    pub const DEFAULT: u32 =
+        TRAVERSE | ENTER | SPEAK | WHISPER | TEXT_MESSAGE | MAKE_TEMP_CHANNEL | LISTEN | SELF_REGISTER;
+}
+
+/// Legacy 32-bit version field, `0x00MMmmpp`.
+pub fn version_v1() -> u32 {
+    ((VERSION_MAJOR << 16) | (VERSION_MINOR << 8) | VERSION_PATCH) as u32
+}
+
+/// 64-bit version field introduced with Mumble 1.5.
+pub fn version_v2() -> u64 {
+    (VERSION_MAJOR << 48) | (VERSION_MINOR << 32) | (VERSION_PATCH << 16)
+}
+
+/// Formats a `Version` message as `major.minor.patch`, preferring the v2 field.
+pub fn format_version(version: &Version) -> String {
+    if let Some(v2) = version.version_v2 {
+        format!("{}.{}.{}", v2 >> 48, (v2 >> 32) & 0xffff, (v2 >> 16) & 0xffff)
+    } else if let Some(v1) = version.version_v1 {
+        format!("{}.{}.{}", v1 >> 16, (v1 >> 8) & 0xff, v1 & 0xff)
+    } else {
+        "unknown".to_string()
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Version {
+    #[prost(uint32, optional, tag = "1")]
+    pub version_v1: Option<u32>,
+    #[prost(uint64, optional, tag = "5")]
+    pub version_v2: Option<u64>,
+    #[prost(string, optional, tag = "2")]
+    pub release: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub os: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub os_version: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UdpTunnel {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub packet: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Authenticate {
+    #[prost(string, optional, tag = "1")]
+    pub username: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub password: Option<String>,
+    #[prost(string, repeated, tag = "3")]
+    pub tokens: Vec<String>,
+    #[prost(int32, repeated, packed = "false", tag = "4")]
+    pub celt_versions: Vec<i32>,
+    #[prost(bool, optional, tag = "5")]
+    pub opus: Option<bool>,
+    #[prost(int32, optional, tag = "6")]
+    pub client_type: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Ping {
+    #[prost(uint64, optional, tag = "1")]
+    pub timestamp: Option<u64>,
+    #[prost(uint32, optional, tag = "2")]
+    pub good: Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub late: Option<u32>,
+    #[prost(uint32, optional, tag = "4")]
+    pub lost: Option<u32>,
+    #[prost(uint32, optional, tag = "5")]
+    pub resync: Option<u32>,
+    #[prost(uint32, optional, tag = "6")]
+    pub udp_packets: Option<u32>,
+    #[prost(uint32, optional, tag = "7")]
+    pub tcp_packets: Option<u32>,
+    #[prost(float, optional, tag = "8")]
+    pub udp_ping_avg: Option<f32>,
+    #[prost(float, optional, tag = "9")]
+    pub udp_ping_var: Option<f32>,
+    #[prost(float, optional, tag = "10")]
+    pub tcp_ping_avg: Option<f32>,
+    #[prost(float, optional, tag = "11")]
+    pub tcp_ping_var: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum RejectType {
+    None = 0,
+    WrongVersion = 1,
+    InvalidUsername = 2,
+    WrongUserPw = 3,
+    WrongServerPw = 4,
+    UsernameInUse = 5,
+    ServerFull = 6,
+    NoCertificate = 7,
+    AuthenticatorFail = 8,
+    NoNewConnections = 9,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Reject {
+    #[prost(enumeration = "RejectType", optional, tag = "1")]
+    pub r#type: Option<i32>,
+    #[prost(string, optional, tag = "2")]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerSync {
+    #[prost(uint32, optional, tag = "1")]
+    pub session: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub max_bandwidth: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub welcome_text: Option<String>,
+    #[prost(uint64, optional, tag = "4")]
+    pub permissions: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChannelRemove {
+    #[prost(uint32, required, tag = "1")]
+    pub channel_id: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChannelState {
+    #[prost(uint32, optional, tag = "1")]
+    pub channel_id: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub parent: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(uint32, repeated, packed = "false", tag = "4")]
+    pub links: Vec<u32>,
+    #[prost(string, optional, tag = "5")]
+    pub description: Option<String>,
+    #[prost(uint32, repeated, packed = "false", tag = "6")]
+    pub links_add: Vec<u32>,
+    #[prost(uint32, repeated, packed = "false", tag = "7")]
+    pub links_remove: Vec<u32>,
+    #[prost(bool, optional, tag = "8")]
+    pub temporary: Option<bool>,
+    #[prost(int32, optional, tag = "9")]
+    pub position: Option<i32>,
+    #[prost(bytes = "vec", optional, tag = "10")]
+    pub description_hash: Option<Vec<u8>>,
+    #[prost(uint32, optional, tag = "11")]
+    pub max_users: Option<u32>,
+    #[prost(bool, optional, tag = "12")]
+    pub is_enter_restricted: Option<bool>,
+    #[prost(bool, optional, tag = "13")]
+    pub can_enter: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UserRemove {
+    #[prost(uint32, required, tag = "1")]
+    pub session: u32,
+    #[prost(uint32, optional, tag = "2")]
+    pub actor: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub reason: Option<String>,
+    #[prost(bool, optional, tag = "4")]
+    pub ban: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UserState {
+    #[prost(uint32, optional, tag = "1")]
+    pub session: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub actor: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(uint32, optional, tag = "4")]
+    pub user_id: Option<u32>,
+    #[prost(uint32, optional, tag = "5")]
+    pub channel_id: Option<u32>,
+    #[prost(bool, optional, tag = "6")]
+    pub mute: Option<bool>,
+    #[prost(bool, optional, tag = "7")]
+    pub deaf: Option<bool>,
+    #[prost(bool, optional, tag = "8")]
+    pub suppress: Option<bool>,
+    #[prost(bool, optional, tag = "9")]
+    pub self_mute: Option<bool>,
+    #[prost(bool, optional, tag = "10")]
+    pub self_deaf: Option<bool>,
+    #[prost(bytes = "vec", optional, tag = "11")]
+    pub texture: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "12")]
+    pub plugin_context: Option<Vec<u8>>,
+    #[prost(string, optional, tag = "13")]
+    pub plugin_identity: Option<String>,
+    #[prost(string, optional, tag = "14")]
+    pub comment: Option<String>,
+    #[prost(string, optional, tag = "15")]
+    pub hash: Option<String>,
+    #[prost(bytes = "vec", optional, tag = "16")]
+    pub comment_hash: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "17")]
+    pub texture_hash: Option<Vec<u8>>,
+    #[prost(bool, optional, tag = "18")]
+    pub priority_speaker: Option<bool>,
+    #[prost(bool, optional, tag = "19")]
+    pub recording: Option<bool>,
+    #[prost(string, repeated, tag = "20")]
+    pub temporary_access_tokens: Vec<String>,
+    #[prost(uint32, repeated, packed = "false", tag = "21")]
+    pub listening_channel_add: Vec<u32>,
+    #[prost(uint32, repeated, packed = "false", tag = "22")]
+    pub listening_channel_remove: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BanEntry {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub address: Vec<u8>,
+    #[prost(uint32, required, tag = "2")]
+    pub mask: u32,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub hash: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub reason: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub start: Option<String>,
+    #[prost(uint32, optional, tag = "7")]
+    pub duration: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BanList {
+    #[prost(message, repeated, tag = "1")]
+    pub bans: Vec<BanEntry>,
+    #[prost(bool, optional, tag = "2")]
+    pub query: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TextMessage {
+    #[prost(uint32, optional, tag = "1")]
+    pub actor: Option<u32>,
+    #[prost(uint32, repeated, packed = "false", tag = "2")]
+    pub session: Vec<u32>,
+    #[prost(uint32, repeated, packed = "false", tag = "3")]
+    pub channel_id: Vec<u32>,
+    #[prost(uint32, repeated, packed = "false", tag = "4")]
+    pub tree_id: Vec<u32>,
+    #[prost(string, required, tag = "5")]
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum DenyType {
+    Text = 0,
+    Permission = 1,
+    SuperUser = 2,
+    ChannelName = 3,
+    TextTooLong = 4,
+    H9k = 5,
+    TemporaryChannel = 6,
+    MissingCertificate = 7,
+    UserName = 8,
+    ChannelFull = 9,
+    NestingLimit = 10,
+    ChannelCountLimit = 11,
+    ChannelListenerLimit = 12,
+    UserListenerLimit = 13,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PermissionDenied {
+    #[prost(uint32, optional, tag = "1")]
+    pub permission: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub channel_id: Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub session: Option<u32>,
+    #[prost(string, optional, tag = "4")]
+    pub reason: Option<String>,
+    #[prost(enumeration = "DenyType", optional, tag = "5")]
+    pub r#type: Option<i32>,
+    #[prost(string, optional, tag = "6")]
+    pub name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryUsers {
+    #[prost(uint32, repeated, packed = "false", tag = "1")]
+    pub ids: Vec<u32>,
+    #[prost(string, repeated, tag = "2")]
+    pub names: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CryptSetup {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    pub key: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub client_nonce: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "3")]
+    pub server_nonce: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RegisteredUser {
+    #[prost(uint32, required, tag = "1")]
+    pub user_id: u32,
+    #[prost(string, optional, tag = "2")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub last_seen: Option<String>,
+    #[prost(uint32, optional, tag = "4")]
+    pub last_channel: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UserList {
+    #[prost(message, repeated, tag = "1")]
+    pub users: Vec<RegisteredUser>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VoiceTargetEntry {
+    #[prost(uint32, repeated, packed = "false", tag = "1")]
+    pub session: Vec<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub channel_id: Option<u32>,
+    #[prost(string, optional, tag = "3")]
+    pub group: Option<String>,
+    #[prost(bool, optional, tag = "4")]
+    pub links: Option<bool>,
+    #[prost(bool, optional, tag = "5")]
+    pub children: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VoiceTarget {
+    #[prost(uint32, optional, tag = "1")]
+    pub id: Option<u32>,
+    #[prost(message, repeated, tag = "2")]
+    pub targets: Vec<VoiceTargetEntry>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PermissionQuery {
+    #[prost(uint32, optional, tag = "1")]
+    pub channel_id: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub permissions: Option<u32>,
+    #[prost(bool, optional, tag = "3")]
+    pub flush: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CodecVersion {
+    #[prost(int32, required, tag = "1")]
+    pub alpha: i32,
+    #[prost(int32, required, tag = "2")]
+    pub beta: i32,
+    #[prost(bool, required, tag = "3")]
+    pub prefer_alpha: bool,
+    #[prost(bool, optional, tag = "4")]
+    pub opus: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PacketStats {
+    #[prost(uint32, optional, tag = "1")]
+    pub good: Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub late: Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub lost: Option<u32>,
+    #[prost(uint32, optional, tag = "4")]
+    pub resync: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UserStats {
+    #[prost(uint32, optional, tag = "1")]
+    pub session: Option<u32>,
+    #[prost(bool, optional, tag = "2")]
+    pub stats_only: Option<bool>,
+    #[prost(bytes = "vec", repeated, tag = "3")]
+    pub certificates: Vec<Vec<u8>>,
+    #[prost(message, optional, tag = "4")]
+    pub from_client: Option<PacketStats>,
+    #[prost(message, optional, tag = "5")]
+    pub from_server: Option<PacketStats>,
+    #[prost(uint32, optional, tag = "6")]
+    pub udp_packets: Option<u32>,
+    #[prost(uint32, optional, tag = "7")]
+    pub tcp_packets: Option<u32>,
+    #[prost(float, optional, tag = "8")]
+    pub udp_ping_avg: Option<f32>,
+    #[prost(float, optional, tag = "9")]
+    pub udp_ping_var: Option<f32>,
+    #[prost(float, optional, tag = "10")]
+    pub tcp_ping_avg: Option<f32>,
+    #[prost(float, optional, tag = "11")]
+    pub tcp_ping_var: Option<f32>,
+    #[prost(message, optional, tag = "12")]
+    pub version: Option<Version>,
+    #[prost(int32, repeated, packed = "false", tag = "13")]
+    pub celt_versions: Vec<i32>,
+    #[prost(bytes = "vec", optional, tag = "14")]
+    pub address: Option<Vec<u8>>,
+    #[prost(uint32, optional, tag = "15")]
+    pub bandwidth: Option<u32>,
+    #[prost(uint32, optional, tag = "16")]
+    pub onlinesecs: Option<u32>,
+    #[prost(uint32, optional, tag = "17")]
+    pub idlesecs: Option<u32>,
+    #[prost(bool, optional, tag = "18")]
+    pub strong_certificate: Option<bool>,
+    #[prost(bool, optional, tag = "19")]
+    pub opus: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RequestBlob {
+    #[prost(uint32, repeated, packed = "false", tag = "1")]
+    pub session_texture: Vec<u32>,
+    #[prost(uint32, repeated, packed = "false", tag = "2")]
+    pub session_comment: Vec<u32>,
+    #[prost(uint32, repeated, packed = "false", tag = "3")]
+    pub channel_description: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerConfig {
+    #[prost(uint32, optional, tag = "1")]
+    pub max_bandwidth: Option<u32>,
+    #[prost(string, optional, tag = "2")]
+    pub welcome_text: Option<String>,
+    #[prost(bool, optional, tag = "3")]
+    pub allow_html: Option<bool>,
+    #[prost(uint32, optional, tag = "4")]
+    pub message_length: Option<u32>,
+    #[prost(uint32, optional, tag = "5")]
+    pub image_message_length: Option<u32>,
+    #[prost(uint32, optional, tag = "6")]
+    pub max_users: Option<u32>,
+    #[prost(bool, optional, tag = "7")]
+    pub recording_allowed: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SuggestConfig {
+    #[prost(uint32, optional, tag = "1")]
+    pub version_v1: Option<u32>,
+    #[prost(uint64, optional, tag = "4")]
+    pub version_v2: Option<u64>,
+    #[prost(bool, optional, tag = "2")]
+    pub positional: Option<bool>,
+    #[prost(bool, optional, tag = "3")]
+    pub push_to_talk: Option<bool>,
+}
+
+/// Generates the `Message` enum together with its wire type mapping.
+macro_rules! messages {
+    ($($id:literal => $variant:ident,)*) => {
+        /// A decoded control channel message.
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Message {
+            $($variant($variant),)*
+            /// A message type we do not model (ACL, context actions, ...).
+            Unknown { kind: u16, payload: Vec<u8> },
+        }
+
+        impl Message {
+            pub fn kind(&self) -> u16 {
+                match self {
+                    $(Self::$variant(_) => $id,)*
+                    Self::Unknown { kind, .. } => *kind,
+                }
+            }
+
+            fn encode_payload(&self) -> Vec<u8> {
+                match self {
+                    $(Self::$variant(m) => m.encode_to_vec(),)*
+                    Self::Unknown { payload, .. } => payload.clone(),
+                }
+            }
+
+            pub fn decode(kind: u16, payload: &[u8]) -> Result<Self> {
+                Ok(match kind {
+                    $($id => Self::$variant($variant::decode(payload)?),)*
+                    _ => Self::Unknown { kind, payload: payload.to_vec() },
+                })
+            }
+        }
+
+        $(
+            impl From<$variant> for Message {
+                fn from(m: $variant) -> Self {
+                    Self::$variant(m)
+                }
+            }
+        )*
+    };
+}
+
+messages! {
+    0 => Version,
+    1 => UdpTunnel,
+    2 => Authenticate,
+    3 => Ping,
+    4 => Reject,
+    5 => ServerSync,
+    6 => ChannelRemove,
+    7 => ChannelState,
+    8 => UserRemove,
+    9 => UserState,
+    10 => BanList,
+    11 => TextMessage,
+    12 => PermissionDenied,
+    14 => QueryUsers,
+    15 => CryptSetup,
+    18 => UserList,
+    19 => VoiceTarget,
+    20 => PermissionQuery,
+    21 => CodecVersion,
+    22 => UserStats,
+    23 => RequestBlob,
+    24 => ServerConfig,
+    25 => SuggestConfig,
+}
+
+impl Message {
+    /// Encodes the message with its 6-byte `type`/`length` prefix.
+    pub fn to_frame(&self) -> Vec<u8> {
+        let payload = self.encode_payload();
+        let mut frame = Vec::with_capacity(6 + payload.len());
+        frame.extend_from_slice(&self.kind().to_be_bytes());
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+        frame
+    }
+}
+
+/// Reads one of Mumble's variable-length integers, returning the value and
+/// the number of bytes used. Negative forms are not needed for sessions and
+/// are rejected.
+fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let first = *data.first()? as u64;
+    let (len, initial) = match first {
+        b if b & 0x80 == 0x00 => (1, b & 0x7f),
+        b if b & 0xc0 == 0x80 => (2, b & 0x3f),
+        b if b & 0xe0 == 0xc0 => (3, b & 0x1f),
+        b if b & 0xf0 == 0xe0 => (4, b & 0x0f),
+        b if b & 0xfc == 0xf0 => (5, 0),
+        b if b & 0xfc == 0xf4 => (9, 0),
+        _ => return None,
+    };
+    let rest = data.get(1..len)?;
+    Some((rest.iter().fold(initial, |v, &b| (v << 8) | b as u64), len))
+}
+
+/// Session of the user who sent a legacy voice packet, as tunnelled
+/// through `UdpTunnel` by the server. `None` for pings and malformed data.
+pub fn voice_sender(packet: &[u8]) -> Option<u32> {
+    const PING: u8 = 1;
+    let header = *packet.first()?;
+    if header >> 5 == PING {
+        return None;
+    }
+    let (session, _) = read_varint(&packet[1..])?;
+    u32::try_from(session).ok()
+}
+
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Message> {
+    let mut header = [0u8; 6];
+    reader.read_exact(&mut header).await?;
+    let kind = u16::from_be_bytes([header[0], header[1]]);
+    let len = u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(anyhow!("Message of type {} is too large ({} bytes)", kind, len));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Message::decode(kind, &payload)
+}
+
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> Result<()> {
+    writer.write_all(&message.to_frame()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        let sent = Message::from(UserState {
+            session: Some(3),
+            name: Some("alice".to_string()),
+            channel_id: Some(0),
+            self_mute: Some(true),
+            listening_channel_add: vec![1, 2],
+            ..Default::default()
+        });
+        let frame = sent.to_frame();
+        assert_eq!(&frame[..2], &9u16.to_be_bytes());
+
+        let received = read_message(&mut frame.as_slice()).await.unwrap();
+        assert_eq!(received, sent);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_message_is_preserved() {
+        let sent = Message::Unknown {
+            kind: 13,
+            payload: vec![1, 2, 3],
+        };
+        let received = read_message(&mut sent.to_frame().as_slice()).await.unwrap();
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn test_voice_sender() {
+        // Opus (type 4), normal talking, session 5.
+        assert_eq!(voice_sender(&[0x80, 0x05, 0x01]), Some(5));
+        // Two-byte varint: 0x80 | 0x01, 0x2c = 300.
+        assert_eq!(voice_sender(&[0x80, 0x81, 0x2c, 0x00]), Some(300));
+        // Four-byte payload of a 32-bit varint.
+        assert_eq!(voice_sender(&[0x80, 0xf0, 0x00, 0x01, 0x00, 0x00]), Some(65536));
+        assert_eq!(voice_sender(&[0x20, 0x05]), None);
+        assert_eq!(voice_sender(&[0x80, 0xc0]), None);
+        assert_eq!(voice_sender(&[]), None);
+    }
+
+    #[test]
+    fn test_format_version() {
+        let version = Version {
+            version_v1: Some(version_v1()),
+            ..Default::default()
+        };
+        assert_eq!(format_version(&version), "1.5.0");
+        let version = Version {
+            version_v2: Some(version_v2()),
+            ..Default::default()
+        };
+        assert_eq!(format_version(&version), "1.5.0");
+    }
+}
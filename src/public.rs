@@ -1,7 +1,23 @@
+use crate::paths;
+use crate::ping::PingReply;
+use anyhow::{anyhow, Result};
+use log::{info, warn};
 use serde::Deserialize;
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 include!(concat!(env!("OUT_DIR"), "/public_server_list.rs"));
 
+pub const PUBLIC_SERVER_LIST_URL: &str = "https://publist.mumble.info/v1/list";
+
+/// How long a cached list is used before the client tries to fetch a new one.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(6 * 60 * 60);
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const CACHE_FILE_NAME: &str = "public_servers.xml";
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServerInfo {
     #[serde(rename = "@name")]
@@ -10,11 +26,37 @@ pub struct ServerInfo {
     pub host: String,
     #[serde(rename = "@port")]
     pub port: u16,
-    // These fields are not in the XML, but we keep them for compatibility with the UI
+    #[serde(rename = "@country", default)]
+    pub country: String,
+    #[serde(rename = "@country_code", default)]
+    pub country_code: String,
+    #[serde(rename = "@continent_code", default)]
+    pub continent_code: String,
+    #[serde(rename = "@region", default)]
+    pub region: String,
+    /// Whether the server presents a CA-signed certificate.
+    #[serde(rename = "@ca", default)]
+    pub ca: bool,
+    #[serde(rename = "@url", default)]
+    pub url: String,
+    // These fields are not in the XML; they are filled in by `ping`
     #[serde(default)]
     pub users: u32,
     #[serde(default)]
     pub max_users: u32,
+    #[serde(skip)]
+    pub ping: Option<Duration>,
+    #[serde(skip)]
+    pub version: Option<String>,
+}
+
+impl ServerInfo {
+    pub fn apply_ping(&mut self, reply: &PingReply) {
+        self.users = reply.users;
+        self.max_users = reply.max_users;
+        self.ping = Some(reply.latency);
+        self.version = Some(reply.version_string());
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,7 +65,485 @@ struct Servers {
     servers: Vec<ServerInfo>,
 }
 
-pub async fn fetch_servers() -> Result<Vec<ServerInfo>, anyhow::Error> {
-    let servers: Servers = quick_xml::de::from_str(PUBLIC_SERVER_LIST_XML)?;
+/// Where a [`ServerList`] was loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListSource {
+    /// Freshly downloaded from the public list.
+    Network,
+    /// Read from the on-disk cache, younger than the maximum age.
+    Cache { age: Duration },
+    /// Read from the on-disk cache after a refresh failed or was not attempted.
+    StaleCache { age: Duration },
+    /// The list embedded into the binary at build time.
+    Bundled,
+}
+
+impl ListSource {
+    /// Whether a background refresh should be attempted.
+    pub fn is_stale(&self) -> bool {
+        matches!(self, Self::StaleCache { .. } | Self::Bundled)
+    }
+}
+
+impl fmt::Display for ListSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network => write!(f, "live"),
+            Self::Cache { age } => write!(f, "cached {}", format_age(*age)),
+            Self::StaleCache { age } => write!(f, "stale, cached {}", format_age(*age)),
+            Self::Bundled => write!(f, "bundled"),
+        }
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        "just now".to_string()
+    } else if secs < 60 * 60 {
+        format!("{}m ago", secs / 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{}h ago", secs / (60 * 60))
+    } else {
+        format!("{}d ago", secs / (24 * 60 * 60))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerList {
+    pub servers: Vec<ServerInfo>,
+    pub source: ListSource,
+}
+
+/// Fetches the public server list at runtime and keeps a copy on disk.
+#[derive(Debug, Clone)]
+pub struct PublicList {
+    url: String,
+    cache_path: PathBuf,
+    max_age: Duration,
+}
+
+impl Default for PublicList {
+    fn default() -> Self {
+        Self::new(
+            PUBLIC_SERVER_LIST_URL,
+            paths::cache_dir().join(CACHE_FILE_NAME),
+        )
+    }
+}
+
+impl PublicList {
+    pub fn new(url: impl Into<String>, cache_path: impl Into<PathBuf>) -> Self {
+        Self {
+            url: url.into(),
+            cache_path: cache_path.into(),
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn cache_path(&self) -> &Path {
+        &self.cache_path
+    }
+
+    /// Loads the list without touching the network: the cache if it is
+    /// readable, otherwise the bundled list.
+    pub fn load_cached(&self) -> ServerList {
+        match self.read_cache() {
+            Ok((servers, age)) => {
+                let source = if age <= self.max_age {
+                    ListSource::Cache { age }
+                } else {
+                    ListSource::StaleCache { age }
+                };
+                ServerList { servers, source }
+            }
+            Err(e) => {
+                info!("No usable public server cache ({}), using bundled list.", e);
+                bundled_list()
+            }
+        }
+    }
+
+    /// Downloads the list and replaces the on-disk cache.
+    pub async fn refresh(&self) -> Result<ServerList> {
+        let client = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build()?;
+        let resp = client.get(&self.url).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!(
+                "Fetching {} failed with status {}",
+                self.url,
+                resp.status()
+            ));
+        }
+        let xml = resp.text().await?;
+        // Parse before caching so a broken response never replaces a good cache.
+        let servers = parse_servers(&xml)?;
+        if let Err(e) = self.write_cache(&xml) {
+            warn!(
+                "Could not write public server cache {}: {}",
+                self.cache_path.display(),
+                e
+            );
+        }
+        Ok(ServerList {
+            servers,
+            source: ListSource::Network,
+        })
+    }
+
+    /// Uses a fresh cache if there is one, otherwise fetches the list and
+    /// falls back to a stale cache or the bundled list if that fails.
+    pub async fn load(&self) -> ServerList {
+        let cached = self.load_cached();
+        if !cached.source.is_stale() {
+            return cached;
+        }
+        match self.refresh().await {
+            Ok(list) => list,
+            Err(e) => {
+                warn!("Failed to fetch public server list: {}", e);
+                cached
+            }
+        }
+    }
+
+    fn read_cache(&self) -> Result<(Vec<ServerInfo>, Duration)> {
+        let modified = std::fs::metadata(&self.cache_path)?.modified()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        let xml = std::fs::read_to_string(&self.cache_path)?;
+        Ok((parse_servers(&xml)?, age))
+    }
+
+    fn write_cache(&self, xml: &str) -> Result<()> {
+        if let Some(parent) = self.cache_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.cache_path.with_extension("xml.tmp");
+        std::fs::write(&tmp_path, xml)?;
+        std::fs::rename(&tmp_path, &self.cache_path)?;
+        Ok(())
+    }
+}
+
+pub fn parse_servers(xml: &str) -> Result<Vec<ServerInfo>> {
+    let servers: Servers = quick_xml::de::from_str(xml)?;
     Ok(servers.servers)
-}
\ No newline at end of file
+}
+
+/// The list embedded at build time, used when nothing better is available.
+pub fn bundled_list() -> ServerList {
+    let servers = parse_servers(PUBLIC_SERVER_LIST_XML).unwrap_or_else(|e| {
+        warn!("Failed to parse bundled public server list: {}", e);
+        Vec::new()
+    });
+    ServerList {
+        servers,
+        source: ListSource::Bundled,
+    }
+}
+
+/// Column the public server table is sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Country,
+    Ping,
+    Users,
+}
+
+impl SortKey {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Name => Self::Country,
+            Self::Country => Self::Ping,
+            Self::Ping => Self::Users,
+            Self::Users => Self::Name,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Country => "country",
+            Self::Ping => "ping",
+            Self::Users => "users",
+        }
+    }
+
+    /// Compares in the key's natural order: alphabetical, lowest ping
+    /// first, most users first.
+    fn compare(&self, a: &ServerInfo, b: &ServerInfo) -> Ordering {
+        match self {
+            Self::Name => cmp_ignore_case(&a.name, &b.name),
+            Self::Country => cmp_ignore_case(&a.country, &b.country)
+                .then_with(|| cmp_ignore_case(&a.name, &b.name)),
+            Self::Ping => a.ping.cmp(&b.ping),
+            Self::Users => b.users.cmp(&a.users),
+        }
+    }
+}
+
+fn cmp_ignore_case(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+/// Filtering and sorting applied to the public server table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerFilter {
+    /// Two-letter continent code, e.g. `EU`.
+    pub continent: Option<String>,
+    /// Two-letter country code, e.g. `DE`.
+    pub country: Option<String>,
+    pub ca_only: bool,
+    /// Case-insensitive substring of the server name.
+    pub search: String,
+    pub sort: SortKey,
+    pub reverse: bool,
+}
+
+impl ServerFilter {
+    pub fn matches(&self, server: &ServerInfo) -> bool {
+        if self.ca_only && !server.ca {
+            return false;
+        }
+        if let Some(continent) = &self.continent {
+            if !server.continent_code.eq_ignore_ascii_case(continent) {
+                return false;
+            }
+        }
+        if let Some(country) = &self.country {
+            if !server.country_code.eq_ignore_ascii_case(country) {
+                return false;
+            }
+        }
+        self.search.is_empty()
+            || server
+                .name
+                .to_lowercase()
+                .contains(&self.search.to_lowercase())
+    }
+
+    /// Returns the indices of the matching servers in display order.
+    pub fn apply(&self, servers: &[ServerInfo]) -> Vec<usize> {
+        let mut indices: Vec<usize> = servers
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| self.matches(s))
+            .map(|(i, _)| i)
+            .collect();
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (&servers[a], &servers[b]);
+            // Unpinged servers stay at the bottom in either direction.
+            if self.sort == SortKey::Ping && a.ping.is_none() != b.ping.is_none() {
+                return a.ping.is_none().cmp(&b.ping.is_none());
+            }
+            let ordering = self.sort.compare(a, b);
+            if self.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        indices
+    }
+
+    /// Short description of the active filters for the table title.
+    pub fn summary(&self) -> String {
+        let mut parts = vec![format!(
+            "sort: {}{}",
+            self.sort.label(),
+            if self.reverse { " (rev)" } else { "" }
+        )];
+        if let Some(continent) = &self.continent {
+            parts.push(format!("continent: {}", continent));
+        }
+        if let Some(country) = &self.country {
+            parts.push(format!("country: {}", country));
+        }
+        if self.ca_only {
+            parts.push("CA only".to_string());
+        }
+        if !self.search.is_empty() {
+            parts.push(format!("search: {}", self.search));
+        }
+        parts.join(" | ")
+    }
+}
+
+/// Continent codes present in the list, sorted.
+pub fn continents(servers: &[ServerInfo]) -> Vec<String> {
+    let mut codes: Vec<String> = servers
+        .iter()
+        .map(|s| s.continent_code.to_uppercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    codes.sort();
+    codes.dedup();
+    codes
+}
+
+/// Country codes present in the list, optionally limited to one continent.
+pub fn countries(servers: &[ServerInfo], continent: Option<&str>) -> Vec<String> {
+    let mut codes: Vec<String> = servers
+        .iter()
+        .filter(|s| continent.is_none_or(|c| s.continent_code.eq_ignore_ascii_case(c)))
+        .map(|s| s.country_code.to_uppercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    codes.sort();
+    codes.dedup();
+    codes
+}
+
+/// Steps through `None` and then each option in turn.
+pub fn cycle_option(current: &Option<String>, options: &[String]) -> Option<String> {
+    match current {
+        None => options.first().cloned(),
+        Some(current) => options
+            .iter()
+            .position(|o| o == current)
+            .and_then(|i| options.get(i + 1))
+            .cloned(),
+    }
+}
+
+pub async fn fetch_servers() -> Result<Vec<ServerInfo>, anyhow::Error> {
+    Ok(PublicList::default().load().await.servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_list_parses() {
+        let list = bundled_list();
+        assert_eq!(list.source, ListSource::Bundled);
+        assert!(!list.servers.is_empty());
+    }
+
+    #[test]
+    fn test_stale_sources() {
+        assert!(ListSource::Bundled.is_stale());
+        assert!(ListSource::StaleCache {
+            age: Duration::from_secs(1)
+        }
+        .is_stale());
+        assert!(!ListSource::Network.is_stale());
+        assert!(!ListSource::Cache {
+            age: Duration::from_secs(1)
+        }
+        .is_stale());
+    }
+
+    fn server(name: &str, country: &str, country_code: &str, continent: &str, ca: bool) -> ServerInfo {
+        ServerInfo {
+            name: name.to_string(),
+            host: format!("{}.example.org", name.to_lowercase()),
+            port: 64738,
+            country: country.to_string(),
+            country_code: country_code.to_string(),
+            continent_code: continent.to_string(),
+            region: String::new(),
+            ca,
+            url: String::new(),
+            users: 0,
+            max_users: 0,
+            ping: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_all_attributes() {
+        let xml = r#"<servers><server name="A" ca="1" continent_code="EU" country="Ukraine" country_code="UA" ip="a.example.org" port="64739" region="Odeska oblast" url="https://a.example.org" /></servers>"#;
+        let servers = parse_servers(xml).unwrap();
+        let s = &servers[0];
+        assert_eq!(s.port, 64739);
+        assert_eq!(s.country, "Ukraine");
+        assert_eq!(s.country_code, "UA");
+        assert_eq!(s.continent_code, "EU");
+        assert_eq!(s.region, "Odeska oblast");
+        assert_eq!(s.url, "https://a.example.org");
+        assert!(s.ca);
+        assert_eq!(s.ping, None);
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let mut servers = vec![
+            server("Bravo", "Germany", "DE", "EU", true),
+            server("alpha", "Canada", "CA", "NA", false),
+            server("Charlie", "France", "FR", "EU", false),
+        ];
+        servers[0].ping = Some(Duration::from_millis(80));
+        servers[0].users = 2;
+        servers[2].ping = Some(Duration::from_millis(20));
+        servers[2].users = 9;
+
+        let mut filter = ServerFilter::default();
+        assert_eq!(filter.apply(&servers), vec![1, 0, 2]);
+
+        filter.sort = SortKey::Country;
+        assert_eq!(filter.apply(&servers), vec![1, 2, 0]);
+
+        filter.sort = SortKey::Ping;
+        assert_eq!(filter.apply(&servers), vec![2, 0, 1]);
+        filter.reverse = true;
+        assert_eq!(filter.apply(&servers), vec![0, 2, 1]);
+
+        filter.reverse = false;
+        filter.sort = SortKey::Users;
+        assert_eq!(filter.apply(&servers), vec![2, 0, 1]);
+
+        filter.continent = Some("EU".to_string());
+        assert_eq!(filter.apply(&servers), vec![2, 0]);
+        filter.ca_only = true;
+        assert_eq!(filter.apply(&servers), vec![0]);
+
+        let filter = ServerFilter {
+            search: "ARL".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(&servers), vec![2]);
+    }
+
+    #[test]
+    fn test_cycle_option() {
+        let options = vec!["EU".to_string(), "NA".to_string()];
+        let first = cycle_option(&None, &options);
+        assert_eq!(first.as_deref(), Some("EU"));
+        let second = cycle_option(&first, &options);
+        assert_eq!(second.as_deref(), Some("NA"));
+        assert_eq!(cycle_option(&second, &options), None);
+        assert_eq!(cycle_option(&Some("XX".to_string()), &options), None);
+    }
+
+    #[test]
+    fn test_countries_by_continent() {
+        let servers = vec![
+            server("Bravo", "Germany", "DE", "EU", true),
+            server("alpha", "Canada", "CA", "NA", false),
+            server("Charlie", "France", "FR", "EU", false),
+        ];
+        assert_eq!(continents(&servers), vec!["EU", "NA"]);
+        assert_eq!(countries(&servers, Some("EU")), vec!["DE", "FR"]);
+        assert_eq!(countries(&servers, None), vec!["CA", "DE", "FR"]);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(5)), "just now");
+        assert_eq!(format_age(Duration::from_secs(120)), "2m ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3h ago");
+        assert_eq!(format_age(Duration::from_secs(2 * 86400)), "2d ago");
+    }
+}
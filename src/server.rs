@@ -1,48 +1,603 @@
 use crate::config::MetaParams;
-use anyhow::Result;
-use log::{info, warn};
-use std::collections::HashMap;
+use crate::proto::{self, permissions, Message, RejectType};
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncRead;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 use tokio_rustls::TlsAcceptor;
 use tokio_rusqlite::Connection as TokioConnection;
 
+/// How long a new connection may take to authenticate.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_USERNAME_LEN: usize = 128;
+
 #[derive(Clone)]
 pub struct ServerParams {
     pub port: u16,
+    pub password: String,
+    pub welcome_text: String,
+    pub max_users: u32,
+    pub max_bandwidth: u32,
+    pub allow_html: bool,
+    pub max_text_message_length: u32,
+    pub max_image_message_length: u32,
+    pub allow_recording: bool,
+}
+
+impl ServerParams {
+    pub fn from_meta(params: &MetaParams) -> Self {
+        Self {
+            port: params.port,
+            password: params.password.clone(),
+            welcome_text: params.welcome_text.clone(),
+            max_users: params.max_users,
+            max_bandwidth: params.max_bandwidth.max(0) as u32,
+            allow_html: params.allow_html,
+            max_text_message_length: params.max_text_message_length.max(0) as u32,
+            max_image_message_length: params.max_image_message_length.max(0) as u32,
+            allow_recording: params.allow_recording,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub id: u32,
+    pub parent: Option<u32>,
+    pub name: String,
+    pub description: String,
+}
+
+impl Channel {
+    fn root() -> Self {
+        Self {
+            id: 0,
+            parent: None,
+            name: "Root".to_string(),
+            description: String::new(),
+        }
+    }
+
+    fn to_state(&self) -> proto::ChannelState {
+        proto::ChannelState {
+            channel_id: Some(self.id),
+            parent: self.parent,
+            name: Some(self.name.clone()),
+            description: (!self.description.is_empty()).then(|| self.description.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+struct ConnectedUser {
+    name: String,
+    channel_id: u32,
+    self_mute: bool,
+    self_deaf: bool,
+    recording: bool,
+    comment: String,
+    listening: BTreeSet<u32>,
+    tx: mpsc::UnboundedSender<Message>,
+}
+
+impl ConnectedUser {
+    fn to_state(&self, session: u32) -> proto::UserState {
+        proto::UserState {
+            session: Some(session),
+            name: Some(self.name.clone()),
+            channel_id: Some(self.channel_id),
+            self_mute: Some(self.self_mute),
+            self_deaf: Some(self.self_deaf),
+            recording: self.recording.then_some(true),
+            comment: (!self.comment.is_empty()).then(|| self.comment.clone()),
+            listening_channel_add: self.listening.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// State shared by all connections of one virtual server.
+struct ServerState {
+    params: ServerParams,
+    channels: BTreeMap<u32, Channel>,
+    users: BTreeMap<u32, ConnectedUser>,
+    next_session: u32,
+    connections: u64,
+}
+
+impl ServerState {
+    fn broadcast(&self, message: &Message) {
+        for user in self.users.values() {
+            user.tx.send(message.clone()).ok();
+        }
+    }
+
+    fn send_to(&self, session: u32, message: Message) {
+        if let Some(user) = self.users.get(&session) {
+            user.tx.send(message).ok();
+        }
+    }
+
+    /// Sessions in `channel_id` and, if `recursive`, all of its subchannels.
+    fn sessions_in(&self, channel_id: u32, recursive: bool) -> Vec<u32> {
+        let mut channels = BTreeSet::from([channel_id]);
+        if recursive {
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for channel in self.channels.values() {
+                    if channel.parent.is_some_and(|p| channels.contains(&p)) {
+                        changed |= channels.insert(channel.id);
+                    }
+                }
+            }
+        }
+        self.users
+            .iter()
+            .filter(|(_, u)| {
+                channels.contains(&u.channel_id) || u.listening.iter().any(|c| channels.contains(c))
+            })
+            .map(|(&session, _)| session)
+            .collect()
+    }
+
+    fn authenticate(&self, auth: &proto::Authenticate) -> Result<String, (RejectType, String)> {
+        let name = auth.username.clone().unwrap_or_default().trim().to_string();
+        if name.is_empty() || name.len() > MAX_USERNAME_LEN {
+            return Err((RejectType::InvalidUsername, "Invalid username".to_string()));
+        }
+        if !self.params.password.is_empty()
+            && auth.password.as_deref() != Some(self.params.password.as_str())
+        {
+            return Err((
+                RejectType::WrongServerPw,
+                "Wrong server password".to_string(),
+            ));
+        }
+        if self.users.values().any(|u| u.name.eq_ignore_ascii_case(&name)) {
+            return Err((
+                RejectType::UsernameInUse,
+                "Username already in use".to_string(),
+            ));
+        }
+        if self.params.max_users > 0 && self.users.len() as u32 >= self.params.max_users {
+            return Err((RejectType::ServerFull, "Server is full".to_string()));
+        }
+        Ok(name)
+    }
+}
+
+fn permission_denied(deny_type: proto::DenyType, reason: &str) -> Message {
+    proto::PermissionDenied {
+        r#type: Some(deny_type as i32),
+        reason: Some(reason.to_string()),
+        ..Default::default()
+    }
+    .into()
 }
 
 pub struct Server {
     pub params: ServerParams,
+    pub channels: Vec<Channel>,
 }
 
 impl Server {
     pub fn new(params: ServerParams) -> Self {
-        Self { params }
+        Self {
+            params,
+            channels: Vec::new(),
+        }
     }
 
-    pub async fn run(&self, acceptor: TlsAcceptor) -> Result<()> {
-        let addr = format!("0.0.0.0:{}", self.params.port);
-        let listener = TcpListener::bind(&addr).await?;
-        info!("Server listening on {}", addr);
+    /// Starts accepting connections on `listener` in a background task.
+    pub fn spawn(&self, listener: TcpListener, acceptor: TlsAcceptor) -> Result<ServerHandle> {
+        let local_addr = listener.local_addr()?;
+        info!("Server listening on {}", local_addr);
 
-        loop {
-            let (stream, _peer_addr) = listener.accept().await?;
-            let acceptor = acceptor.clone();
-            tokio::spawn(async move {
-                if let Err(err) = handle_connection(stream, acceptor).await {
-                    warn!("Connection error: {:?}", err);
-                }
-            });
+        let mut channels: BTreeMap<u32, Channel> =
+            self.channels.iter().map(|c| (c.id, c.clone())).collect();
+        channels.entry(0).or_insert_with(Channel::root);
+        let state = Arc::new(Mutex::new(ServerState {
+            params: self.params.clone(),
+            channels,
+            users: BTreeMap::new(),
+            next_session: 1,
+            connections: 0,
+        }));
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(serve(listener, acceptor, Arc::clone(&state), shutdown_rx));
+        Ok(ServerHandle {
+            local_addr,
+            started: Instant::now(),
+            state,
+            shutdown_tx,
+            task: Some(task),
+        })
+    }
+}
+
+/// Whether a spawned server is still accepting connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerStatus {
+    Running,
+    Stopped,
+}
+
+/// A snapshot of a running server's counters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerStats {
+    pub users: usize,
+    pub max_users: u32,
+    pub channels: usize,
+    /// Connections accepted since start, including rejected ones.
+    pub connections: u64,
+    pub uptime: Duration,
+}
+
+/// Controls a server started with [`Server::spawn`].
+///
+/// Dropping the handle stops the server as well.
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    started: Instant,
+    state: Arc<Mutex<ServerState>>,
+    shutdown_tx: watch::Sender<bool>,
+    task: Option<JoinHandle<Result<()>>>,
+}
+
+impl ServerHandle {
+    /// The address the server is bound to; useful after binding port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        match &self.task {
+            Some(task) if !task.is_finished() => ServerStatus::Running,
+            _ => ServerStatus::Stopped,
+        }
+    }
+
+    pub fn stats(&self) -> ServerStats {
+        let state = self.state.lock().unwrap();
+        ServerStats {
+            users: state.users.len(),
+            max_users: state.params.max_users,
+            channels: state.channels.len(),
+            connections: state.connections,
+            uptime: self.started.elapsed(),
+        }
+    }
+
+    /// Waits until the server stops on its own, e.g. because accepting failed.
+    pub async fn wait(&mut self) -> Result<()> {
+        match self.task.as_mut() {
+            Some(task) => {
+                let result = task.await;
+                self.task = None;
+                result?
+            }
+            None => Ok(()),
         }
     }
+
+    /// Disconnects all users, stops listening and waits for the server to finish.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.shutdown_tx.send(true).ok();
+        self.wait().await
+    }
 }
 
-async fn handle_connection(stream: TcpStream, acceptor: TlsAcceptor) -> Result<()> {
-    let _tls_stream = acceptor.accept(stream).await?;
-    // TODO: Handle Mumble protocol
+async fn serve(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    state: Arc<Mutex<ServerState>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    loop {
+        let (stream, peer_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.wait_for(|&stop| stop) => break,
+        };
+        state.lock().unwrap().connections += 1;
+        let acceptor = acceptor.clone();
+        let state = Arc::clone(&state);
+        let mut shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                result = handle_connection(stream, acceptor, state) => {
+                    if let Err(err) = result {
+                        warn!("Connection error from {}: {:?}", peer_addr, err);
+                    }
+                }
+                _ = shutdown.wait_for(|&stop| stop) => {}
+            }
+        });
+    }
+
+    // Dropping the senders ends every connection's writer task.
+    let mut state = state.lock().unwrap();
+    info!("Server stopping, disconnecting {} users", state.users.len());
+    state.users.clear();
     Ok(())
 }
 
+async fn handle_connection(
+    stream: TcpStream,
+    acceptor: TlsAcceptor,
+    state: Arc<Mutex<ServerState>>,
+) -> Result<()> {
+    let tls_stream = acceptor.accept(stream).await?;
+    let (mut reader, mut writer) = tokio::io::split(tls_stream);
+
+    let version = proto::Version {
+        version_v1: Some(proto::version_v1()),
+        version_v2: Some(proto::version_v2()),
+        release: Some(format!("mumble-rs {}", env!("CARGO_PKG_VERSION"))),
+        os: Some(std::env::consts::OS.to_string()),
+        os_version: None,
+    };
+    proto::write_message(&mut writer, &version.into()).await?;
+
+    let auth = tokio::time::timeout(HANDSHAKE_TIMEOUT, read_authenticate(&mut reader))
+        .await
+        .map_err(|_| anyhow!("Client did not authenticate in time"))??;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let joined = {
+        let mut state = state.lock().unwrap();
+        state
+            .authenticate(&auth)
+            .map(|name| join(&mut state, name, tx.clone()))
+    };
+    let session = match joined {
+        Ok(session) => session,
+        Err((reject_type, reason)) => {
+            info!("Rejecting {:?}: {}", auth.username, reason);
+            let reject = proto::Reject {
+                r#type: Some(reject_type as i32),
+                reason: Some(reason),
+            };
+            proto::write_message(&mut writer, &reject.into()).await?;
+            return Ok(());
+        }
+    };
+
+    let writer_task = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if proto::write_message(&mut writer, &message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = loop {
+        let message = match proto::read_message(&mut reader).await {
+            Ok(message) => message,
+            Err(e) => break Err(e),
+        };
+        let mut state = state.lock().unwrap();
+        if !state.users.contains_key(&session) {
+            // Kicked while we were waiting for input.
+            break Ok(());
+        }
+        handle_message(&mut state, session, message);
+    };
+
+    {
+        let mut state = state.lock().unwrap();
+        if let Some(user) = state.users.remove(&session) {
+            info!("User {} (session {}) disconnected", user.name, session);
+            state.broadcast(
+                &proto::UserRemove {
+                    session,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+    }
+    drop(tx);
+    writer_task.await.ok();
+    // A closed connection is the normal way for a client to leave.
+    match result {
+        Err(e) if e.downcast_ref::<std::io::Error>().is_some() => Ok(()),
+        other => other,
+    }
+}
+
+/// Registers an authenticated user and queues the initial server state for it.
+fn join(state: &mut ServerState, name: String, tx: mpsc::UnboundedSender<Message>) -> u32 {
+    let session = state.next_session;
+    state.next_session += 1;
+
+    for channel in state.channels.values() {
+        tx.send(channel.to_state().into()).ok();
+    }
+    for (&other, user) in &state.users {
+        tx.send(user.to_state(other).into()).ok();
+    }
+    let user = ConnectedUser {
+        name,
+        channel_id: 0,
+        self_mute: false,
+        self_deaf: false,
+        recording: false,
+        comment: String::new(),
+        listening: BTreeSet::new(),
+        tx: tx.clone(),
+    };
+    info!("User {} authenticated as session {}", user.name, session);
+    let user_state = user.to_state(session);
+    state.users.insert(session, user);
+    state.broadcast(&user_state.into());
+
+    let params = &state.params;
+    tx.send(
+        proto::ServerSync {
+            session: Some(session),
+            max_bandwidth: Some(params.max_bandwidth),
+            welcome_text: Some(params.welcome_text.clone()),
+            permissions: Some(permissions::DEFAULT as u64),
+        }
+        .into(),
+    )
+    .ok();
+    tx.send(
+        proto::ServerConfig {
+            max_bandwidth: Some(params.max_bandwidth),
+            welcome_text: None,
+            allow_html: Some(params.allow_html),
+            message_length: Some(params.max_text_message_length),
+            image_message_length: Some(params.max_image_message_length),
+            max_users: Some(params.max_users),
+            recording_allowed: Some(params.allow_recording),
+        }
+        .into(),
+    )
+    .ok();
+    session
+}
+
+async fn read_authenticate<R: AsyncRead + Unpin>(reader: &mut R) -> Result<proto::Authenticate> {
+    loop {
+        match proto::read_message(reader).await? {
+            Message::Authenticate(auth) => return Ok(auth),
+            Message::Version(_) | Message::Ping(_) => {}
+            other => debug!("Ignoring message type {} before authentication", other.kind()),
+        }
+    }
+}
+
+fn handle_message(state: &mut ServerState, session: u32, message: Message) {
+    match message {
+        Message::Ping(ping) => {
+            state.send_to(
+                session,
+                proto::Ping {
+                    timestamp: ping.timestamp,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        Message::UserState(user_state) => handle_user_state(state, session, user_state),
+        Message::TextMessage(text) => handle_text_message(state, session, text),
+        Message::UserRemove(_) => {
+            state.send_to(
+                session,
+                permission_denied(proto::DenyType::Permission, "Permission denied"),
+            );
+        }
+        other => debug!("Ignoring message type {} from session {}", other.kind(), session),
+    }
+}
+
+fn handle_user_state(state: &mut ServerState, session: u32, request: proto::UserState) {
+    let target = request.session.unwrap_or(session);
+    if target != session {
+        // There are no ACLs yet, so nobody may change other users.
+        state.send_to(
+            session,
+            permission_denied(proto::DenyType::Permission, "Permission denied"),
+        );
+        return;
+    }
+    if let Some(channel_id) = request.channel_id {
+        if !state.channels.contains_key(&channel_id) {
+            return;
+        }
+    }
+    let allow_recording = state.params.allow_recording;
+    let known_channels: BTreeSet<u32> = state.channels.keys().copied().collect();
+    let Some(user) = state.users.get_mut(&session) else {
+        return;
+    };
+
+    let mut update = proto::UserState {
+        session: Some(session),
+        actor: Some(session),
+        ..Default::default()
+    };
+    if let Some(channel_id) = request.channel_id {
+        user.channel_id = channel_id;
+        update.channel_id = Some(channel_id);
+    }
+    if let Some(self_deaf) = request.self_deaf {
+        user.self_deaf = self_deaf;
+        if self_deaf {
+            user.self_mute = true;
+        }
+    }
+    if let Some(self_mute) = request.self_mute {
+        user.self_mute = self_mute;
+        if !self_mute {
+            user.self_deaf = false;
+        }
+    }
+    if request.self_mute.is_some() || request.self_deaf.is_some() {
+        update.self_mute = Some(user.self_mute);
+        update.self_deaf = Some(user.self_deaf);
+    }
+    if let Some(recording) = request.recording {
+        if allow_recording {
+            user.recording = recording;
+            update.recording = Some(recording);
+        }
+    }
+    if let Some(comment) = request.comment {
+        user.comment = comment.clone();
+        update.comment = Some(comment);
+    }
+    for channel_id in request.listening_channel_add {
+        if known_channels.contains(&channel_id) && user.listening.insert(channel_id) {
+            update.listening_channel_add.push(channel_id);
+        }
+    }
+    for channel_id in request.listening_channel_remove {
+        if user.listening.remove(&channel_id) {
+            update.listening_channel_remove.push(channel_id);
+        }
+    }
+    state.broadcast(&update.into());
+}
+
+fn handle_text_message(state: &mut ServerState, session: u32, mut text: proto::TextMessage) {
+    let max_len = state.params.max_text_message_length as usize;
+    if max_len > 0 && text.message.len() > max_len {
+        state.send_to(
+            session,
+            permission_denied(proto::DenyType::TextTooLong, "Message too long"),
+        );
+        return;
+    }
+    text.actor = Some(session);
+    let mut recipients: BTreeSet<u32> = text
+        .session
+        .iter()
+        .copied()
+        .filter(|s| state.users.contains_key(s))
+        .collect();
+    for &channel_id in &text.channel_id {
+        recipients.extend(state.sessions_in(channel_id, false));
+    }
+    for &channel_id in &text.tree_id {
+        recipients.extend(state.sessions_in(channel_id, true));
+    }
+    recipients.remove(&session);
+    let message: Message = text.into();
+    for recipient in recipients {
+        state.send_to(recipient, message.clone());
+    }
+}
+
 pub struct Meta {
     pub params: MetaParams,
     pub db_connection: TokioConnection,
@@ -95,23 +650,23 @@ impl Meta {
             return Ok(());
         }
 
-        let params = ServerParams {
-            port: self.params.port,
-            // ... other params ...
-        };
-        let s = Server::new(params);
+        let params = ServerParams::from_meta(&self.params);
+        let mut s = Server::new(params);
+        s.channels = self.db_connection.call(|conn| {
+            let mut stmt = conn.prepare("SELECT id, name, parent_id FROM channels ORDER BY id")?;
+            let channels = stmt
+                .query_map([], |row| {
+                    Ok(Channel {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        parent: row.get(2)?,
+                        description: String::new(),
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(channels)
+        }).await?;
         self.servers.insert(srvnum, s);
         Ok(())
     }
-
-    pub async fn start_server(&self, acceptor: TlsAcceptor) -> Result<()> {
-        // For now, we'll just run the first server.
-        // A real implementation would manage multiple servers.
-        if let Some(server) = self.servers.values().next() {
-            server.run(acceptor).await?;
-        } else {
-            warn!("No servers booted, nothing to start.");
-        }
-        Ok(())
-    }
-}
\ No newline at end of file
+}
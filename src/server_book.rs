@@ -0,0 +1,192 @@
+use crate::paths;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BOOK_FILE: &str = "servers.json";
+/// How many recently used servers are remembered.
+const RECENT_LIMIT: usize = 10;
+
+/// A bookmarked server with the details to log in with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Favourite {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// Empty to use the default user name.
+    pub username: String,
+    pub password: String,
+    /// Identity to present; `None` uses the one selected for the server.
+    pub identity: Option<String>,
+    pub tokens: Vec<String>,
+}
+
+/// A server we connected to, most recent first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecentServer {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// Seconds since the Unix epoch.
+    pub last_used: u64,
+}
+
+/// The servers the user keeps coming back to, kept in the config directory.
+///
+/// These are lists of records, which an INI file can't express, and may hold
+/// passwords, so they live in `servers.json` readable only by us rather than
+/// with the preferences in `client.ini` ([`crate::ui::settings::ClientSettings`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerBook {
+    pub favourites: Vec<Favourite>,
+    pub recent: Vec<RecentServer>,
+}
+
+impl ServerBook {
+    pub fn default_path() -> PathBuf {
+        paths::config_dir().join(BOOK_FILE)
+    }
+
+    /// Reads the server list, falling back to defaults if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        paths::read_json_or_default(path, "server list")
+    }
+
+    /// Writes the server list readable only by us, as favourites may hold
+    /// passwords.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        paths::write_private(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn favourite(&self, host: &str, port: u16) -> Option<&Favourite> {
+        self.favourites
+            .iter()
+            .find(|f| f.host.eq_ignore_ascii_case(host) && f.port == port)
+    }
+
+    /// Stores `favourite` at `index`, or else in place of the favourite for
+    /// the same server, or else at the end.
+    pub fn save_favourite(&mut self, index: Option<usize>, favourite: Favourite) {
+        let existing = index.filter(|&i| i < self.favourites.len()).or_else(|| {
+            self.favourites.iter().position(|f| {
+                f.host.eq_ignore_ascii_case(&favourite.host) && f.port == favourite.port
+            })
+        });
+        match existing {
+            Some(i) => self.favourites[i] = favourite,
+            None => self.favourites.push(favourite),
+        }
+    }
+
+    pub fn remove_favourite(&mut self, index: usize) -> Option<Favourite> {
+        (index < self.favourites.len()).then(|| self.favourites.remove(index))
+    }
+
+    /// Records a connection attempt, moving the server to the front of the
+    /// recent list.
+    pub fn remember(&mut self, host: &str, port: u16, username: &str) {
+        self.recent
+            .retain(|r| !(r.host.eq_ignore_ascii_case(host) && r.port == port));
+        let last_used = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.recent.insert(
+            0,
+            RecentServer {
+                host: host.to_string(),
+                port,
+                username: username.to_string(),
+                last_used,
+            },
+        );
+        self.recent.truncate(RECENT_LIMIT);
+    }
+
+    /// The user name last used on a server, or the one its favourite sets.
+    pub fn username_for(&self, host: &str, port: u16) -> Option<&str> {
+        self.recent
+            .iter()
+            .find(|r| r.host.eq_ignore_ascii_case(host) && r.port == port)
+            .map(|r| r.username.as_str())
+            .or_else(|| self.favourite(host, port).map(|f| f.username.as_str()))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Recent servers that aren't also favourites, as listed after them.
+    pub fn recent_only(&self) -> impl Iterator<Item = &RecentServer> {
+        self.recent
+            .iter()
+            .filter(|r| self.favourite(&r.host, r.port).is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn favourite(name: &str, host: &str) -> Favourite {
+        Favourite {
+            name: name.to_string(),
+            host: host.to_string(),
+            port: 64738,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_favourites_and_recent() {
+        let mut book = ServerBook::default();
+        book.save_favourite(None, favourite("Home", "home.example"));
+        book.save_favourite(None, favourite("Work", "work.example"));
+        book.save_favourite(None, favourite("Home again", "HOME.example"));
+        assert_eq!(book.favourites.len(), 2);
+        assert_eq!(book.favourites[0].name, "Home again");
+        book.save_favourite(Some(1), favourite("Office", "office.example"));
+        assert_eq!(book.favourites[1].host, "office.example");
+
+        book.remember("home.example", 64738, "alice");
+        book.remember("other.example", 64738, "bob");
+        book.remember("home.example", 64738, "carol");
+        assert_eq!(book.recent.len(), 2);
+        assert_eq!(book.recent[0].username, "carol");
+        assert_eq!(book.username_for("home.example", 64738), Some("carol"));
+        assert_eq!(book.username_for("office.example", 64738), None);
+        let recent: Vec<_> = book.recent_only().map(|r| r.host.as_str()).collect();
+        assert_eq!(recent, ["other.example"]);
+
+        for i in 0..20 {
+            book.remember(&format!("host{}", i), 1, "x");
+        }
+        assert_eq!(book.recent.len(), RECENT_LIMIT);
+        assert_eq!(book.recent[0].host, "host19");
+
+        assert!(book.remove_favourite(5).is_none());
+        assert_eq!(book.remove_favourite(0).unwrap().name, "Home again");
+    }
+
+    #[test]
+    fn test_book_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(BOOK_FILE);
+        assert_eq!(ServerBook::load_from(&path).unwrap(), ServerBook::default());
+
+        let mut book = ServerBook::default();
+        book.save_favourite(
+            None,
+            Favourite {
+                password: "secret".to_string(),
+                identity: Some("work".to_string()),
+                tokens: vec!["alpha".to_string()],
+                ..favourite("Home", "home.example")
+            },
+        );
+        book.remember("home.example", 64738, "alice");
+        book.save_to(&path).unwrap();
+        assert_eq!(ServerBook::load_from(&path).unwrap(), book);
+    }
+}
@@ -0,0 +1,442 @@
+use crate::connection::{ServerModel, UserInfo};
+use crate::ui::settings::Theme;
+use crossterm::event::KeyCode;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+};
+use std::collections::BTreeSet;
+
+/// A row of the tree, identified independently of its position so the
+/// selection survives users joining and leaving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeNode {
+    Channel(u32),
+    User(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeItem {
+    pub node: TreeNode,
+    pub depth: usize,
+}
+
+/// What the tree wants the app to do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TreeAction {
+    /// The key only changed the tree itself.
+    Handled,
+    JoinChannel(u32),
+    MoveUser { session: u32, channel_id: u32 },
+    /// Chat with the selected channel or user.
+    OpenChat(TreeNode),
+}
+
+/// Selection, collapsed channels and popups of the channel tree.
+#[derive(Debug, Default)]
+pub struct ChannelTreeState {
+    collapsed: BTreeSet<u32>,
+    selected: Option<TreeNode>,
+    /// User picked with `m`, moved into the next channel chosen with Enter.
+    moving: Option<u32>,
+    /// User whose info popup is open.
+    info: Option<u32>,
+}
+
+/// Channels in display order with their users, skipping collapsed subtrees.
+pub fn flatten(model: &ServerModel, collapsed: &BTreeSet<u32>) -> Vec<TreeItem> {
+    fn visit(
+        model: &ServerModel,
+        collapsed: &BTreeSet<u32>,
+        id: u32,
+        depth: usize,
+        items: &mut Vec<TreeItem>,
+    ) {
+        items.push(TreeItem {
+            node: TreeNode::Channel(id),
+            depth,
+        });
+        if collapsed.contains(&id) {
+            return;
+        }
+        for user in model.channel_users(id) {
+            items.push(TreeItem {
+                node: TreeNode::User(user.session),
+                depth: depth + 1,
+            });
+        }
+        for child in model.children(id) {
+            visit(model, collapsed, child.id, depth + 1, items);
+        }
+    }
+
+    let mut items = Vec::new();
+    let roots = model
+        .channels
+        .values()
+        .filter(|c| c.parent.is_none_or(|p| p == c.id || !model.channels.contains_key(&p)));
+    for root in roots {
+        visit(model, collapsed, root.id, 0, &mut items);
+    }
+    items
+}
+
+impl ChannelTreeState {
+    /// Index of the selected row, falling back to the first one.
+    fn selected_index(&self, items: &[TreeItem]) -> usize {
+        self.selected
+            .and_then(|node| items.iter().position(|i| i.node == node))
+            .unwrap_or(0)
+    }
+
+    pub fn info_user(&self) -> Option<u32> {
+        self.info
+    }
+
+    pub fn moving_user(&self) -> Option<u32> {
+        self.moving
+    }
+
+    /// Selects the row clicked at `row` of a tree `height` rows tall (borders
+    /// excluded). Returns whether there was a row there.
+    pub fn click(&mut self, model: &ServerModel, row: usize, height: usize) -> bool {
+        let items = flatten(model, &self.collapsed);
+        // The list scrolls just far enough to show the selection.
+        let offset = self.selected_index(&items).saturating_sub(height.saturating_sub(1));
+        match items.get(offset + row) {
+            Some(item) => {
+                self.selected = Some(item.node);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Handles the tree keys. Returns `None` if the key isn't for the tree.
+    pub fn handle_key(&mut self, code: KeyCode, model: &ServerModel) -> Option<TreeAction> {
+        if self.info.is_some() {
+            // The info popup swallows everything until it is closed.
+            if matches!(code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('i')) {
+                self.info = None;
+            }
+            return Some(TreeAction::Handled);
+        }
+
+        let items = flatten(model, &self.collapsed);
+        if items.is_empty() {
+            return None;
+        }
+        let index = self.selected_index(&items);
+        let current = items[index];
+        match code {
+            KeyCode::Up => self.selected = Some(items[index.saturating_sub(1)].node),
+            KeyCode::Down => self.selected = Some(items[(index + 1).min(items.len() - 1)].node),
+            KeyCode::Home => self.selected = Some(items[0].node),
+            KeyCode::End => self.selected = Some(items[items.len() - 1].node),
+            KeyCode::Left => match current.node {
+                TreeNode::Channel(id) if !self.collapsed.contains(&id) && has_content(model, id) => {
+                    self.collapsed.insert(id);
+                }
+                _ => {
+                    // Jump to the enclosing channel.
+                    if let Some(parent) = items[..index].iter().rev().find(|i| i.depth < current.depth) {
+                        self.selected = Some(parent.node);
+                    }
+                }
+            },
+            KeyCode::Right => {
+                if let TreeNode::Channel(id) = current.node {
+                    self.collapsed.remove(&id);
+                }
+            }
+            KeyCode::Char(' ') => {
+                if let TreeNode::Channel(id) = current.node {
+                    if !self.collapsed.remove(&id) {
+                        self.collapsed.insert(id);
+                    }
+                }
+            }
+            KeyCode::Enter => {
+                self.selected = Some(current.node);
+                return Some(match current.node {
+                    TreeNode::Channel(channel_id) => match self.moving.take() {
+                        Some(session) => TreeAction::MoveUser {
+                            session,
+                            channel_id,
+                        },
+                        None => TreeAction::JoinChannel(channel_id),
+                    },
+                    TreeNode::User(session) => {
+                        self.info = Some(session);
+                        TreeAction::Handled
+                    }
+                });
+            }
+            KeyCode::Char('c') => {
+                self.selected = Some(current.node);
+                return Some(TreeAction::OpenChat(current.node));
+            }
+            KeyCode::Char('i') => match current.node {
+                TreeNode::User(session) => self.info = Some(session),
+                TreeNode::Channel(_) => return None,
+            },
+            KeyCode::Char('m') => match current.node {
+                TreeNode::User(session) if self.moving != Some(session) => {
+                    self.moving = Some(session);
+                }
+                TreeNode::User(_) => self.moving = None,
+                TreeNode::Channel(_) => return None,
+            },
+            KeyCode::Esc if self.moving.is_some() => self.moving = None,
+            _ => return None,
+        }
+        Some(TreeAction::Handled)
+    }
+}
+
+fn has_content(model: &ServerModel, id: u32) -> bool {
+    !model.channel_users(id).is_empty() || !model.children(id).is_empty()
+}
+
+/// Status markers shown after a user's name.
+fn user_flags(user: &UserInfo, theme: &Theme) -> Vec<Span<'static>> {
+    let mut flags = Vec::new();
+    let mut flag = |on: bool, text: &'static str, color: Color| {
+        if on {
+            flags.push(Span::raw(" "));
+            flags.push(Span::styled(text, theme.fg(color)));
+        }
+    };
+    flag(user.mute, "[muted]", theme.bad);
+    flag(user.deaf, "[deafened]", theme.bad);
+    flag(user.suppress, "[suppressed]", theme.bad);
+    flag(user.self_mute && !user.self_deaf, "[self-muted]", theme.busy);
+    flag(user.self_deaf, "[self-deafened]", theme.busy);
+    flag(user.recording, "[REC]", theme.bad);
+    flag(user.priority_speaker, "[priority]", theme.accent);
+    flags
+}
+
+fn render_item(
+    model: &ServerModel,
+    state: &ChannelTreeState,
+    item: &TreeItem,
+    theme: &Theme,
+) -> ListItem<'static> {
+    let indent = "  ".repeat(item.depth);
+    let own_channel = model.own_user().map(|u| u.channel_id);
+    let line = match item.node {
+        TreeNode::Channel(id) => {
+            let Some(channel) = model.channels.get(&id) else {
+                return ListItem::new("");
+            };
+            let marker = if !has_content(model, id) {
+                "  "
+            } else if state.collapsed.contains(&id) {
+                "▸ "
+            } else {
+                "▾ "
+            };
+            let mut spans = vec![Span::raw(format!("{}{}", indent, marker))];
+            let name_style = if own_channel == Some(id) {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            spans.push(Span::styled(channel.name.clone(), name_style));
+            if state.collapsed.contains(&id) {
+                let count = model.channel_users(id).len();
+                if count > 0 {
+                    spans.push(Span::styled(format!(" ({})", count), theme.fg(theme.muted)));
+                }
+            }
+            Line::from(spans)
+        }
+        TreeNode::User(session) => {
+            let Some(user) = model.users.get(&session) else {
+                return ListItem::new("");
+            };
+            let talking = if user.is_talking() {
+                Span::styled("● ", theme.fg(theme.good))
+            } else {
+                Span::styled("○ ", theme.fg(theme.muted))
+            };
+            let mut name_style = Style::default();
+            if model.session == Some(session) {
+                name_style = name_style.add_modifier(Modifier::BOLD);
+            }
+            if state.moving == Some(session) {
+                name_style = name_style.add_modifier(Modifier::UNDERLINED);
+            }
+            let mut spans = vec![
+                Span::raw(indent),
+                talking,
+                Span::styled(user.name.clone(), name_style),
+            ];
+            spans.extend(user_flags(user, theme));
+            Line::from(spans)
+        }
+    };
+    ListItem::new(line)
+}
+
+/// Renders the channel tree of `model` into `area`.
+pub fn render(
+    frame: &mut Frame,
+    area: Rect,
+    model: &ServerModel,
+    state: &ChannelTreeState,
+    theme: &Theme,
+    has_focus: bool,
+) {
+    let items = flatten(model, &state.collapsed);
+    let rows: Vec<ListItem> = items
+        .iter()
+        .map(|item| render_item(model, state, item, theme))
+        .collect();
+
+    let title = match state.moving.and_then(|s| model.users.get(&s)) {
+        Some(user) => format!("Channels - moving {}: pick a channel, Esc cancels", user.name),
+        None => "Channels".to_string(),
+    };
+    let list = List::new(rows)
+        .block(
+            Block::default()
+                .title(title)
+                .title_bottom(" Enter join/info  Space collapse  [m]ove  [i]nfo  [c]hat ")
+                .borders(Borders::ALL)
+                .border_style(theme.border(has_focus))
+                .border_type(if has_focus {
+                    ratatui::widgets::BorderType::Double
+                } else {
+                    ratatui::widgets::BorderType::Plain
+                }),
+        )
+        .highlight_style(theme.selected());
+
+    let mut list_state = ListState::default();
+    if !items.is_empty() {
+        list_state.select(Some(state.selected_index(&items)));
+    }
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+/// Renders the info popup of the user picked with Enter or `i`, if any.
+pub fn render_user_info(frame: &mut Frame, model: &ServerModel, state: &ChannelTreeState, theme: &Theme) {
+    let Some(user) = state.info.and_then(|s| model.users.get(&s)) else {
+        return;
+    };
+    let channel = model
+        .channels
+        .get(&user.channel_id)
+        .map(|c| c.name.clone())
+        .unwrap_or_else(|| user.channel_id.to_string());
+    let mut lines = vec![
+        Line::from(vec!["Session: ".bold(), user.session.to_string().into()]),
+        Line::from(vec![
+            "Registered: ".bold(),
+            user.user_id
+                .map(|id| format!("yes (id {})", id))
+                .unwrap_or_else(|| "no".to_string())
+                .into(),
+        ]),
+        Line::from(vec!["Channel: ".bold(), channel.into()]),
+        Line::from(vec!["Certificate: ".bold(), user.hash.clone().into()]),
+    ];
+    let flags = user_flags(user, theme);
+    if !flags.is_empty() {
+        let mut status = vec!["Status:".bold()];
+        status.extend(flags);
+        lines.push(Line::from(status));
+    }
+    if !user.comment.is_empty() {
+        lines.push(Line::from(""));
+        lines.extend(user.comment.lines().map(|l| Line::from(l.to_string())));
+    }
+
+    let area = super::centered_rect(frame.area(), 70, lines.len() as u16 + 2);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .title(user.name.clone())
+                    .title_bottom(" Esc close ")
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Double)
+                    .border_style(theme.border(true)),
+            ),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> ServerModel {
+        ServerModel::with(
+            &[(0, 0, "Root"), (1, 0, "Lobby"), (2, 0, "Games")],
+            &[(1, "alice", 1), (2, "bob", 0)],
+        )
+    }
+
+    #[test]
+    fn test_flatten_and_collapse() {
+        let model = model();
+        let nodes: Vec<TreeNode> = flatten(&model, &BTreeSet::new()).iter().map(|i| i.node).collect();
+        assert_eq!(
+            nodes,
+            [
+                TreeNode::Channel(0),
+                TreeNode::User(2),
+                TreeNode::Channel(2),
+                TreeNode::Channel(1),
+                TreeNode::User(1),
+            ]
+        );
+
+        let collapsed = BTreeSet::from([1]);
+        assert_eq!(flatten(&model, &collapsed).len(), 4);
+    }
+
+    #[test]
+    fn test_join_and_move() {
+        let model = model();
+        let mut state = ChannelTreeState::default();
+        state.handle_key(KeyCode::Down, &model);
+        assert_eq!(state.handle_key(KeyCode::Char('m'), &model), Some(TreeAction::Handled));
+        assert_eq!(state.moving_user(), Some(2));
+        state.handle_key(KeyCode::End, &model);
+        state.handle_key(KeyCode::Up, &model);
+        assert_eq!(
+            state.handle_key(KeyCode::Enter, &model),
+            Some(TreeAction::MoveUser {
+                session: 2,
+                channel_id: 1
+            })
+        );
+        assert_eq!(state.moving_user(), None);
+        assert_eq!(state.handle_key(KeyCode::Enter, &model), Some(TreeAction::JoinChannel(1)));
+
+        state.handle_key(KeyCode::Down, &model);
+        state.handle_key(KeyCode::Enter, &model);
+        assert_eq!(state.info_user(), Some(1));
+        state.handle_key(KeyCode::Esc, &model);
+        assert_eq!(state.info_user(), None);
+    }
+
+    #[test]
+    fn test_click() {
+        let model = model();
+        let mut state = ChannelTreeState::default();
+        assert!(state.click(&model, 2, 3));
+        assert_eq!(state.selected, Some(TreeNode::Channel(2)));
+        state.handle_key(KeyCode::End, &model);
+        // Three rows tall, the view ends at the selected last row.
+        assert!(state.click(&model, 0, 3));
+        assert_eq!(state.selected, Some(TreeNode::Channel(2)));
+        assert!(!state.click(&model, 5, 10));
+    }
+}
@@ -0,0 +1,526 @@
+use crate::connection::ServerModel;
+use crate::html;
+use crate::log_buffer;
+use crate::proto;
+use crate::ui::commands;
+use crate::ui::input::InputLine;
+use crate::ui::settings::Theme;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Paragraph},
+};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// How many messages a conversation keeps.
+const MESSAGE_LIMIT: usize = 500;
+
+/// A chat with a channel or, privately, with one user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConversationKey {
+    Channel(u32),
+    /// Keyed by the other user's session.
+    Private(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub timestamp: SystemTime,
+    pub sender: String,
+    /// Where the message went when that differs from the conversation,
+    /// e.g. to a whole channel tree.
+    pub target: Option<String>,
+    pub text: String,
+    pub own: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Conversation {
+    pub messages: Vec<ChatMessage>,
+    /// Messages hidden below the view; 0 follows new ones.
+    scroll: usize,
+    pub unread: usize,
+}
+
+impl Conversation {
+    fn push(&mut self, message: ChatMessage, seen: bool) {
+        self.messages.push(message);
+        if self.scroll > 0 {
+            // Keep the scrolled-back view where it is.
+            self.scroll += 1;
+        }
+        if !seen {
+            self.unread += 1;
+        }
+        if self.messages.len() > MESSAGE_LIMIT {
+            self.messages.remove(0);
+            self.scroll = self.scroll.min(MESSAGE_LIMIT - 1);
+            self.unread = self.unread.min(MESSAGE_LIMIT);
+        }
+    }
+}
+
+/// Conversations, their scrollback and the message input.
+#[derive(Debug, Default)]
+pub struct ChatState {
+    conversations: BTreeMap<ConversationKey, Conversation>,
+    /// `None` follows the channel we are in.
+    active: Option<ConversationKey>,
+    /// Send channel messages to the channel and all its subchannels.
+    pub to_tree: bool,
+    pub input: InputLine,
+    /// Tab completions of the input line and the one last shown.
+    completions: Vec<String>,
+    completion: usize,
+}
+
+fn clock(timestamp: SystemTime) -> String {
+    let t = log_buffer::local_time(timestamp);
+    format!("{:02}:{:02}", t.hour(), t.minute())
+}
+
+fn user_name(model: &ServerModel, session: u32) -> String {
+    model
+        .users
+        .get(&session)
+        .map(|u| u.name.clone())
+        .unwrap_or_else(|| format!("session {}", session))
+}
+
+fn channel_name(model: &ServerModel, id: u32) -> String {
+    model
+        .channels
+        .get(&id)
+        .map(|c| c.name.clone())
+        .unwrap_or_else(|| format!("channel {}", id))
+}
+
+/// How a conversation is named in titles.
+pub fn conversation_name(model: &ServerModel, key: ConversationKey) -> String {
+    match key {
+        ConversationKey::Channel(id) => format!("#{}", channel_name(model, id)),
+        ConversationKey::Private(session) => format!("@{}", user_name(model, session)),
+    }
+}
+
+impl ChatState {
+    /// The conversation shown, resolving "our channel" against `model`.
+    pub fn active_key(&self, model: &ServerModel) -> Option<ConversationKey> {
+        self.active
+            .or_else(|| model.own_user().map(|u| ConversationKey::Channel(u.channel_id)))
+    }
+
+    pub fn conversation(&self, key: ConversationKey) -> Option<&Conversation> {
+        self.conversations.get(&key)
+    }
+
+    /// Shows `key`. Opening our own channel goes back to following it.
+    pub fn open(&mut self, model: &ServerModel, key: ConversationKey) {
+        let own_channel = model.own_user().map(|u| ConversationKey::Channel(u.channel_id));
+        self.active = (Some(key) != own_channel).then_some(key);
+        self.conversations.entry(key).or_default().unread = 0;
+    }
+
+    /// Switches to the next (or previous) conversation.
+    pub fn cycle(&mut self, model: &ServerModel, forward: bool) {
+        let mut keys: Vec<ConversationKey> = self.conversations.keys().copied().collect();
+        let current = self.active_key(model);
+        if let Some(current) = current {
+            if !keys.contains(&current) {
+                keys.push(current);
+                keys.sort();
+            }
+        }
+        if keys.is_empty() {
+            return;
+        }
+        let index = current
+            .and_then(|c| keys.iter().position(|&k| k == c))
+            .unwrap_or(0);
+        let next = if forward {
+            (index + 1) % keys.len()
+        } else {
+            (index + keys.len() - 1) % keys.len()
+        };
+        self.open(model, keys[next]);
+    }
+
+    /// Files an incoming message under its conversation.
+    pub fn receive(&mut self, model: &ServerModel, text: &proto::TextMessage) {
+        let sender = text
+            .actor
+            .map(|s| user_name(model, s))
+            .unwrap_or_else(|| "Server".to_string());
+        let (key, target) = if !text.session.is_empty() {
+            (ConversationKey::Private(text.actor.unwrap_or_default()), None)
+        } else if let Some(&id) = text.channel_id.first() {
+            (ConversationKey::Channel(id), None)
+        } else if let Some(&id) = text.tree_id.first() {
+            (
+                ConversationKey::Channel(id),
+                Some(format!("{} and subchannels", channel_name(model, id))),
+            )
+        } else {
+            let own = model.own_user().map_or(0, |u| u.channel_id);
+            (ConversationKey::Channel(own), None)
+        };
+        let seen = self.active_key(model) == Some(key);
+        self.conversations.entry(key).or_default().push(
+            ChatMessage {
+                timestamp: SystemTime::now(),
+                sender,
+                target,
+                text: html::to_plain_text(&text.message),
+                own: false,
+            },
+            seen,
+        );
+    }
+
+    /// Builds the message for `line` to the active conversation and records
+    /// it as sent.
+    pub fn send(&mut self, model: &ServerModel, line: &str) -> Option<proto::TextMessage> {
+        let key = self.active_key(model)?;
+        Some(self.send_to(model, key, line))
+    }
+
+    /// Builds the message for `line` to `key` and records it as sent.
+    pub fn send_to(
+        &mut self,
+        model: &ServerModel,
+        key: ConversationKey,
+        line: &str,
+    ) -> proto::TextMessage {
+        let mut message = proto::TextMessage {
+            message: html::escape(line),
+            ..Default::default()
+        };
+        let mut target = None;
+        match key {
+            ConversationKey::Channel(id) if self.to_tree => {
+                message.tree_id.push(id);
+                target = Some(format!("{} and subchannels", channel_name(model, id)));
+            }
+            ConversationKey::Channel(id) => message.channel_id.push(id),
+            ConversationKey::Private(session) => message.session.push(session),
+        }
+        let sender = model.own_user().map(|u| u.name.clone()).unwrap_or_default();
+        self.conversations.entry(key).or_default().push(
+            ChatMessage {
+                timestamp: SystemTime::now(),
+                sender,
+                target,
+                text: line.to_string(),
+                own: true,
+            },
+            true,
+        );
+        message
+    }
+
+    /// Completes the slash command in the input line, cycling through the
+    /// candidates on repeated presses. Returns false if there are none.
+    pub fn complete(&mut self, model: Option<&ServerModel>) -> bool {
+        let text = self.input.text();
+        if self.completions.get(self.completion).is_some_and(|c| c == text) {
+            self.completion = (self.completion + 1) % self.completions.len();
+        } else {
+            self.completions = commands::complete(text, model);
+            self.completion = 0;
+        }
+        let Some(line) = self.completions.get(self.completion).cloned() else {
+            return false;
+        };
+        self.input.set_text(line);
+        if self.completions.len() == 1 {
+            // Nothing to cycle through, so the next Tab completes the
+            // following argument.
+            self.completions.clear();
+        }
+        true
+    }
+
+    /// Scrolls the active conversation by `messages`; negative goes back.
+    pub fn scroll(&mut self, model: &ServerModel, messages: isize) {
+        let Some(key) = self.active_key(model) else {
+            return;
+        };
+        if let Some(conversation) = self.conversations.get_mut(&key) {
+            let max = conversation.messages.len().saturating_sub(1);
+            conversation.scroll = conversation
+                .scroll
+                .saturating_add_signed(-messages)
+                .min(max);
+        }
+    }
+
+    /// Conversations other than the active one that have unread messages.
+    fn unread_summary(&self, model: &ServerModel, active: Option<ConversationKey>) -> String {
+        self.conversations
+            .iter()
+            .filter(|(&key, c)| c.unread > 0 && Some(key) != active)
+            .map(|(&key, c)| format!("{} ({})", conversation_name(model, key), c.unread))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Splits `text` into lines of at most `width` characters.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for chunk in chars.chunks(width) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+    lines
+}
+
+fn message_lines(message: &ChatMessage, width: usize, theme: &Theme) -> Vec<Line<'static>> {
+    let sender_style = if message.own {
+        theme.fg(theme.accent).bold()
+    } else {
+        Style::default().bold()
+    };
+    let mut prefix = vec![
+        Span::styled(format!("[{}] ", clock(message.timestamp)), theme.fg(theme.muted)),
+        Span::styled(message.sender.clone(), sender_style),
+    ];
+    if let Some(target) = &message.target {
+        prefix.push(Span::styled(format!(" to {}", target), theme.fg(theme.muted)));
+    }
+    prefix.push(Span::raw(": "));
+    let prefix_width: usize = prefix.iter().map(|s| s.content.chars().count()).sum();
+
+    let mut text_lines = wrap(&message.text, width.saturating_sub(prefix_width).max(10)).into_iter();
+    let mut first = prefix;
+    first.push(Span::raw(text_lines.next().unwrap_or_default()));
+    let mut lines = vec![Line::from(first)];
+    lines.extend(text_lines.map(|l| Line::from(format!("{}{}", " ".repeat(prefix_width), l))));
+    lines
+}
+
+/// Renders the active conversation and the input line into `area`.
+pub fn render(
+    frame: &mut Frame,
+    area: Rect,
+    model: &ServerModel,
+    state: &ChatState,
+    theme: &Theme,
+    input_focus: bool,
+) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let active = state.active_key(model);
+    let conversation = active.and_then(|key| state.conversation(key));
+    let height = layout[0].height.saturating_sub(2) as usize;
+    let width = layout[0].width.saturating_sub(2) as usize;
+
+    // Fill the view bottom-up from the newest visible message.
+    let mut lines: Vec<Line> = Vec::new();
+    if let Some(conversation) = conversation {
+        let end = conversation.messages.len() - conversation.scroll;
+        for message in conversation.messages[..end].iter().rev() {
+            if lines.len() >= height {
+                break;
+            }
+            let mut message_lines = message_lines(message, width, theme);
+            message_lines.append(&mut lines);
+            lines = message_lines;
+        }
+    }
+    let skip = lines.len().saturating_sub(height);
+    let lines: Vec<Line> = lines.into_iter().skip(skip).collect();
+
+    let mut title = active
+        .map(|key| conversation_name(model, key))
+        .unwrap_or_else(|| "Chat".to_string());
+    if conversation.is_some_and(|c| c.scroll > 0) {
+        title.push_str(" (scrolled back)");
+    }
+    let unread = state.unread_summary(model, active);
+    let mut block = Block::default().title(title).borders(Borders::ALL);
+    if !unread.is_empty() {
+        block = block.title_bottom(Line::from(format!(" Unread: {} ", unread)).style(theme.fg(theme.busy)));
+    }
+    frame.render_widget(Paragraph::new(lines).block(block), layout[0]);
+
+    let target = match active {
+        Some(ConversationKey::Channel(id)) if state.to_tree => {
+            format!("To #{} and subchannels", channel_name(model, id))
+        }
+        Some(key) => format!("To {}", conversation_name(model, key)),
+        None => "Not in a channel".to_string(),
+    };
+    render_input(
+        frame,
+        layout[1],
+        &state.input,
+        target,
+        " Ctrl+N/P switch  Ctrl+T tree  PgUp/PgDn scroll ",
+        theme,
+        input_focus,
+    );
+}
+
+/// Renders an input line titled `title` with the key hints `hint` below.
+pub fn render_input(
+    frame: &mut Frame,
+    area: Rect,
+    input: &InputLine,
+    title: String,
+    hint: &str,
+    theme: &Theme,
+    focus: bool,
+) {
+    let widget = Paragraph::new(format!("> {}", input.text())).block(
+        Block::default()
+            .title(title)
+            .title_bottom(hint.to_string())
+            .borders(Borders::ALL)
+            .border_style(theme.border(focus))
+            .border_type(if focus {
+                ratatui::widgets::BorderType::Double
+            } else {
+                ratatui::widgets::BorderType::Plain
+            }),
+    );
+    frame.render_widget(widget, area);
+    if focus {
+        let x = area.x + 3 + input.cursor() as u16;
+        frame.set_cursor_position((x.min(area.right().saturating_sub(2)), area.y + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> ServerModel {
+        let mut model =
+            ServerModel::with(&[(0, 0, "Root"), (1, 0, "Lobby")], &[(1, "me", 1), (2, "bob", 1)]);
+        model.session = Some(1);
+        model
+    }
+
+    #[test]
+    fn test_messages_are_filed_per_conversation() {
+        let model = model();
+        let mut chat = ChatState::default();
+        chat.receive(
+            &model,
+            &proto::TextMessage {
+                actor: Some(2),
+                channel_id: vec![1],
+                message: "hi <b>all</b>".to_string(),
+                ..Default::default()
+            },
+        );
+        chat.receive(
+            &model,
+            &proto::TextMessage {
+                actor: Some(2),
+                session: vec![1],
+                message: "psst".to_string(),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(chat.active_key(&model), Some(ConversationKey::Channel(1)));
+        let channel = chat.conversation(ConversationKey::Channel(1)).unwrap();
+        assert_eq!(channel.messages[0].text, "hi all");
+        assert_eq!(channel.messages[0].sender, "bob");
+        assert_eq!(channel.unread, 0);
+        let private = chat.conversation(ConversationKey::Private(2)).unwrap();
+        assert_eq!(private.unread, 1);
+
+        chat.cycle(&model, true);
+        assert_eq!(chat.active_key(&model), Some(ConversationKey::Private(2)));
+        assert_eq!(chat.conversation(ConversationKey::Private(2)).unwrap().unread, 0);
+
+        let sent = chat.send(&model, "a < b").unwrap();
+        assert_eq!(sent.session, vec![2]);
+        assert_eq!(sent.message, "a &lt; b");
+        assert_eq!(chat.conversation(ConversationKey::Private(2)).unwrap().messages.len(), 2);
+
+        chat.cycle(&model, true);
+        chat.to_tree = true;
+        let sent = chat.send(&model, "everyone").unwrap();
+        assert_eq!(sent.tree_id, vec![1]);
+        assert!(sent.channel_id.is_empty());
+    }
+
+    #[test]
+    fn test_scrollback_stays_put() {
+        let model = model();
+        let mut chat = ChatState::default();
+        for i in 0..5 {
+            chat.send(&model, &format!("line {}", i));
+        }
+        chat.scroll(&model, -2);
+        chat.send(&model, "new");
+        let channel = chat.conversation(ConversationKey::Channel(1)).unwrap();
+        assert_eq!(channel.scroll, 3);
+        chat.scroll(&model, 10);
+        assert_eq!(chat.conversation(ConversationKey::Channel(1)).unwrap().scroll, 0);
+    }
+
+    #[test]
+    fn test_complete_cycles() {
+        let model = model();
+        let mut chat = ChatState::default();
+        chat.input.set_text("/j".to_string());
+        assert!(chat.complete(Some(&model)));
+        assert_eq!(chat.input.text(), "/join ");
+        assert!(chat.complete(Some(&model)));
+        assert_eq!(chat.input.text(), "/join Lobby ");
+
+        chat.input.set_text("/msg ".to_string());
+        chat.complete(Some(&model));
+        assert_eq!(chat.input.text(), "/msg bob ");
+        chat.complete(Some(&model));
+        assert_eq!(chat.input.text(), "/msg me ");
+        chat.complete(Some(&model));
+        assert_eq!(chat.input.text(), "/msg bob ");
+
+        chat.input.set_text("hello".to_string());
+        assert!(!chat.complete(Some(&model)));
+    }
+
+    #[test]
+    fn test_conversation_drops_oldest_messages() {
+        let mut conversation = Conversation::default();
+        let message = |i: usize| ChatMessage {
+            timestamp: SystemTime::now(),
+            sender: "bob".to_string(),
+            target: None,
+            text: i.to_string(),
+            own: false,
+        };
+        for i in 0..MESSAGE_LIMIT + 10 {
+            conversation.push(message(i), false);
+        }
+        assert_eq!(conversation.messages.len(), MESSAGE_LIMIT);
+        assert_eq!(conversation.messages[0].text, "10");
+        assert_eq!(conversation.unread, MESSAGE_LIMIT);
+
+        // Scrolled back to the oldest message, the view stays in range.
+        conversation.scroll = MESSAGE_LIMIT - 1;
+        conversation.push(message(0), true);
+        assert_eq!(conversation.scroll, MESSAGE_LIMIT - 1);
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("abcdef", 4), ["abcd", "ef"]);
+        assert_eq!(wrap("a\n\nb", 4), ["a", "", "b"]);
+    }
+}
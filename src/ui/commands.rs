@@ -0,0 +1,333 @@
+use crate::connection::{self, ServerModel};
+use crate::mumble_url::MumbleUrl;
+
+/// Command names with their argument synopsis, for `/help` and completion.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("join", "<channel>"),
+    ("msg", "<user> <text>"),
+    ("mute", "[user]"),
+    ("deaf", "[user]"),
+    ("kick", "<user> [reason]"),
+    ("ban", "<user> [reason]"),
+    ("register", "[user]"),
+    ("comment", "[text]"),
+    ("whisper", "[user | #channel]"),
+    ("connect", "<host[:port] | mumble://...>"),
+    ("disconnect", ""),
+    ("url", ""),
+    ("server", "start | stop | restart"),
+    ("help", ""),
+];
+
+const SERVER_ACTIONS: &[&str] = &["start", "stop", "restart"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalServerAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhisperTarget {
+    User(u32),
+    Channel(u32),
+}
+
+/// A parsed command with user and channel names resolved against the
+/// server model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    Join(u32),
+    Msg { session: u32, text: String },
+    /// `None` toggles our own self-mute.
+    Mute(Option<u32>),
+    /// `None` toggles our own self-deafen.
+    Deaf(Option<u32>),
+    Kick { session: u32, reason: Option<String> },
+    Ban { session: u32, reason: Option<String> },
+    /// `None` registers ourselves.
+    Register(Option<u32>),
+    Comment(String),
+    /// `None` stops whispering.
+    Whisper(Option<WhisperTarget>),
+    Connect { host: String, port: u16 },
+    /// Connect as a `mumble://` URL says and join its channel.
+    Open(MumbleUrl),
+    Disconnect,
+    /// Show the current session as a `mumble://` URL.
+    Url,
+    Server(LocalServerAction),
+    Help,
+}
+
+/// Splits a quoted or leading user name off `rest`, preferring the longest
+/// name that matches so names with spaces work without quotes.
+fn take_user<'a>(model: &ServerModel, rest: &'a str) -> Result<(u32, &'a str), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() {
+        return Err("a user name is required".to_string());
+    }
+    if let Some(quoted) = rest.strip_prefix('"') {
+        let end = quoted.find('"').ok_or("missing closing quote")?;
+        let name = &quoted[..end];
+        let user = model
+            .users
+            .values()
+            .find(|u| u.name.eq_ignore_ascii_case(name) || u.name == name)
+            .ok_or_else(|| format!("unknown user '{}'", name))?;
+        return Ok((user.session, &quoted[end + 1..]));
+    }
+    model
+        .users
+        .values()
+        .filter(|u| {
+            let len = u.name.len();
+            rest.is_char_boundary(len)
+                && rest.len() >= len
+                && rest[..len].to_lowercase() == u.name.to_lowercase()
+                && rest[len..].chars().next().is_none_or(char::is_whitespace)
+        })
+        .max_by_key(|u| u.name.len())
+        .map(|u| (u.session, &rest[u.name.len()..]))
+        .ok_or_else(|| format!("unknown user '{}'", rest.split_whitespace().next().unwrap_or(rest)))
+}
+
+fn find_channel(model: &ServerModel, name: &str) -> Result<u32, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("a channel name is required".to_string());
+    }
+    if let Some(channel) = model.channels.values().find(|c| c.name.eq_ignore_ascii_case(name)) {
+        return Ok(channel.id);
+    }
+    let lower = name.to_lowercase();
+    let matches: Vec<_> = model
+        .channels
+        .values()
+        .filter(|c| c.name.to_lowercase().starts_with(&lower))
+        .collect();
+    match matches.as_slice() {
+        [channel] => Ok(channel.id),
+        [] => Err(format!("unknown channel '{}'", name)),
+        _ => Err(format!(
+            "'{}' matches several channels: {}",
+            name,
+            matches.iter().map(|c| c.name.as_str()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+fn optional(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Parses a line starting with `/`. `model` is `None` while disconnected.
+pub fn parse(line: &str, model: Option<&ServerModel>) -> Result<SlashCommand, String> {
+    let line = line.trim().strip_prefix('/').ok_or("commands start with '/'")?;
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let connected = || model.ok_or_else(|| format!("/{}: not connected to a server", name));
+    let error = |e: String| format!("/{}: {}", name, e);
+
+    Ok(match name {
+        "join" => SlashCommand::Join(find_channel(connected()?, rest).map_err(error)?),
+        "msg" => {
+            let (session, text) = take_user(connected()?, rest).map_err(error)?;
+            let text = optional(text).ok_or_else(|| error("no message given".to_string()))?;
+            SlashCommand::Msg { session, text }
+        }
+        "mute" | "deaf" | "register" => {
+            let model = connected()?;
+            let target = match optional(rest) {
+                Some(_) => Some(take_user(model, rest).map_err(error)?.0),
+                None => None,
+            };
+            match name {
+                "mute" => SlashCommand::Mute(target),
+                "deaf" => SlashCommand::Deaf(target),
+                _ => SlashCommand::Register(target),
+            }
+        }
+        "kick" | "ban" => {
+            let (session, reason) = take_user(connected()?, rest).map_err(error)?;
+            let reason = optional(reason);
+            if name == "kick" {
+                SlashCommand::Kick { session, reason }
+            } else {
+                SlashCommand::Ban { session, reason }
+            }
+        }
+        "comment" => {
+            connected()?;
+            SlashCommand::Comment(rest.trim().to_string())
+        }
+        "whisper" => {
+            let model = connected()?;
+            let rest = rest.trim();
+            if rest.is_empty() {
+                SlashCommand::Whisper(None)
+            } else if let Some(channel) = rest.strip_prefix('#') {
+                let id = find_channel(model, channel).map_err(error)?;
+                SlashCommand::Whisper(Some(WhisperTarget::Channel(id)))
+            } else {
+                let (session, extra) = take_user(model, rest).map_err(error)?;
+                if !extra.trim().is_empty() {
+                    return Err(error(format!("unexpected '{}'", extra.trim())));
+                }
+                SlashCommand::Whisper(Some(WhisperTarget::User(session)))
+            }
+        }
+        "connect" => {
+            let address = optional(rest).ok_or_else(|| error("usage: /connect <host[:port]>".to_string()))?;
+            if address.to_ascii_lowercase().starts_with("mumble://") {
+                let url = MumbleUrl::parse(&address).map_err(|e| error(e.to_string()))?;
+                return Ok(SlashCommand::Open(url));
+            }
+            let (host, port) =
+                connection::parse_address(&address).map_err(|e| error(e.to_string()))?;
+            SlashCommand::Connect { host, port }
+        }
+        // Also stops a session that is connecting or waiting to reconnect.
+        "disconnect" => SlashCommand::Disconnect,
+        "url" => {
+            connected()?;
+            SlashCommand::Url
+        }
+        "server" => SlashCommand::Server(match rest.trim() {
+            "start" => LocalServerAction::Start,
+            "stop" => LocalServerAction::Stop,
+            "restart" => LocalServerAction::Restart,
+            _ => return Err(error("usage: /server start|stop|restart".to_string())),
+        }),
+        "help" => SlashCommand::Help,
+        _ => return Err(format!("unknown command '/{}', try /help", name)),
+    })
+}
+
+/// Lines `line` can be completed to, in order.
+pub fn complete(line: &str, model: Option<&ServerModel>) -> Vec<String> {
+    let Some(command_line) = line.strip_prefix('/') else {
+        return Vec::new();
+    };
+    let Some((name, arg)) = command_line.split_once(' ') else {
+        return COMMANDS
+            .iter()
+            .filter(|(command, _)| command.starts_with(command_line))
+            .map(|(command, _)| format!("/{} ", command))
+            .collect();
+    };
+
+    let users = || -> Vec<String> {
+        model
+            .map(|m| m.users.values().map(|u| u.name.clone()).collect())
+            .unwrap_or_default()
+    };
+    let channels = |prefix: &str| -> Vec<String> {
+        model
+            .map(|m| m.channels.values().map(|c| format!("{}{}", prefix, c.name)).collect())
+            .unwrap_or_default()
+    };
+    let candidates: Vec<String> = match name {
+        "join" => channels(""),
+        "msg" | "mute" | "deaf" | "kick" | "ban" | "register" => users(),
+        "whisper" => {
+            let mut names = users();
+            names.extend(channels("#"));
+            names
+        }
+        "server" => SERVER_ACTIONS.iter().map(|s| s.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    let lower = arg.to_lowercase();
+    let mut completions: Vec<String> = candidates
+        .into_iter()
+        .filter(|c| c.to_lowercase().starts_with(&lower))
+        .map(|c| format!("/{} {} ", name, c))
+        .collect();
+    completions.sort_by_key(|c| c.to_lowercase());
+    completions.dedup();
+    completions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> ServerModel {
+        ServerModel::with(
+            &[(0, 0, "Root"), (1, 0, "Lobby"), (2, 0, "Lounge"), (3, 0, "Games")],
+            &[(1, "alice", 0), (2, "bob", 0), (3, "bob smith", 0)],
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        let model = model();
+        let parse = |line| parse(line, Some(&model));
+        assert_eq!(parse("/join games"), Ok(SlashCommand::Join(3)));
+        assert!(parse("/join Lo").unwrap_err().contains("several channels"));
+        assert_eq!(
+            parse("/msg bob smith hello there"),
+            Ok(SlashCommand::Msg {
+                session: 3,
+                text: "hello there".to_string()
+            })
+        );
+        assert_eq!(
+            parse(r#"/msg "bob" hi"#),
+            Ok(SlashCommand::Msg {
+                session: 2,
+                text: "hi".to_string()
+            })
+        );
+        assert_eq!(parse("/mute"), Ok(SlashCommand::Mute(None)));
+        assert_eq!(parse("/deaf Alice"), Ok(SlashCommand::Deaf(Some(1))));
+        assert_eq!(
+            parse("/ban bob  spamming "),
+            Ok(SlashCommand::Ban {
+                session: 2,
+                reason: Some("spamming".to_string())
+            })
+        );
+        assert_eq!(
+            parse("/whisper #lobby"),
+            Ok(SlashCommand::Whisper(Some(WhisperTarget::Channel(1))))
+        );
+        assert_eq!(
+            parse("/connect example.com:1234"),
+            Ok(SlashCommand::Connect {
+                host: "example.com".to_string(),
+                port: 1234
+            })
+        );
+        assert_eq!(
+            parse("/connect mumble://bob@example.com/Lobby"),
+            Ok(SlashCommand::Open(MumbleUrl::parse("mumble://bob@example.com/Lobby").unwrap()))
+        );
+        assert_eq!(parse("/server restart"), Ok(SlashCommand::Server(LocalServerAction::Restart)));
+        assert!(parse("/kick carol").unwrap_err().contains("unknown user 'carol'"));
+        assert!(parse("/frobnicate").unwrap_err().contains("unknown command"));
+    }
+
+    #[test]
+    fn test_parse_while_disconnected() {
+        assert_eq!(
+            parse("/server start", None),
+            Ok(SlashCommand::Server(LocalServerAction::Start))
+        );
+        assert!(parse("/join Lobby", None).unwrap_err().contains("not connected"));
+        assert_eq!(parse("/disconnect", None), Ok(SlashCommand::Disconnect));
+    }
+
+    #[test]
+    fn test_complete() {
+        let model = model();
+        assert_eq!(complete("/d", None), ["/deaf ", "/disconnect "]);
+        assert_eq!(complete("/msg b", Some(&model)), ["/msg bob ", "/msg bob smith "]);
+        assert_eq!(complete("/join lo", Some(&model)), ["/join Lobby ", "/join Lounge "]);
+        assert_eq!(complete("/whisper #g", Some(&model)), ["/whisper #Games "]);
+        assert_eq!(complete("/server st", None), ["/server start ", "/server stop "]);
+        assert!(complete("hello", Some(&model)).is_empty());
+    }
+}
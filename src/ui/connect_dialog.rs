@@ -0,0 +1,349 @@
+use crate::server_book::Favourite;
+use crate::connection::{self, DEFAULT_PORT};
+use crate::ui::client::{default_username, ConnectionInfo};
+use crossterm::event::KeyCode;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+const LABELS: [&str; 7] = [
+    "Name",
+    "Address",
+    "Username",
+    "Password",
+    "Identity",
+    "Access tokens",
+    "Save favourite",
+];
+const ADDRESS_FIELD: usize = 1;
+const PASSWORD_FIELD: usize = 3;
+const IDENTITY_FIELD: usize = 4;
+const TOKENS_FIELD: usize = 5;
+const SAVE_FIELD: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogMode {
+    /// Connect, optionally saving the details as a favourite.
+    Connect,
+    /// Add or edit a favourite without connecting.
+    Edit,
+}
+
+/// What the dialog wants the app to do after a key press.
+#[derive(Debug)]
+pub enum DialogAction {
+    None,
+    Cancel,
+    /// Connect, saving `favourite` first if it is set.
+    Connect {
+        info: ConnectionInfo,
+        favourite: Option<Favourite>,
+    },
+    Save(Favourite),
+}
+
+/// Popup asking for the details to connect with.
+pub struct ConnectDialog {
+    mode: DialogMode,
+    /// Text of the fields before the identity.
+    fields: [String; 4],
+    /// Choices for the identity field; the first stands for "automatic".
+    identities: Vec<String>,
+    identity: usize,
+    tokens: String,
+    save: bool,
+    /// Used when the username field is left empty.
+    default_username: String,
+    selected: usize,
+    error: Option<String>,
+}
+
+/// `host:port` as typed by a user, leaving out the default port.
+pub fn format_address(host: &str, port: u16) -> String {
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    if port == DEFAULT_PORT {
+        host
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+impl ConnectDialog {
+    /// Opens the dialog filled in from `favourite`. `identities` are the
+    /// names in the identity store; `save` preselects saving a favourite.
+    pub fn new(mode: DialogMode, favourite: &Favourite, mut identities: Vec<String>, save: bool) -> Self {
+        identities.insert(0, "(automatic)".to_string());
+        let identity = match &favourite.identity {
+            Some(name) => match identities.iter().skip(1).position(|i| i == name) {
+                Some(i) => i + 1,
+                None => {
+                    identities.push(name.clone());
+                    identities.len() - 1
+                }
+            },
+            None => 0,
+        };
+        let address = if favourite.host.is_empty() {
+            String::new()
+        } else {
+            format_address(&favourite.host, favourite.port)
+        };
+        Self {
+            mode,
+            fields: [
+                favourite.name.clone(),
+                address,
+                favourite.username.clone(),
+                favourite.password.clone(),
+            ],
+            identities,
+            identity,
+            tokens: favourite.tokens.join(", "),
+            save,
+            default_username: default_username(),
+            // Start where typing is most likely needed.
+            selected: if favourite.host.is_empty() { ADDRESS_FIELD } else { 2 },
+            error: None,
+        }
+    }
+
+    /// Replaces the login name as the username used when none is entered.
+    pub fn with_default_username(mut self, username: String) -> Self {
+        self.default_username = username;
+        self
+    }
+
+    fn field_count(&self) -> usize {
+        match self.mode {
+            DialogMode::Connect => LABELS.len(),
+            DialogMode::Edit => SAVE_FIELD,
+        }
+    }
+
+    fn parse(&self) -> Result<Favourite, String> {
+        let address = self.fields[ADDRESS_FIELD].trim();
+        if address.is_empty() {
+            return Err("Enter the server address as host or host:port".to_string());
+        }
+        let (host, port) = connection::parse_address(address).map_err(|e| e.to_string())?;
+        let name = self.fields[0].trim();
+        Ok(Favourite {
+            name: if name.is_empty() { host.clone() } else { name.to_string() },
+            host,
+            port,
+            username: self.fields[2].trim().to_string(),
+            password: self.fields[PASSWORD_FIELD].clone(),
+            identity: (self.identity > 0).then(|| self.identities[self.identity].clone()),
+            tokens: self
+                .tokens
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    fn submit(&self) -> Result<DialogAction, String> {
+        let favourite = self.parse()?;
+        if self.mode == DialogMode::Edit {
+            return Ok(DialogAction::Save(favourite));
+        }
+        let info = ConnectionInfo {
+            host: favourite.host.clone(),
+            port: favourite.port,
+            username: if favourite.username.is_empty() {
+                self.default_username.clone()
+            } else {
+                favourite.username.clone()
+            },
+            password: (!favourite.password.is_empty()).then(|| favourite.password.clone()),
+            identity: favourite.identity.clone(),
+            tokens: favourite.tokens.clone(),
+        };
+        Ok(DialogAction::Connect {
+            info,
+            favourite: self.save.then_some(favourite),
+        })
+    }
+
+    fn text_field(&mut self) -> Option<&mut String> {
+        match self.selected {
+            i if i < self.fields.len() => Some(&mut self.fields[i]),
+            TOKENS_FIELD => Some(&mut self.tokens),
+            _ => None,
+        }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> DialogAction {
+        let count = self.field_count();
+        match code {
+            KeyCode::Esc => return DialogAction::Cancel,
+            KeyCode::Enter => match self.submit() {
+                Ok(action) => return action,
+                Err(e) => self.error = Some(e),
+            },
+            KeyCode::Tab | KeyCode::Down => self.selected = (self.selected + 1) % count,
+            KeyCode::BackTab | KeyCode::Up => self.selected = (self.selected + count - 1) % count,
+            KeyCode::Left if self.selected == IDENTITY_FIELD => {
+                self.identity = (self.identity + self.identities.len() - 1) % self.identities.len()
+            }
+            KeyCode::Right if self.selected == IDENTITY_FIELD => {
+                self.identity = (self.identity + 1) % self.identities.len()
+            }
+            KeyCode::Char(' ') if self.selected == SAVE_FIELD => self.save = !self.save,
+            KeyCode::Backspace => {
+                if let Some(field) = self.text_field() {
+                    field.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(field) = self.text_field() {
+                    field.push(c);
+                }
+            }
+            _ => {}
+        }
+        DialogAction::None
+    }
+
+    fn value(&self, field: usize) -> String {
+        match field {
+            PASSWORD_FIELD => "*".repeat(self.fields[field].chars().count()),
+            IDENTITY_FIELD => format!("< {} >", self.identities[self.identity]),
+            TOKENS_FIELD => self.tokens.clone(),
+            SAVE_FIELD => if self.save { "[x]" } else { "[ ]" }.to_string(),
+            2 if self.fields[2].is_empty() && field != self.selected => {
+                format!("({})", self.default_username)
+            }
+            _ => self.fields[field].clone(),
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let count = self.field_count();
+        let popup = super::centered_rect(area, 64, count as u16 + 4);
+
+        let mut lines: Vec<Line> = LABELS[..count]
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let editable = !matches!(i, IDENTITY_FIELD | SAVE_FIELD);
+                let line = Line::from(vec![
+                    format!("{:>14}: ", label).into(),
+                    self.value(i).into(),
+                    if i == self.selected && editable { "_".into() } else { "".into() },
+                ]);
+                if i == self.selected {
+                    line.style(Style::default().add_modifier(Modifier::BOLD))
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(match (&self.error, self.selected) {
+            (Some(error), _) => Line::from(error.clone().red()),
+            (None, IDENTITY_FIELD) => Line::from("Left/Right choose identity".dark_gray()),
+            (None, TOKENS_FIELD) => Line::from("Separate access tokens with commas".dark_gray()),
+            (None, SAVE_FIELD) => Line::from("Space toggles".dark_gray()),
+            (None, _) => Line::from(
+                match self.mode {
+                    DialogMode::Connect => "Enter connect, Esc cancel, Tab next field",
+                    DialogMode::Edit => "Enter save, Esc cancel, Tab next field",
+                }
+                .dark_gray(),
+            ),
+        });
+
+        let title = match self.mode {
+            DialogMode::Connect => "Connect",
+            DialogMode::Edit => "Favourite Server",
+        };
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Double),
+            ),
+            popup,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(dialog: &mut ConnectDialog, text: &str) {
+        for c in text.chars() {
+            dialog.handle_key(KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_connect_and_save() {
+        let favourite = Favourite {
+            name: "Home".to_string(),
+            host: "home.example".to_string(),
+            port: 1234,
+            identity: Some("gone".to_string()),
+            ..Default::default()
+        };
+        let identities = vec!["default".to_string(), "work".to_string()];
+        let mut dialog = ConnectDialog::new(DialogMode::Connect, &favourite, identities, false);
+        assert_eq!(dialog.fields[ADDRESS_FIELD], "home.example:1234");
+        // The dialog starts on the username.
+        type_text(&mut dialog, "alice");
+        dialog.handle_key(KeyCode::Tab);
+        type_text(&mut dialog, "pw");
+        dialog.handle_key(KeyCode::Tab);
+        // "gone" was appended after the stored identities; wrap to "work".
+        dialog.handle_key(KeyCode::Left);
+        dialog.handle_key(KeyCode::Tab);
+        type_text(&mut dialog, "a, b,,");
+        dialog.handle_key(KeyCode::Tab);
+        dialog.handle_key(KeyCode::Char(' '));
+
+        match dialog.handle_key(KeyCode::Enter) {
+            DialogAction::Connect { info, favourite } => {
+                assert_eq!((info.host.as_str(), info.port), ("home.example", 1234));
+                assert_eq!(info.username, "alice");
+                assert_eq!(info.password.as_deref(), Some("pw"));
+                assert_eq!(info.identity.as_deref(), Some("work"));
+                assert_eq!(info.tokens, ["a", "b"]);
+                assert_eq!(favourite.unwrap().name, "Home");
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_add_favourite() {
+        let mut dialog = ConnectDialog::new(DialogMode::Edit, &Favourite::default(), Vec::new(), true);
+        assert!(matches!(dialog.handle_key(KeyCode::Enter), DialogAction::None));
+        assert!(dialog.error.is_some());
+        type_text(&mut dialog, "[::1]:99999");
+        assert!(matches!(dialog.handle_key(KeyCode::Enter), DialogAction::None));
+        assert_eq!(dialog.error.as_deref(), Some("Invalid port in '[::1]:99999'"));
+        for _ in 0..":99999".len() {
+            dialog.handle_key(KeyCode::Backspace);
+        }
+        match dialog.handle_key(KeyCode::Enter) {
+            DialogAction::Save(favourite) => {
+                assert_eq!(favourite.host, "::1");
+                assert_eq!(favourite.port, DEFAULT_PORT);
+                assert_eq!(favourite.name, "::1");
+                assert_eq!(favourite.identity, None);
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crossterm::event::KeyCode;
+
+/// How many submitted lines the history keeps.
+const HISTORY_LIMIT: usize = 100;
+
+/// A single-line text input with cursor movement and history.
+#[derive(Debug, Default)]
+pub struct InputLine {
+    text: String,
+    /// Cursor position in characters.
+    cursor: usize,
+    history: Vec<String>,
+    /// Position while browsing the history; `None` when editing a new line.
+    history_pos: Option<usize>,
+    /// The unfinished line, restored when browsing past the newest entry.
+    draft: String,
+}
+
+impl InputLine {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replaces the text and puts the cursor at the end.
+    pub fn set_text(&mut self, text: String) {
+        self.cursor = text.chars().count();
+        self.text = text;
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    fn show_history(&mut self, pos: Option<usize>) {
+        self.history_pos = pos;
+        let text = match pos {
+            Some(pos) => self.history[pos].clone(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.set_text(text);
+    }
+
+    /// Applies an editing key. Returns the line when Enter submits a
+    /// non-empty one.
+    pub fn handle_key(&mut self, code: KeyCode) -> Option<String> {
+        match code {
+            KeyCode::Char(c) => {
+                let at = self.byte_index(self.cursor);
+                self.text.insert(at, c);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                let at = self.byte_index(self.cursor);
+                self.text.remove(at);
+            }
+            KeyCode::Delete if self.cursor < self.text.chars().count() => {
+                let at = self.byte_index(self.cursor);
+                self.text.remove(at);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.text.chars().count()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.chars().count(),
+            KeyCode::Up if !self.history.is_empty() => {
+                let pos = match self.history_pos {
+                    Some(pos) => pos.saturating_sub(1),
+                    None => {
+                        self.draft = std::mem::take(&mut self.text);
+                        self.history.len() - 1
+                    }
+                };
+                self.show_history(Some(pos));
+            }
+            KeyCode::Down => {
+                if let Some(pos) = self.history_pos {
+                    let next = (pos + 1 < self.history.len()).then_some(pos + 1);
+                    self.show_history(next);
+                }
+            }
+            KeyCode::Esc => {
+                self.set_text(String::new());
+                self.history_pos = None;
+            }
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.text);
+                self.cursor = 0;
+                self.history_pos = None;
+                if line.trim().is_empty() {
+                    return None;
+                }
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                    if self.history.len() > HISTORY_LIMIT {
+                        self.history.remove(0);
+                    }
+                }
+                return Some(line);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(input: &mut InputLine, text: &str) {
+        for c in text.chars() {
+            input.handle_key(KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut input = InputLine::default();
+        type_text(&mut input, "hllo");
+        input.handle_key(KeyCode::Home);
+        input.handle_key(KeyCode::Right);
+        type_text(&mut input, "e");
+        assert_eq!(input.text(), "hello");
+        input.handle_key(KeyCode::End);
+        input.handle_key(KeyCode::Backspace);
+        input.handle_key(KeyCode::Home);
+        input.handle_key(KeyCode::Delete);
+        assert_eq!(input.text(), "ell");
+        type_text(&mut input, "ü");
+        assert_eq!(input.text(), "üell");
+        assert_eq!(input.cursor(), 1);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut input = InputLine::default();
+        type_text(&mut input, "first");
+        assert_eq!(input.handle_key(KeyCode::Enter), Some("first".to_string()));
+        type_text(&mut input, "second");
+        input.handle_key(KeyCode::Enter);
+        assert_eq!(input.handle_key(KeyCode::Enter), None);
+
+        type_text(&mut input, "draft");
+        input.handle_key(KeyCode::Up);
+        assert_eq!(input.text(), "second");
+        input.handle_key(KeyCode::Up);
+        input.handle_key(KeyCode::Up);
+        assert_eq!(input.text(), "first");
+        input.handle_key(KeyCode::Down);
+        input.handle_key(KeyCode::Down);
+        assert_eq!(input.text(), "draft");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::connection::CertificateChanged;
+use crate::known_hosts::{KnownHost, KnownHosts};
+use crate::ui::client::ConnectionInfo;
+use crate::ui::connect_dialog::format_address;
+use crate::ui::settings::Theme;
+use crate::ui::tabs::TabId;
+use crossterm::event::KeyCode;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+/// What the popups want the app to do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KnownHostsAction {
+    None,
+    /// Forget this certificate in the store on disk.
+    Forget(KnownHost),
+    Close,
+}
+
+/// Warning shown when a server presents a different certificate than the
+/// one we know it by.
+pub struct CertificateWarning {
+    pub changed: CertificateChanged,
+    /// The connection to retry if the new certificate is accepted.
+    pub info: ConnectionInfo,
+    /// The tab to retry it in.
+    pub tab: TabId,
+}
+
+impl CertificateWarning {
+    /// Returns `Some(true)` to trust the new certificate, `Some(false)` to
+    /// give up on the connection.
+    pub fn handle_key(&self, code: KeyCode) -> Option<bool> {
+        match code {
+            KeyCode::Char('a') | KeyCode::Char('A') => Some(true),
+            KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let popup = super::centered_rect(area, 72, 16);
+        let changed = &self.changed;
+        let lines = vec![
+            Line::from(format!(
+                "The certificate of {} is not the one it presented before.",
+                format_address(&changed.host, changed.port)
+            ))
+            .bold(),
+            Line::from(""),
+            Line::from("Known SHA-256 fingerprint:"),
+            Line::from(changed.old.clone()).style(theme.fg(theme.muted)),
+            Line::from("Presented SHA-256 fingerprint:"),
+            Line::from(changed.new.clone()).style(theme.fg(theme.busy)),
+            Line::from(""),
+            Line::from(
+                "The server may have been given a new certificate, or someone may be \
+                 impersonating it. Nothing was sent to it yet.",
+            ),
+            Line::from(""),
+            Line::from("[a]ccept the new certificate and connect, [n]o or Esc to cancel")
+                .style(theme.fg(theme.muted)),
+        ];
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+                Block::default()
+                    .title("Server Certificate Changed")
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Double)
+                    .border_style(theme.fg(theme.bad)),
+            ),
+            popup,
+        );
+    }
+}
+
+/// Popup listing the remembered server certificates.
+#[derive(Default)]
+pub struct KnownHostsScreen {
+    selected: usize,
+}
+
+impl KnownHostsScreen {
+    pub fn handle_key(&mut self, code: KeyCode, known_hosts: &KnownHosts) -> KnownHostsAction {
+        let last = known_hosts.hosts.len().saturating_sub(1);
+        // The store may have shrunk since the last key.
+        self.selected = self.selected.min(last);
+        match code {
+            KeyCode::Esc => return KnownHostsAction::Close,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = last,
+            KeyCode::Delete | KeyCode::Char('d') => {
+                if let Some(host) = known_hosts.hosts.get(self.selected) {
+                    return KnownHostsAction::Forget(host.clone());
+                }
+            }
+            _ => {}
+        }
+        KnownHostsAction::None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, known_hosts: &KnownHosts, theme: &Theme) {
+        let popup = super::centered_rect(area, 104, area.height.saturating_sub(4).max(10));
+
+        let mut lines = Vec::new();
+        for (i, host) in known_hosts.hosts.iter().enumerate() {
+            let style = if i == self.selected {
+                theme.selected()
+            } else {
+                Style::default()
+            };
+            lines.push(Line::from(format_address(&host.host, host.port)).style(style.bold()));
+            lines.push(Line::from(format!("  {}", host.fingerprint)).style(style));
+        }
+        if lines.is_empty() {
+            lines.push(
+                Line::from("No servers yet; certificates are remembered on first connect.")
+                    .style(theme.fg(theme.muted)),
+            );
+        }
+
+        let height = popup.height.saturating_sub(2) as usize;
+        let scroll = (self.selected * 2 + 2).saturating_sub(height);
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).scroll((scroll as u16, 0)).block(
+                Block::default()
+                    .title(format!("Known Hosts ({})", KnownHosts::default_path().display()))
+                    .title_bottom(Line::from(" Del forget, Esc close ").style(theme.fg(theme.muted)))
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Double)
+                    .border_style(theme.border(true)),
+            ),
+            popup,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forget() {
+        let mut known_hosts = KnownHosts::default();
+        known_hosts.trust("a.example", 64738, "AA");
+        known_hosts.trust("b.example", 64738, "BB");
+        let mut screen = KnownHostsScreen::default();
+        screen.handle_key(KeyCode::End, &known_hosts);
+        let action = screen.handle_key(KeyCode::Delete, &known_hosts);
+        assert_eq!(action, KnownHostsAction::Forget(known_hosts.hosts[1].clone()));
+        known_hosts.forget(&known_hosts.hosts[1].clone());
+        assert_eq!(known_hosts.hosts.len(), 1);
+        assert_eq!(known_hosts.hosts[0].host, "a.example");
+        let action = screen.handle_key(KeyCode::Delete, &known_hosts);
+        assert_eq!(screen.selected, 0);
+        assert_eq!(action, KnownHostsAction::Forget(known_hosts.hosts[0].clone()));
+        known_hosts.forget(&known_hosts.hosts[0].clone());
+        assert_eq!(screen.handle_key(KeyCode::Delete, &known_hosts), KnownHostsAction::None);
+        assert_eq!(screen.handle_key(KeyCode::Esc, &known_hosts), KnownHostsAction::Close);
+    }
+}
@@ -1,36 +1,52 @@
+use crate::local::LocalServerSettings;
 use crate::ui::client::LocalServerState;
+use crate::ui::settings::{key_name, ClientSettings, Theme};
+use crossterm::event::KeyCode;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
 };
 
-pub fn render(state: &LocalServerState, has_focus: bool) -> Paragraph<'static> {
+pub fn render(
+    state: &LocalServerState,
+    settings: &LocalServerSettings,
+    client_settings: &ClientSettings,
+    has_focus: bool,
+) -> Paragraph<'static> {
+    let theme = &client_settings.theme;
+    let keys = &client_settings.keys;
+    let status = |text: &'static str, color: Color| {
+        Line::from(vec!["Status: ".into(), Span::styled(text, theme.fg(color).bold())])
+    };
     let (status_text, button_text) = match state {
         LocalServerState::Running => (
-            Line::from(vec!["Status: ".into(), "Running".green().bold()]),
-            " [S]top [R]estart ",
+            status("Running", theme.good),
+            format!(
+                " /server stop|restart [{}] edit [{}] gen cert ",
+                key_name(keys.edit_local_server),
+                key_name(keys.regenerate_certificate)
+            ),
         ),
         LocalServerState::Stopped => (
-            Line::from(vec!["Status: ".into(), "Stopped".red().bold()]),
-            " [S]tart ",
-        ),
-        LocalServerState::Starting => (
-            Line::from(vec!["Status: ".into(), "Starting...".yellow().bold()]),
-            " ".into(),
-        ),
-        LocalServerState::Stopping => (
-            Line::from(vec!["Status: ".into(), "Stopping...".yellow().bold()]),
-            " ".into(),
-        ),
-        LocalServerState::Restarting => (
-            Line::from(vec!["Status: ".into(), "Restarting...".yellow().bold()]),
-            " ".into(),
+            status("Stopped", theme.bad),
+            format!(
+                " /server start [{}] edit [{}] gen cert ",
+                key_name(keys.edit_local_server),
+                key_name(keys.regenerate_certificate)
+            ),
         ),
+        LocalServerState::Starting => (status("Starting...", theme.busy), " ".into()),
+        LocalServerState::Stopping => (status("Stopping...", theme.busy), " ".into()),
+        LocalServerState::Restarting => (status("Restarting...", theme.busy), " ".into()),
     };
 
     let text = Text::from(vec![
         status_text,
-        Line::from(""), // Spacer
+        Line::from(format!(
+            "Port {}{}",
+            settings.port,
+            if settings.password.is_empty() { "" } else { ", password set" }
+        )),
         Line::from(button_text).alignment(Alignment::Center),
     ]);
 
@@ -39,6 +55,7 @@ pub fn render(state: &LocalServerState, has_focus: bool) -> Paragraph<'static> {
             Block::default()
                 .title("Local Server")
                 .borders(Borders::ALL)
+                .border_style(theme.border(has_focus))
                 .border_type(if has_focus {
                     ratatui::widgets::BorderType::Double
                 } else {
@@ -47,3 +64,40 @@ pub fn render(state: &LocalServerState, has_focus: bool) -> Paragraph<'static> {
         )
         .alignment(Alignment::Center)
 }
+
+/// Returns `Some(true)` to regenerate the certificate, `Some(false)` to keep
+/// the current one.
+pub fn confirm_regenerate_key(code: KeyCode) -> Option<bool> {
+    match code {
+        KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => Some(true),
+        KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => Some(false),
+        _ => None,
+    }
+}
+
+/// Asks before replacing the certificate clients may have pinned.
+pub fn render_regenerate_confirm(frame: &mut Frame, area: Rect, theme: &Theme) {
+    let popup = super::centered_rect(area, 60, 9);
+    let lines = vec![
+        Line::from("Generate a new certificate for the local server?").bold(),
+        Line::from(""),
+        Line::from(
+            "Clients that trusted the current one will warn that it changed \
+             the next time they connect.",
+        ),
+        Line::from(""),
+        Line::from("[y]es or Enter to generate, [n]o or Esc to keep it")
+            .style(theme.fg(theme.muted)),
+    ];
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .title("Regenerate Certificate")
+                .borders(Borders::ALL)
+                .border_type(ratatui::widgets::BorderType::Double)
+                .border_style(theme.border(true)),
+        ),
+        popup,
+    );
+}
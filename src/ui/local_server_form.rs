@@ -0,0 +1,165 @@
+use crate::local::LocalServerSettings;
+use crossterm::event::KeyCode;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+const LABELS: [&str; 4] = ["Port", "Password", "Welcome text", "Max users"];
+const PASSWORD_FIELD: usize = 1;
+
+/// What the form wants the app to do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FormAction {
+    None,
+    Cancel,
+    Save(LocalServerSettings),
+}
+
+/// Popup for editing [`LocalServerSettings`].
+pub struct LocalServerForm {
+    fields: [String; 4],
+    selected: usize,
+    error: Option<String>,
+}
+
+impl LocalServerForm {
+    pub fn new(settings: &LocalServerSettings) -> Self {
+        Self {
+            fields: [
+                settings.port.to_string(),
+                settings.password.clone(),
+                settings.welcome_text.clone(),
+                settings.max_users.to_string(),
+            ],
+            selected: 0,
+            error: None,
+        }
+    }
+
+    fn parse(&self) -> Result<LocalServerSettings, String> {
+        let port = self.fields[0]
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or("Port must be a number between 1 and 65535")?;
+        let max_users = self.fields[3]
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| "Max users must be a number (0 for no limit)")?;
+        Ok(LocalServerSettings {
+            port,
+            password: self.fields[1].clone(),
+            welcome_text: self.fields[2].clone(),
+            max_users,
+        })
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> FormAction {
+        match code {
+            KeyCode::Esc => return FormAction::Cancel,
+            KeyCode::Enter => match self.parse() {
+                Ok(settings) => return FormAction::Save(settings),
+                Err(e) => self.error = Some(e),
+            },
+            KeyCode::Tab | KeyCode::Down => self.selected = (self.selected + 1) % LABELS.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                self.selected = (self.selected + LABELS.len() - 1) % LABELS.len()
+            }
+            KeyCode::Backspace => {
+                self.fields[self.selected].pop();
+            }
+            KeyCode::Char(c) => self.fields[self.selected].push(c),
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let popup = super::centered_rect(area, 60, LABELS.len() as u16 + 4);
+
+        let mut lines: Vec<Line> = LABELS
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let value = if i == PASSWORD_FIELD {
+                    "*".repeat(self.fields[i].chars().count())
+                } else {
+                    self.fields[i].clone()
+                };
+                let line = Line::from(vec![
+                    format!("{:>13}: ", label).into(),
+                    value.into(),
+                    if i == self.selected { "_".into() } else { "".into() },
+                ]);
+                if i == self.selected {
+                    line.style(Style::default().add_modifier(Modifier::BOLD))
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(match &self.error {
+            Some(error) => Line::from(error.clone().red()),
+            None => Line::from("Enter save, Esc cancel, Tab next field".dark_gray()),
+        });
+
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .title("Local Server Settings")
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Double),
+            ),
+            popup,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(form: &mut LocalServerForm, text: &str) {
+        for c in text.chars() {
+            form.handle_key(KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_edit_and_save() {
+        let mut form = LocalServerForm::new(&LocalServerSettings::default());
+        for _ in 0..5 {
+            form.handle_key(KeyCode::Backspace);
+        }
+        type_text(&mut form, "50000");
+        form.handle_key(KeyCode::Tab);
+        type_text(&mut form, "pw");
+        form.handle_key(KeyCode::Up);
+        form.handle_key(KeyCode::Up);
+        form.handle_key(KeyCode::Backspace);
+        form.handle_key(KeyCode::Backspace);
+        type_text(&mut form, "0");
+
+        match form.handle_key(KeyCode::Enter) {
+            FormAction::Save(settings) => {
+                assert_eq!(settings.port, 50000);
+                assert_eq!(settings.password, "pw");
+                assert_eq!(settings.max_users, 10);
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_port_is_reported() {
+        let mut form = LocalServerForm::new(&LocalServerSettings::default());
+        type_text(&mut form, "9");
+        assert_eq!(form.handle_key(KeyCode::Enter), FormAction::None);
+        assert!(form.error.is_some());
+        assert_eq!(form.handle_key(KeyCode::Esc), FormAction::Cancel);
+    }
+}
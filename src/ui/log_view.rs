@@ -1,27 +1,236 @@
+use crate::log_buffer::{LogBuffer, LogFilter, LogRecord};
+use crate::ui::settings::Theme;
+use crossterm::event::KeyCode;
+use log::{Level, LevelFilter};
 use ratatui::{
     prelude::*,
     widgets::{Block, Borders, Paragraph},
 };
-use std::sync::{Arc, Mutex};
+use std::cell::Cell;
 
+/// Scroll position and filter of the server log view.
+#[derive(Debug, Default)]
+pub struct LogViewState {
+    pub filter: LogFilter,
+    pub search_active: bool,
+    /// Sequence number of the bottom line; `None` follows new records.
+    anchor: Option<u64>,
+    /// Lines that fit in the view, remembered from the last render.
+    height: Cell<usize>,
+}
+
+impl LogViewState {
+    pub fn following(&self) -> bool {
+        self.anchor.is_none()
+    }
+
+    /// Index one past the bottom visible line in `seqs`.
+    fn end(&self, seqs: &[u64]) -> usize {
+        let min_end = self.height.get().max(1).min(seqs.len());
+        match self.anchor {
+            None => seqs.len(),
+            Some(anchor) => seqs.partition_point(|&s| s <= anchor).max(min_end),
+        }
+    }
+
+    /// Moves the bottom of the view to `end`; the last line means following.
+    fn set_end(&mut self, seqs: &[u64], end: usize) {
+        self.anchor = if end >= seqs.len() { None } else { Some(seqs[end - 1]) };
+    }
+
+    fn scroll(&mut self, buffer: &LogBuffer, lines: isize) {
+        let seqs: Vec<u64> = buffer.filtered(&self.filter).map(|r| r.seq).collect();
+        if seqs.is_empty() {
+            return;
+        }
+        let min_end = self.height.get().max(1).min(seqs.len());
+        let end = self.end(&seqs).saturating_add_signed(lines).max(min_end);
+        self.set_end(&seqs, end);
+    }
+
+    /// Handles the log view keys. Returns whether the key was consumed.
+    pub fn handle_key(&mut self, code: KeyCode, buffer: &LogBuffer) -> bool {
+        if self.search_active {
+            match code {
+                KeyCode::Char(c) => self.filter.text.push(c),
+                KeyCode::Backspace => {
+                    self.filter.text.pop();
+                }
+                KeyCode::Esc => {
+                    self.filter.text.clear();
+                    self.search_active = false;
+                }
+                KeyCode::Enter | KeyCode::Tab => self.search_active = false,
+                _ => {}
+            }
+            return true;
+        }
+        let page = self.height.get().max(1) as isize;
+        match code {
+            KeyCode::Up => self.scroll(buffer, -1),
+            KeyCode::Down => self.scroll(buffer, 1),
+            KeyCode::PageUp => self.scroll(buffer, -page),
+            KeyCode::PageDown => self.scroll(buffer, page),
+            KeyCode::Home => {
+                let seqs: Vec<u64> = buffer.filtered(&self.filter).map(|r| r.seq).collect();
+                if !seqs.is_empty() {
+                    let top_end = self.height.get().max(1).min(seqs.len());
+                    self.set_end(&seqs, top_end);
+                }
+            }
+            KeyCode::End => self.anchor = None,
+            KeyCode::Char('f') => {
+                self.anchor = match self.anchor {
+                    Some(_) => None,
+                    None => buffer.filtered(&self.filter).last().map(|r| r.seq),
+                };
+            }
+            KeyCode::Char('l') => self.filter.min_level = next_level(self.filter.min_level),
+            KeyCode::Char('/') => self.search_active = true,
+            KeyCode::Esc => self.filter = LogFilter::default(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The records that fit in `height` lines at the current position.
+    pub fn visible<'a>(&self, buffer: &'a LogBuffer, height: usize) -> Vec<&'a LogRecord> {
+        self.height.set(height);
+        let records: Vec<&LogRecord> = buffer.iter().filter(|r| self.filter.matches(r)).collect();
+        let seqs: Vec<u64> = records.iter().map(|r| r.seq).collect();
+        let end = self.end(&seqs);
+        records[end.saturating_sub(height)..end].to_vec()
+    }
+}
+
+/// Cycles the minimum level shown: everything, then debug and up, and so on.
+fn next_level(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::Trace | LevelFilter::Off => LevelFilter::Debug,
+        LevelFilter::Debug => LevelFilter::Info,
+        LevelFilter::Info => LevelFilter::Warn,
+        LevelFilter::Warn => LevelFilter::Error,
+        LevelFilter::Error => LevelFilter::Trace,
+    }
+}
+
+fn level_style(level: Level, theme: &Theme) -> Style {
+    match level {
+        Level::Error => theme.fg(theme.bad),
+        Level::Warn => theme.fg(theme.busy),
+        Level::Info => Style::default(),
+        Level::Debug => theme.fg(theme.muted),
+        Level::Trace => theme.fg(theme.muted).dim(),
+    }
+}
+
+/// Renders the lines of the server log that fit in `height` rows (borders
+/// excluded).
 pub fn render<'a>(
-    log_buffer: &'a Arc<Mutex<Vec<String>>>,
+    buffer: &LogBuffer,
+    state: &LogViewState,
+    theme: &Theme,
     has_focus: bool,
-    scroll_offset: usize,
+    height: usize,
 ) -> Paragraph<'a> {
-    let logs = log_buffer.lock().unwrap();
-    let log_text: Vec<Line> = logs.iter().map(|s| Line::from(s.clone())).collect();
-
-    Paragraph::new(log_text)
-        .block(
-            Block::default()
-                .title("Local Server Log")
-                .borders(Borders::ALL)
-                .border_style(if has_focus {
-                    Style::default().fg(Color::Yellow)
-                } else {
-                    Style::default()
-                }),
-        )
-        .scroll((scroll_offset as u16, 0))
+    let lines: Vec<Line> = state
+        .visible(buffer, height)
+        .into_iter()
+        .map(|record| {
+            Line::from(vec![
+                Span::styled(record.time_of_day(), theme.fg(theme.muted)),
+                Span::raw(" "),
+                Span::styled(format!("{:<5}", record.level), level_style(record.level, theme).bold()),
+                Span::raw(" "),
+                Span::styled(record.message.clone(), level_style(record.level, theme)),
+            ])
+        })
+        .collect();
+
+    let mut title = "Local Server Log".to_string();
+    if state.filter.min_level != LevelFilter::Trace {
+        title.push_str(&format!(" [{}+]", state.filter.min_level));
+    }
+    if state.search_active || !state.filter.text.is_empty() {
+        title.push_str(&format!(" [/{}]", state.filter.text));
+    }
+    title.push_str(if state.following() { " (following)" } else { " (paused)" });
+
+    Paragraph::new(lines).block(
+        Block::default()
+            .title(title)
+            .title_bottom(" [l]evel [/]search [f]ollow [w]rite ")
+            .borders(Borders::ALL)
+            .border_style(if has_focus {
+                theme.fg(theme.busy)
+            } else {
+                Style::default()
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(lines: usize) -> LogBuffer {
+        let mut buffer = LogBuffer::new(100);
+        for i in 0..lines {
+            let level = if i % 2 == 0 { Level::Info } else { Level::Debug };
+            buffer.push(level, "test", format!("line {}", i));
+        }
+        buffer
+    }
+
+    fn messages(state: &LogViewState, buffer: &LogBuffer, height: usize) -> Vec<String> {
+        state
+            .visible(buffer, height)
+            .into_iter()
+            .map(|r| r.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_follow_and_scroll() {
+        let mut log = buffer(10);
+        let mut state = LogViewState::default();
+        assert_eq!(messages(&state, &log, 3), ["line 7", "line 8", "line 9"]);
+
+        state.handle_key(KeyCode::Up, &log);
+        assert!(!state.following());
+        log.push(Level::Info, "test", "line 10".to_string());
+        // A paused view stays put while new records arrive.
+        assert_eq!(messages(&state, &log, 3), ["line 6", "line 7", "line 8"]);
+
+        state.handle_key(KeyCode::Home, &log);
+        assert_eq!(messages(&state, &log, 3), ["line 0", "line 1", "line 2"]);
+        state.handle_key(KeyCode::Up, &log);
+        assert_eq!(messages(&state, &log, 3), ["line 0", "line 1", "line 2"]);
+
+        state.handle_key(KeyCode::PageDown, &log);
+        state.handle_key(KeyCode::PageDown, &log);
+        state.handle_key(KeyCode::PageDown, &log);
+        assert_eq!(messages(&state, &log, 3), ["line 8", "line 9", "line 10"]);
+        assert!(state.following());
+    }
+
+    #[test]
+    fn test_level_and_search_filter() {
+        let log = buffer(10);
+        let mut state = LogViewState::default();
+        state.handle_key(KeyCode::Char('l'), &log);
+        state.handle_key(KeyCode::Char('l'), &log);
+        assert_eq!(state.filter.min_level, LevelFilter::Info);
+        assert_eq!(messages(&state, &log, 2), ["line 6", "line 8"]);
+
+        state.handle_key(KeyCode::Char('/'), &log);
+        for c in "line 4".chars() {
+            state.handle_key(KeyCode::Char(c), &log);
+        }
+        state.handle_key(KeyCode::Enter, &log);
+        assert_eq!(messages(&state, &log, 5), ["line 4"]);
+
+        state.handle_key(KeyCode::Esc, &log);
+        assert_eq!(messages(&state, &log, 20).len(), 10);
+    }
 }
@@ -1,5 +1,33 @@
+use ratatui::layout::Rect;
+
+pub mod channel_tree;
+pub mod chat;
 pub mod client;
+pub mod commands;
+pub mod connect_dialog;
+pub mod input;
+pub mod known_hosts_screen;
 pub mod local_server;
+pub mod local_server_form;
 pub mod log_view;
+pub mod mouse;
+pub mod notify;
+pub mod reconnect;
 pub mod server;
+pub mod server_details;
 pub mod servers;
+pub mod settings;
+pub mod settings_screen;
+pub mod tabs;
+
+/// A `width` x `height` popup centered in `area`, shrunk to fit.
+pub fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
@@ -0,0 +1,230 @@
+use crate::ui::settings::ClientSettings;
+use ratatui::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Two clicks on the same cell within this time make a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Where each pane goes for the current layout settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneAreas {
+    pub left: Rect,
+    pub right: Rect,
+    pub local_server: Rect,
+    pub favourites: Rect,
+    pub lan: Rect,
+    pub public: Rect,
+    /// The session tabs above the content.
+    pub tabs: Rect,
+    /// Everything between the tabs and the client log: chat, tree or
+    /// server log.
+    pub content: Rect,
+    pub client_log: Rect,
+    /// The channel tree, when connected and showing the chat.
+    pub tree: Option<Rect>,
+    /// Messages and input line, when showing the chat.
+    pub chat: Option<Rect>,
+    pub chat_input: Option<Rect>,
+}
+
+/// A pane edge that can be dragged to resize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Border {
+    /// Between the server panes and the content.
+    Left,
+    LocalServer,
+    Favourites,
+    Lan,
+    ClientLog,
+    /// Between the channel tree and the chat.
+    Tree,
+}
+
+fn contains(rect: Rect, column: u16, row: u16) -> bool {
+    rect.contains(Position::new(column, row))
+}
+
+impl PaneAreas {
+    /// Splits `area` as set in `settings`. `chat` is whether the chat view is
+    /// shown and `tree` whether the channel tree is shown next to it.
+    pub fn new(area: Rect, settings: &ClientSettings, chat: bool, tree: bool) -> Self {
+        let layout = &settings.layout;
+        let [left, right] = Layout::horizontal([
+            Constraint::Percentage(layout.left_width),
+            Constraint::Percentage(100 - layout.left_width),
+        ])
+        .areas(area);
+        let [local_server, favourites, lan, public] = Layout::vertical([
+            Constraint::Length(layout.local_server_height),
+            Constraint::Percentage(layout.favourites_height),
+            Constraint::Percentage(layout.lan_height),
+            Constraint::Min(0),
+        ])
+        .areas(left);
+        let [tabs, content, client_log] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(layout.client_log_height),
+        ])
+        .areas(right);
+
+        let (tree, chat) = match (chat, tree) {
+            (false, _) => (None, None),
+            (true, false) => (None, Some(content)),
+            (true, true) => {
+                let [tree, chat] = Layout::horizontal([
+                    Constraint::Percentage(layout.tree_width),
+                    Constraint::Percentage(100 - layout.tree_width),
+                ])
+                .areas(content);
+                (Some(tree), Some(chat))
+            }
+        };
+        let chat_input = chat.map(|chat| {
+            let [_, input] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(chat);
+            input
+        });
+        Self {
+            left,
+            right,
+            local_server,
+            favourites,
+            lan,
+            public,
+            tabs,
+            content,
+            client_log,
+            tree,
+            chat,
+            chat_input,
+        }
+    }
+
+    /// The border under the mouse. Each border is the two touching edges
+    /// of the panes on either side.
+    pub fn border_at(&self, column: u16, row: u16) -> Option<Border> {
+        let on_edge = |end: u16, start: u16, at: u16| at + 1 == end || at == start;
+        if contains(self.left.union(self.right), column, row)
+            && on_edge(self.left.right(), self.right.x, column)
+        {
+            return Some(Border::Left);
+        }
+        if contains(self.left, column, row) {
+            let splits = [
+                (self.local_server, self.favourites, Border::LocalServer),
+                (self.favourites, self.lan, Border::Favourites),
+                (self.lan, self.public, Border::Lan),
+            ];
+            return splits
+                .into_iter()
+                .find(|(above, below, _)| on_edge(above.bottom(), below.y, row))
+                .map(|(_, _, border)| border);
+        }
+        if let (Some(tree), Some(chat)) = (self.tree, self.chat) {
+            if contains(self.content, column, row) && on_edge(tree.right(), chat.x, column) {
+                return Some(Border::Tree);
+            }
+        }
+        if contains(self.right, column, row) && on_edge(self.content.bottom(), self.client_log.y, row) {
+            return Some(Border::ClientLog);
+        }
+        None
+    }
+
+    /// Moves `border` to the mouse position, within the limits the settings
+    /// allow. Returns whether the layout changed.
+    pub fn drag(&self, border: Border, column: u16, row: u16, settings: &mut ClientSettings) -> bool {
+        let percent = |at: u16, start: u16, size: u16| {
+            ((at + 1).saturating_sub(start) as u32 * 100 / size.max(1) as u32).to_string()
+        };
+        let (key, value) = match border {
+            Border::Left => {
+                let whole = self.left.union(self.right);
+                ("left_width", percent(column, whole.x, whole.width))
+            }
+            Border::LocalServer => {
+                ("local_server_height", (row + 1).saturating_sub(self.left.y).to_string())
+            }
+            Border::Favourites => {
+                ("favourites_height", percent(row, self.favourites.y, self.left.height))
+            }
+            Border::Lan => ("lan_height", percent(row, self.lan.y, self.left.height)),
+            Border::ClientLog => {
+                ("client_log_height", self.right.bottom().saturating_sub(row).to_string())
+            }
+            Border::Tree => ("tree_width", percent(column, self.content.x, self.content.width)),
+        };
+        let before = settings.layout.clone();
+        // Positions past the limits just leave the border where it is.
+        settings.set("layout", key, &value).ok();
+        settings.layout != before
+    }
+}
+
+/// Recognises double-clicks.
+#[derive(Debug, Default)]
+pub struct ClickTracker {
+    last: Option<(Instant, u16, u16)>,
+}
+
+impl ClickTracker {
+    /// Records a click. Returns whether it completes a double-click.
+    pub fn click(&mut self, at: Instant, column: u16, row: u16) -> bool {
+        let double = self.last.is_some_and(|(time, c, r)| {
+            (c, r) == (column, row) && at.duration_since(time) <= DOUBLE_CLICK
+        });
+        // A third click starts over rather than making another double-click.
+        self.last = if double { None } else { Some((at, column, row)) };
+        double
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_borders_and_drag() {
+        let mut settings = ClientSettings::default();
+        let area = Rect::new(0, 0, 100, 50);
+        let areas = PaneAreas::new(area, &settings, true, true);
+        assert_eq!(areas.left.width, 30);
+        assert_eq!(areas.local_server.height, 5);
+        assert_eq!(areas.chat_input.unwrap().height, 3);
+        assert_eq!((areas.tabs.y, areas.tabs.height, areas.content.y), (0, 1, 1));
+
+        assert_eq!(areas.border_at(29, 10), Some(Border::Left));
+        assert_eq!(areas.border_at(30, 10), Some(Border::Left));
+        assert_eq!(areas.border_at(10, 4), Some(Border::LocalServer));
+        assert_eq!(areas.border_at(10, areas.lan.y), Some(Border::Favourites));
+        assert_eq!(areas.border_at(60, areas.client_log.y), Some(Border::ClientLog));
+        assert_eq!(areas.border_at(areas.chat.unwrap().x, 10), Some(Border::Tree));
+        assert_eq!(areas.border_at(10, 10), None);
+        assert_eq!(areas.border_at(60, 10), None);
+
+        assert!(areas.drag(Border::Left, 49, 10, &mut settings));
+        assert_eq!(settings.layout.left_width, 50);
+        assert!(areas.drag(Border::ClientLog, 60, 39, &mut settings));
+        assert_eq!(settings.layout.client_log_height, 11);
+        // Too small a pane is refused.
+        assert!(!areas.drag(Border::LocalServer, 10, 0, &mut settings));
+        assert_eq!(settings.layout.local_server_height, 5);
+
+        let areas = PaneAreas::new(area, &settings, false, false);
+        assert_eq!(areas.left.width, 50);
+        assert_eq!(areas.border_at(areas.right.x + 5, 10), None);
+        assert!(areas.chat_input.is_none());
+    }
+
+    #[test]
+    fn test_double_click() {
+        let mut clicks = ClickTracker::default();
+        let start = Instant::now();
+        assert!(!clicks.click(start, 5, 5));
+        assert!(clicks.click(start + Duration::from_millis(100), 5, 5));
+        assert!(!clicks.click(start + Duration::from_millis(200), 5, 5));
+        assert!(!clicks.click(start + Duration::from_millis(300), 6, 5));
+        assert!(!clicks.click(start + Duration::from_secs(1), 6, 5));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::connection::{ConnectionEvent, ServerModel};
+use crate::html;
+use crate::ui::settings::{DesktopNotification, NotificationSettings};
+use std::io::{self, Write};
+
+const BEL: char = '\x07';
+
+/// Something on a server worth interrupting the user for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alert {
+    /// Someone wrote our name in a channel.
+    Mention { from: String, text: String },
+    PrivateMessage { from: String, text: String },
+    /// Someone came into our channel.
+    UserJoined { name: String, channel: String },
+    Kicked {
+        by: Option<String>,
+        reason: Option<String>,
+        ban: bool,
+    },
+    /// Someone else moved us to another channel.
+    Moved { by: String, channel: String },
+}
+
+impl Alert {
+    pub fn enabled(&self, settings: &NotificationSettings) -> bool {
+        match self {
+            Self::Mention { .. } => settings.mention,
+            Self::PrivateMessage { .. } => settings.private_message,
+            Self::UserJoined { .. } => settings.user_joined,
+            Self::Kicked { .. } => settings.kicked,
+            Self::Moved { .. } => settings.moved,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match self {
+            Self::Mention { from, .. } => format!("{} mentioned you", from),
+            Self::PrivateMessage { from, .. } => format!("Message from {}", from),
+            Self::UserJoined { name, .. } => format!("{} joined your channel", name),
+            Self::Kicked { ban: true, .. } => "You were banned".to_string(),
+            Self::Kicked { .. } => "You were kicked".to_string(),
+            Self::Moved { by, .. } => format!("{} moved you", by),
+        }
+    }
+
+    pub fn body(&self) -> String {
+        match self {
+            Self::Mention { text, .. } | Self::PrivateMessage { text, .. } => text.clone(),
+            Self::UserJoined { channel, .. } | Self::Moved { channel, .. } => channel.clone(),
+            Self::Kicked { by, reason, .. } => {
+                let by = by.as_deref().unwrap_or("the server");
+                match reason {
+                    Some(reason) => format!("by {}: {}", by, reason),
+                    None => format!("by {}", by),
+                }
+            }
+        }
+    }
+}
+
+/// Whether `text` contains `name` as a whole word, ignoring case.
+pub fn mentions(text: &str, name: &str) -> bool {
+    if name.is_empty() {
+        return false;
+    }
+    let text = text.to_lowercase();
+    let name = name.to_lowercase();
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    text.match_indices(&name).any(|(start, _)| {
+        !is_word(text[..start].chars().next_back())
+            && !is_word(text[start + name.len()..].chars().next())
+    })
+}
+
+/// The alert a connection event raises, if any. `model` is the server
+/// after the event was applied.
+pub fn alert_for(model: &ServerModel, event: &ConnectionEvent) -> Option<Alert> {
+    let own_session = model.session?;
+    let user_name = |session: u32| model.users.get(&session).map(|u| u.name.clone());
+    let channel_name = |id: u32| {
+        model
+            .channels
+            .get(&id)
+            .map(|c| c.name.clone())
+            .unwrap_or_else(|| format!("channel {}", id))
+    };
+    match *event {
+        ConnectionEvent::TextMessage(ref text) => {
+            if text.actor == Some(own_session) {
+                return None;
+            }
+            let from = text
+                .actor
+                .and_then(user_name)
+                .unwrap_or_else(|| "Server".to_string());
+            let message = html::to_plain_text(&text.message);
+            if !text.session.is_empty() {
+                return Some(Alert::PrivateMessage { from, text: message });
+            }
+            let own = model.own_user()?;
+            mentions(&message, &own.name).then_some(Alert::Mention { from, text: message })
+        }
+        ConnectionEvent::UserJoined(session) | ConnectionEvent::UserMoved { session, .. }
+            if session != own_session =>
+        {
+            let own_channel = model.own_user()?.channel_id;
+            let user = model.users.get(&session)?;
+            let came_from = match *event {
+                ConnectionEvent::UserMoved { from, .. } => Some(from),
+                _ => None,
+            };
+            (user.channel_id == own_channel && came_from != Some(own_channel)).then(|| {
+                Alert::UserJoined {
+                    name: user.name.clone(),
+                    channel: channel_name(own_channel),
+                }
+            })
+        }
+        ConnectionEvent::UserMoved {
+            actor: Some(actor), ..
+        } if actor != own_session => Some(Alert::Moved {
+            by: user_name(actor).unwrap_or_else(|| "The server".to_string()),
+            channel: channel_name(model.own_user()?.channel_id),
+        }),
+        ConnectionEvent::UserLeft {
+            session,
+            actor,
+            ref reason,
+            ban,
+            ..
+        } if session == own_session => Some(Alert::Kicked {
+            by: actor.and_then(user_name),
+            reason: reason.clone(),
+            ban,
+        }),
+        _ => None,
+    }
+}
+
+/// Replaces control characters, which could end or extend the escape
+/// sequence, and `;` if it would start another field.
+fn clean(text: &str, semicolons: bool) -> String {
+    text.chars()
+        .map(|c| if c.is_control() || (semicolons && c == ';') { ' ' } else { c })
+        .collect()
+}
+
+/// The bytes announcing `alert` as configured: nothing in do not disturb
+/// mode or for a disabled event, otherwise a bell and a desktop notification
+/// escape.
+pub fn sequences(alert: &Alert, settings: &NotificationSettings) -> String {
+    let mut out = String::new();
+    if settings.do_not_disturb || !alert.enabled(settings) {
+        return out;
+    }
+    if settings.bell {
+        out.push(BEL);
+    }
+    let (title, body) = (alert.title(), alert.body());
+    match settings.desktop {
+        DesktopNotification::Off => {}
+        DesktopNotification::Osc9 => {
+            out.push_str(&format!("\x1b]9;{}: {}{}", clean(&title, false), clean(&body, false), BEL));
+        }
+        DesktopNotification::Osc777 => {
+            out.push_str(&format!(
+                "\x1b]777;notify;{};{}{}",
+                clean(&title, true),
+                clean(&body, false),
+                BEL
+            ));
+        }
+    }
+    out
+}
+
+/// Writes the sequences for `alerts` to the terminal.
+pub fn emit(out: &mut impl Write, alerts: &[Alert], settings: &NotificationSettings) -> io::Result<()> {
+    for alert in alerts {
+        out.write_all(sequences(alert, settings).as_bytes())?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto;
+
+    fn model() -> ServerModel {
+        let mut model = ServerModel::with(
+            &[(0, 0, "Root"), (1, 0, "Lobby")],
+            &[(1, "alice", 1), (2, "bob", 0)],
+        );
+        model.session = Some(1);
+        model
+    }
+
+    fn text(actor: u32, session: Vec<u32>, message: &str) -> ConnectionEvent {
+        ConnectionEvent::TextMessage(proto::TextMessage {
+            actor: Some(actor),
+            session,
+            channel_id: vec![1],
+            message: message.to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_mentions() {
+        assert!(mentions("hey Alice, look", "alice"));
+        assert!(mentions("alice", "alice"));
+        assert!(!mentions("malice aforethought", "alice"));
+        assert!(!mentions("alice_2 is here", "alice"));
+        assert!(!mentions("anything", ""));
+    }
+
+    #[test]
+    fn test_alerts_for_events() {
+        let mut model = model();
+        assert_eq!(
+            alert_for(&model, &text(2, vec![], "ping <b>alice</b>")),
+            Some(Alert::Mention {
+                from: "bob".to_string(),
+                text: "ping alice".to_string()
+            })
+        );
+        assert_eq!(alert_for(&model, &text(2, vec![], "hello all")), None);
+        assert_eq!(alert_for(&model, &text(1, vec![2], "alice here")), None);
+        assert!(matches!(
+            alert_for(&model, &text(2, vec![1], "psst")),
+            Some(Alert::PrivateMessage { .. })
+        ));
+
+        let bob_moves = proto::UserState {
+            session: Some(2),
+            channel_id: Some(1),
+            ..Default::default()
+        };
+        let event = model.apply(&bob_moves.into()).unwrap();
+        assert_eq!(
+            alert_for(&model, &event),
+            Some(Alert::UserJoined {
+                name: "bob".to_string(),
+                channel: "Lobby".to_string()
+            })
+        );
+
+        let moved = proto::UserState {
+            session: Some(1),
+            actor: Some(2),
+            channel_id: Some(0),
+            ..Default::default()
+        };
+        let event = model.apply(&moved.into()).unwrap();
+        assert_eq!(
+            alert_for(&model, &event),
+            Some(Alert::Moved {
+                by: "bob".to_string(),
+                channel: "Root".to_string()
+            })
+        );
+        let went = proto::UserState {
+            session: Some(1),
+            actor: Some(1),
+            channel_id: Some(1),
+            ..Default::default()
+        };
+        let event = model.apply(&went.into()).unwrap();
+        assert_eq!(alert_for(&model, &event), None);
+
+        let kick = proto::UserRemove {
+            session: 1,
+            actor: Some(2),
+            reason: Some("spam".to_string()),
+            ban: None,
+        };
+        let event = model.apply(&kick.into()).unwrap();
+        let alert = alert_for(&model, &event).unwrap();
+        assert_eq!(alert.title(), "You were kicked");
+        assert_eq!(alert.body(), "by bob: spam");
+    }
+
+    #[test]
+    fn test_sequences() {
+        let alert = Alert::PrivateMessage {
+            from: "bob".to_string(),
+            text: "hi;\x1b]0;pwned\x07".to_string(),
+        };
+        let mut settings = NotificationSettings::default();
+        assert_eq!(
+            sequences(&alert, &settings),
+            "\x07\x1b]9;Message from bob: hi; ]0;pwned \x07"
+        );
+
+        settings.bell = false;
+        settings.desktop = DesktopNotification::Osc777;
+        let mut out = Vec::new();
+        emit(&mut out, std::slice::from_ref(&alert), &settings).unwrap();
+        assert_eq!(out, b"\x1b]777;notify;Message from bob;hi; ]0;pwned \x07");
+
+        settings.desktop = DesktopNotification::Off;
+        settings.bell = true;
+        assert_eq!(sequences(&alert, &settings), "\x07");
+
+        settings.private_message = false;
+        assert_eq!(sequences(&alert, &settings), "");
+        settings.private_message = true;
+        settings.do_not_disturb = true;
+        assert_eq!(sequences(&alert, &settings), "");
+    }
+}
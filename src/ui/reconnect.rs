@@ -0,0 +1,132 @@
+use crate::connection::ServerModel;
+use crate::proto;
+use std::time::Duration;
+
+/// Wait before the first attempt to reconnect; each failed attempt doubles
+/// it, up to `MAX_DELAY`.
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How long to wait before reconnect attempt number `attempt` (from 1).
+pub fn delay(attempt: u32) -> Duration {
+    FIRST_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_DELAY)
+}
+
+/// Our own state on a server, to put back after reconnecting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionRestore {
+    /// Channel names below the root, as channel ids change on restarts.
+    pub channel: Vec<String>,
+    pub self_mute: bool,
+    pub self_deaf: bool,
+    pub comment: String,
+    pub listening: Vec<Vec<String>>,
+}
+
+impl SessionRestore {
+    pub fn capture(model: &ServerModel) -> Option<Self> {
+        let own = model.own_user()?;
+        Some(Self {
+            channel: model.channel_path(own.channel_id),
+            self_mute: own.self_mute,
+            self_deaf: own.self_deaf,
+            comment: own.comment.clone(),
+            listening: own.listening.iter().map(|&id| model.channel_path(id)).collect(),
+        })
+    }
+
+    /// The `UserState` that restores everything still possible on the
+    /// server as it is now, and the channels that no longer exist.
+    pub fn user_state(&self, model: &ServerModel) -> (proto::UserState, Vec<String>) {
+        let mut missing = Vec::new();
+        let mut find = |path: &Vec<String>| {
+            let id = model.channel_by_path(path);
+            if id.is_none() {
+                missing.push(path.join("/"));
+            }
+            id
+        };
+        let channel_id = find(&self.channel).filter(|&id| {
+            model.own_user().is_some_and(|own| own.channel_id != id)
+        });
+        let listening_channel_add = self.listening.iter().filter_map(&mut find).collect();
+        let state = proto::UserState {
+            session: model.session,
+            channel_id,
+            self_mute: self.self_mute.then_some(true),
+            self_deaf: self.self_deaf.then_some(true),
+            comment: (!self.comment.is_empty()).then(|| self.comment.clone()),
+            listening_channel_add,
+            ..Default::default()
+        };
+        (state, missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> ServerModel {
+        let mut model = ServerModel::with(
+            &[(0, 0, "Root"), (1, 0, "Lobby"), (2, 1, "Games")],
+            &[(7, "alice", 2)],
+        );
+        model.session = Some(7);
+        let user = proto::UserState {
+            session: Some(7),
+            self_mute: Some(true),
+            comment: Some("hi".to_string()),
+            listening_channel_add: vec![1],
+            ..Default::default()
+        };
+        model.apply(&user.into());
+        model
+    }
+
+    #[test]
+    fn test_delay() {
+        assert_eq!(delay(1), Duration::from_secs(1));
+        assert_eq!(delay(2), Duration::from_secs(2));
+        assert_eq!(delay(4), Duration::from_secs(8));
+        assert_eq!(delay(7), MAX_DELAY);
+        assert_eq!(delay(u32::MAX), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_capture_and_restore() {
+        let restore = SessionRestore::capture(&model()).unwrap();
+        assert_eq!(restore.channel, ["Lobby", "Games"]);
+        assert!(restore.self_mute && !restore.self_deaf);
+        assert_eq!(restore.listening, [vec!["Lobby".to_string()]]);
+
+        // After reconnecting we start out in the root channel.
+        let mut fresh = model();
+        let moved = proto::UserState {
+            session: Some(7),
+            channel_id: Some(0),
+            self_mute: Some(false),
+            listening_channel_remove: vec![1],
+            ..Default::default()
+        };
+        fresh.apply(&moved.into());
+        let (state, missing) = restore.user_state(&fresh);
+        assert_eq!(state.session, Some(7));
+        assert_eq!(state.channel_id, Some(2));
+        assert_eq!(state.self_mute, Some(true));
+        assert_eq!(state.self_deaf, None);
+        assert_eq!(state.comment.as_deref(), Some("hi"));
+        assert_eq!(state.listening_channel_add, [1]);
+        assert!(missing.is_empty());
+
+        let gone = SessionRestore {
+            channel: vec!["Gone".to_string()],
+            ..restore
+        };
+        let (state, missing) = gone.user_state(&fresh);
+        assert_eq!(state.channel_id, None);
+        assert_eq!(missing, ["Gone"]);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::connection::{ServerModel, TlsInfo, Trust};
+use crate::html;
+use crate::ping::Probe;
+use crate::ui::connect_dialog::format_address;
+use crate::ui::settings::Theme;
+use crate::{lan, public};
+use crossterm::event::KeyCode;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Paragraph, Wrap},
+};
+
+/// The server picked in one of the server lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetailTarget {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+}
+
+/// What the detail view shows and how far it is scrolled.
+#[derive(Debug, Default)]
+pub struct ServerDetailsState {
+    pub target: Option<DetailTarget>,
+    /// Addresses and ping of the target, once probed.
+    pub probe: Option<Probe>,
+    scroll: u16,
+}
+
+impl ServerDetailsState {
+    /// Switches to another server. Returns whether it changed.
+    pub fn set_target(&mut self, target: Option<DetailTarget>) -> bool {
+        if self.target == target {
+            return false;
+        }
+        self.target = target;
+        self.probe = None;
+        self.scroll = 0;
+        true
+    }
+
+    /// Handles the scrolling keys. Returns whether the key was consumed.
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Down => self.scroll = self.scroll.saturating_add(1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::Home => self.scroll = 0,
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn heading(text: &str) -> Line<'static> {
+    Line::from(text.to_string()).bold().underlined()
+}
+
+fn field(label: &str, value: impl Into<String>) -> Line<'static> {
+    Line::from(vec![
+        Span::raw(format!("{:<16}", format!("{}:", label))).bold(),
+        Span::raw(value.into()),
+    ])
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Everything known about the selected server without connecting to it.
+pub fn server_lines(
+    target: &DetailTarget,
+    public: Option<&public::ServerInfo>,
+    lan: Option<&lan::ServerInfo>,
+    probe: Option<&Probe>,
+    theme: &Theme,
+) -> Vec<Line<'static>> {
+    let mut lines = vec![
+        heading("Selected server"),
+        field("Name", target.name.clone()),
+        field("Address", format_address(&target.host, target.port)),
+    ];
+    if let Some(lan) = lan {
+        lines.push(field("Announced IP", lan.ip.to_string()));
+    }
+    lines.push(match probe {
+        Some(probe) if !probe.addresses.is_empty() => field(
+            "Resolves to",
+            probe
+                .addresses
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        Some(_) => field("Resolves to", "-"),
+        None => field("Resolves to", "resolving..."),
+    });
+    if let Some(server) = public {
+        let location = [&server.country, &server.region]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        lines.push(field("Location", location));
+        if !server.url.is_empty() {
+            lines.push(field("Website", server.url.clone()));
+        }
+        lines.push(Line::from(vec![
+            Span::raw(format!("{:<16}", "Certificate:")).bold(),
+            if server.ca {
+                Span::styled("CA-signed", theme.fg(theme.good))
+            } else {
+                Span::styled("self-signed or unknown", theme.fg(theme.busy))
+            },
+        ]));
+    }
+
+    let reply = probe.and_then(|p| p.reply);
+    let ping = reply.map(|r| r.latency).or(public.and_then(|s| s.ping));
+    lines.push(field(
+        "Ping",
+        ping.map_or("-".to_string(), |p| format!("{}ms", p.as_millis())),
+    ));
+    let users = match (reply, public) {
+        (Some(reply), _) => Some((reply.users, reply.max_users)),
+        (None, Some(server)) if server.ping.is_some() => Some((server.users, server.max_users)),
+        _ => None,
+    };
+    lines.push(field(
+        "Users",
+        users.map_or("-".to_string(), |(users, max)| format!("{}/{}", users, max)),
+    ));
+    let version = reply
+        .map(|r| r.version_string())
+        .or_else(|| public.and_then(|s| s.version.clone()));
+    lines.push(field("Version", version.unwrap_or_else(|| "-".to_string())));
+    if let Some(reply) = reply {
+        lines.push(field("Max bandwidth", format!("{} kbit/s", reply.max_bandwidth / 1000)));
+    }
+    if let Some(error) = probe.and_then(|p| p.error.as_ref()) {
+        lines.push(Line::from(error.clone()).style(theme.fg(theme.bad)));
+    }
+    lines
+}
+
+/// The connected server as it presented itself.
+pub fn session_lines(model: &ServerModel, tls: &TlsInfo, theme: &Theme) -> Vec<Line<'static>> {
+    let mut lines = vec![heading("Connected server")];
+    let version = match (&model.server_release, &model.server_version) {
+        (Some(release), Some(version)) => format!("{} ({})", release, version),
+        (release, version) => release.clone().or(version.clone()).unwrap_or_else(|| "-".to_string()),
+    };
+    lines.push(field("Version", version));
+    lines.push(field(
+        "Latency",
+        model
+            .tcp_ping
+            .map_or("-".to_string(), |p| format!("{}ms", p.as_millis())),
+    ));
+    lines.push(field("Users online", model.users.len().to_string()));
+
+    lines.push(Line::from(""));
+    lines.push(heading("TLS"));
+    lines.push(field("Protocol", tls.protocol.clone()));
+    lines.push(field("Cipher", tls.cipher.clone()));
+    lines.push(match tls.trust {
+        Trust::Authority => Line::from(vec![
+            Span::raw(format!("{:<16}", "Trusted:")).bold(),
+            Span::styled("signed by a certificate authority", theme.fg(theme.good)),
+        ]),
+        Trust::KnownHost => field("Trusted", "same certificate as before"),
+        Trust::FirstUse => Line::from(vec![
+            Span::raw(format!("{:<16}", "Trusted:")).bold(),
+            Span::styled("first connection, remembered now", theme.fg(theme.busy)),
+        ]),
+        Trust::Unchecked => field("Trusted", "not checked"),
+    });
+    match tls.chain.first() {
+        Some(leaf) => lines.push(Line::from(vec![
+            Span::raw(format!("{:<16}", "Fingerprint:")).bold(),
+            Span::styled(leaf.sha1.clone(), theme.fg(theme.accent)),
+        ])),
+        None => lines.push(Line::from("The server sent no certificate.").style(theme.fg(theme.bad))),
+    }
+    for (i, cert) in tls.chain.iter().enumerate() {
+        lines.push(Line::from(""));
+        lines.push(field(&format!("Certificate {}", i + 1), cert.subject.clone()));
+        if cert.is_self_signed() {
+            lines.push(field("  Issued by", "itself (self-signed)"));
+        } else {
+            lines.push(field("  Issued by", cert.issuer.clone()));
+        }
+        lines.push(field("  Valid", format!("{} to {}", cert.not_before, cert.not_after)));
+        lines.push(field("  SHA-1", cert.sha1.clone()));
+        lines.push(field("  SHA-256", cert.sha256.clone()));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(heading("Limits"));
+    let config = model.config.clone().unwrap_or_default();
+    let limit = |value: Option<u32>, unit: &str| {
+        value.map_or("-".to_string(), |v| format!("{}{}", v, unit))
+    };
+    let bandwidth = model.max_bandwidth.or(config.max_bandwidth).map(|b| b / 1000);
+    lines.push(field("Max bandwidth", limit(bandwidth, " kbit/s")));
+    lines.push(field("Max users", limit(config.max_users, "")));
+    lines.push(field("Message length", limit(config.message_length, " characters")));
+    lines.push(field("Image length", limit(config.image_message_length, " bytes")));
+    lines.push(field("HTML", config.allow_html.map_or("-", yes_no)));
+    lines.push(field("Recording", config.recording_allowed.map_or("-", yes_no)));
+
+    let welcome = html::to_plain_text(&model.welcome_text);
+    if !welcome.trim().is_empty() {
+        lines.push(Line::from(""));
+        lines.push(heading("Welcome text"));
+        lines.extend(welcome.lines().map(|l| Line::from(l.to_string())));
+    }
+    lines
+}
+
+pub fn render(
+    frame: &mut Frame,
+    area: Rect,
+    lines: Vec<Line<'static>>,
+    state: &ServerDetailsState,
+    theme: &Theme,
+    has_focus: bool,
+) {
+    let text = if lines.is_empty() {
+        vec![Line::from("Select a server to see its details.").style(theme.fg(theme.muted))]
+    } else {
+        lines
+    };
+    let widget = Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .scroll((state.scroll, 0))
+        .block(
+            Block::default()
+                .title("Server Details")
+                .title_bottom(" Up/Down scroll ")
+                .borders(Borders::ALL)
+                .border_style(theme.border(has_focus))
+                .border_type(if has_focus {
+                    ratatui::widgets::BorderType::Double
+                } else {
+                    ratatui::widgets::BorderType::Plain
+                }),
+        );
+    frame.render_widget(widget, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping::PingReply;
+    use std::time::Duration;
+
+    fn text(lines: &[Line]) -> String {
+        lines
+            .iter()
+            .map(|l| l.spans.iter().map(|s| s.content.as_ref()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_server_lines() {
+        let target = DetailTarget {
+            name: "Example".to_string(),
+            host: "voice.example.org".to_string(),
+            port: 64738,
+        };
+        let theme = Theme::default();
+        let lines = text(&server_lines(&target, None, None, None, &theme));
+        assert!(lines.contains("Address:        voice.example.org"));
+        assert!(lines.contains("resolving..."));
+        assert!(lines.contains("Version:        -"));
+
+        let probe = Probe {
+            addresses: vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()],
+            reply: Some(PingReply {
+                version: 0x0001_0500,
+                users: 3,
+                max_users: 50,
+                max_bandwidth: 72000,
+                latency: Duration::from_millis(42),
+            }),
+            error: None,
+        };
+        let lines = text(&server_lines(&target, None, None, Some(&probe), &theme));
+        assert!(lines.contains("192.0.2.1, 2001:db8::1"));
+        assert!(lines.contains("42ms"));
+        assert!(lines.contains("3/50"));
+        assert!(lines.contains("1.5.0"));
+        assert!(lines.contains("72 kbit/s"));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut state = ServerDetailsState::default();
+        state.handle_key(KeyCode::PageDown);
+        assert_eq!(state.scroll, 10);
+        assert!(state.set_target(Some(DetailTarget {
+            name: String::new(),
+            host: "a".to_string(),
+            port: 1,
+        })));
+        assert_eq!(state.scroll, 0);
+        assert!(!state.handle_key(KeyCode::Enter));
+    }
+}
@@ -1,42 +1,153 @@
+use crate::server_book::ServerBook;
+use crate::ui::connect_dialog::format_address;
+use crate::ui::settings::Theme;
 use crate::{lan, public};
+use crossterm::event::KeyCode;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Row, Table},
+    widgets::{
+        Block, Borders, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Table, TableState,
+    },
 };
+use std::cell::Cell;
+
+/// Rows above the first server: the top border and the header.
+const TABLE_TOP: u16 = 2;
+
+/// Scroll position of a server table, remembered between frames so the
+/// view only moves when the selection leaves it.
+#[derive(Debug, Default)]
+pub struct TableScroll {
+    offset: Cell<usize>,
+    /// Rows that fit in the table, remembered from the last render.
+    height: Cell<usize>,
+}
+
+impl TableScroll {
+    /// The first row in view.
+    pub fn offset(&self) -> usize {
+        self.offset.get()
+    }
+
+    /// The row at line `row` of a table drawn at `area`, if it is a row.
+    pub fn row_at(&self, area: Rect, row: u16) -> Option<usize> {
+        let line = row.checked_sub(area.y + TABLE_TOP)?;
+        (line < area.height.saturating_sub(TABLE_TOP + 1)).then(|| self.offset() + line as usize)
+    }
+
+    /// Moves `selected` within `len` rows for the navigation keys. Returns
+    /// whether the key was one of them.
+    pub fn navigate(&self, code: KeyCode, selected: &mut usize, len: usize) -> bool {
+        let last = len.saturating_sub(1);
+        let page = self.height.get().max(1);
+        *selected = match code {
+            KeyCode::Up => selected.saturating_sub(1),
+            KeyCode::Down => (*selected + 1).min(last),
+            KeyCode::PageUp => selected.saturating_sub(page),
+            KeyCode::PageDown => (*selected + page).min(last),
+            KeyCode::Home => 0,
+            KeyCode::End => last,
+            _ => return false,
+        };
+        true
+    }
+
+    /// Draws `table` of `len` rows scrolled to show `selected`, with a
+    /// scrollbar when not all rows fit.
+    pub fn render(&self, frame: &mut Frame, area: Rect, table: Table, selected: usize, len: usize) {
+        let height = area.height.saturating_sub(TABLE_TOP + 1) as usize;
+        let mut state = TableState::default()
+            .with_offset(self.offset.get().min(len.saturating_sub(height)))
+            .with_selected((len > 0).then_some(selected));
+        frame.render_stateful_widget(table, area, &mut state);
+        self.offset.set(state.offset());
+        self.height.set(height);
+
+        if len > height {
+            let mut scrollbar = ScrollbarState::new(len.saturating_sub(height)).position(state.offset());
+            frame.render_stateful_widget(
+                Scrollbar::new(ScrollbarOrientation::VerticalRight).begin_symbol(None).end_symbol(None),
+                area.inner(Margin { vertical: 1, horizontal: 0 }),
+                &mut scrollbar,
+            );
+        }
+    }
+}
+
+/// Favourites, followed by recently used servers that aren't favourites.
+pub fn render_favourite_server_list(
+    book: &ServerBook,
+    theme: &Theme,
+    has_focus: bool,
+) -> Table<'static> {
+    let header = Row::new(vec!["Name", "Address", "Username"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+
+    let favourites = book
+        .favourites
+        .iter()
+        .map(|f| (f.name.clone(), format_address(&f.host, f.port), f.username.clone(), false));
+    let recent = book
+        .recent_only()
+        .map(|r| (r.host.clone(), format_address(&r.host, r.port), r.username.clone(), true));
+    let rows: Vec<Row> = favourites
+        .chain(recent)
+        .map(|(name, address, username, recent)| {
+            let style = if recent {
+                theme.fg(theme.muted)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![name, address, username]).style(style)
+        })
+        .collect();
+    let count = rows.len();
+
+    Table::new(rows, [Constraint::Percentage(40), Constraint::Percentage(40), Constraint::Percentage(20)])
+        .header(header)
+        .highlight_style(theme.selected())
+        .block(
+            Block::default()
+                .title(format!("Favourites & Recent ({})", count))
+                .title_bottom(" [A]dd [E]dit [D]elete ")
+                .borders(Borders::ALL)
+                .border_style(theme.border(has_focus))
+                .border_type(if has_focus {
+                    ratatui::widgets::BorderType::Double
+                } else {
+                    ratatui::widgets::BorderType::Plain
+                }),
+        )
+}
 
 pub fn render_lan_server_list<'a>(
     servers: &'a [lan::ServerInfo],
+    theme: &Theme,
     has_focus: bool,
-    selected_index: usize,
 ) -> Table<'a> {
     let header = Row::new(vec!["Server Name", "Host", "IP", "Port"])
         .style(Style::default().add_modifier(Modifier::BOLD));
 
     let rows: Vec<Row> = servers
         .iter()
-        .enumerate()
-        .map(|(i, s)| {
-            let style = if i == selected_index {
-                Style::default().add_modifier(Modifier::REVERSED)
-            } else {
-                Style::default()
-            };
+        .map(|s| {
             Row::new(vec![
                 s.name.clone(),
                 s.host.clone(),
                 s.ip.to_string(),
                 s.port.to_string(),
             ])
-            .style(style)
         })
         .collect();
 
     Table::new(rows, [Constraint::Percentage(40), Constraint::Percentage(30), Constraint::Percentage(20), Constraint::Percentage(10)])
         .header(header)
+        .highlight_style(theme.selected())
         .block(
             Block::default()
-                .title("LAN Servers")
+                .title(format!("LAN Servers ({})", servers.len()))
                 .borders(Borders::ALL)
+                .border_style(theme.border(has_focus))
                 .border_type(if has_focus {
                     ratatui::widgets::BorderType::Double
                 } else {
@@ -47,36 +158,49 @@ pub fn render_lan_server_list<'a>(
 
 pub fn render_public_server_list<'a>(
     servers: &'a [public::ServerInfo],
+    view: &[usize],
+    theme: &Theme,
     has_focus: bool,
-    selected_index: usize,
+    status: &str,
+    filter_line: String,
 ) -> Table<'a> {
-    let header = Row::new(vec!["Server Name", "Address", "Users"])
+    let header = Row::new(vec!["Server Name", "Country", "Ping", "Users"])
         .style(Style::default().add_modifier(Modifier::BOLD));
 
-    let rows: Vec<Row> = servers
+    let rows: Vec<Row> = view
         .iter()
-        .enumerate()
-        .map(|(i, s)| {
-            let style = if i == selected_index {
-                Style::default().add_modifier(Modifier::REVERSED)
+        .map(|&server_index| {
+            let s = &servers[server_index];
+            let name = if s.ca {
+                format!("\u{2713} {}", s.name)
             } else {
-                Style::default()
+                s.name.clone()
             };
-            Row::new(vec![
-                s.name.clone(),
-                format!("{}:{}", s.host, s.port),
-                format!("{}/{}", s.users, s.max_users),
-            ])
-            .style(style)
+            let (ping, users) = match s.ping {
+                Some(ping) => (
+                    format!("{}ms", ping.as_millis()),
+                    format!("{}/{}", s.users, s.max_users),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            Row::new(vec![name, s.country.clone(), ping, users])
         })
         .collect();
 
-    Table::new(rows, [Constraint::Percentage(50), Constraint::Percentage(30), Constraint::Percentage(20)])
+    Table::new(rows, [Constraint::Percentage(45), Constraint::Percentage(25), Constraint::Percentage(15), Constraint::Percentage(15)])
         .header(header)
+        .highlight_style(theme.selected())
         .block(
             Block::default()
-                .title("Public Servers")
+                .title(format!(
+                    "Public Servers {}/{} ({})",
+                    view.len(),
+                    servers.len(),
+                    status
+                ))
+                .title_bottom(filter_line)
                 .borders(Borders::ALL)
+                .border_style(theme.border(has_focus))
                 .border_type(if has_focus {
                     ratatui::widgets::BorderType::Double
                 } else {
@@ -84,3 +208,35 @@ pub fn render_public_server_list<'a>(
                 }),
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_navigate() {
+        let scroll = TableScroll::default();
+        scroll.height.set(10);
+        let mut selected = 0;
+        assert!(scroll.navigate(KeyCode::PageDown, &mut selected, 25));
+        assert_eq!(selected, 10);
+        scroll.navigate(KeyCode::End, &mut selected, 25);
+        assert_eq!(selected, 24);
+        scroll.navigate(KeyCode::Down, &mut selected, 25);
+        assert_eq!(selected, 24);
+        scroll.navigate(KeyCode::PageUp, &mut selected, 25);
+        assert_eq!(selected, 14);
+        scroll.navigate(KeyCode::Home, &mut selected, 25);
+        assert_eq!(selected, 0);
+        assert!(!scroll.navigate(KeyCode::Enter, &mut selected, 25));
+        scroll.navigate(KeyCode::End, &mut selected, 0);
+        assert_eq!(selected, 0);
+
+        scroll.offset.set(5);
+        let area = Rect::new(0, 10, 30, 8);
+        assert_eq!(scroll.row_at(area, 11), None);
+        assert_eq!(scroll.row_at(area, 12), Some(5));
+        assert_eq!(scroll.row_at(area, 16), Some(9));
+        assert_eq!(scroll.row_at(area, 17), None);
+    }
+}
@@ -0,0 +1,572 @@
+use crate::paths;
+use anyhow::{anyhow, Context, Result};
+use crossterm::event::KeyCode;
+use ratatui::style::{Color, Modifier, Style};
+use std::fmt;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const SETTINGS_FILE: &str = "client.ini";
+
+/// Keys for the app-wide actions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+    pub quit: KeyCode,
+    pub next_pane: KeyCode,
+    pub toggle_server_log: KeyCode,
+    pub server_details: KeyCode,
+    pub settings: KeyCode,
+    pub known_hosts: KeyCode,
+    pub previous_tab: KeyCode,
+    pub next_tab: KeyCode,
+    pub close_tab: KeyCode,
+    pub refresh_public_list: KeyCode,
+    pub edit_local_server: KeyCode,
+    pub regenerate_certificate: KeyCode,
+    pub save_server_log: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            quit: KeyCode::Char('q'),
+            next_pane: KeyCode::Tab,
+            toggle_server_log: KeyCode::Char('\\'),
+            server_details: KeyCode::Char('i'),
+            settings: KeyCode::F(2),
+            known_hosts: KeyCode::F(3),
+            previous_tab: KeyCode::F(5),
+            next_tab: KeyCode::F(6),
+            close_tab: KeyCode::F(8),
+            refresh_public_list: KeyCode::Char('r'),
+            edit_local_server: KeyCode::Char('e'),
+            regenerate_certificate: KeyCode::Char('g'),
+            save_server_log: KeyCode::Char('w'),
+        }
+    }
+}
+
+/// Colours used across the panes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Borders of the focused pane and our own chat messages.
+    pub accent: Color,
+    /// Background of selected rows; `Reset` shows them in reverse video.
+    pub highlight: Color,
+    pub good: Color,
+    pub bad: Color,
+    /// Transitions, warnings and unread messages.
+    pub busy: Color,
+    /// Timestamps, hints and other secondary text.
+    pub muted: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            accent: Color::Reset,
+            highlight: Color::Reset,
+            good: Color::Green,
+            bad: Color::Red,
+            busy: Color::Yellow,
+            muted: Color::DarkGray,
+        }
+    }
+}
+
+impl Theme {
+    pub fn border(&self, focus: bool) -> Style {
+        if focus {
+            Style::default().fg(self.accent)
+        } else {
+            Style::default()
+        }
+    }
+
+    pub fn selected(&self) -> Style {
+        match self.highlight {
+            Color::Reset => Style::default().add_modifier(Modifier::REVERSED),
+            color => Style::default().bg(color),
+        }
+    }
+
+    pub fn fg(&self, color: Color) -> Style {
+        Style::default().fg(color)
+    }
+}
+
+/// Pane sizes, as percentages of the space they split or in rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutSettings {
+    /// Width of the server panes on the left.
+    pub left_width: u16,
+    pub local_server_height: u16,
+    pub favourites_height: u16,
+    pub lan_height: u16,
+    pub client_log_height: u16,
+    /// Width of the channel tree next to the chat.
+    pub tree_width: u16,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        Self {
+            left_width: 30,
+            local_server_height: 5,
+            favourites_height: 30,
+            lan_height: 30,
+            client_log_height: 5,
+            tree_width: 35,
+        }
+    }
+}
+
+impl LayoutSettings {
+    /// Checks what the ranges of single settings can't: the favourites and
+    /// LAN panes share the rest of the left column with the public list.
+    fn check(&self) -> Result<(), String> {
+        if self.favourites_height + self.lan_height > 100 {
+            return Err(format!(
+                "favourites_height and lan_height add up to {}%, more than 100%",
+                self.favourites_height + self.lan_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Preferences for voice. The client doesn't play or record audio yet, so
+/// these are only stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioSettings {
+    /// Empty for the system default.
+    pub input_device: String,
+    pub output_device: String,
+    pub volume: u16,
+    pub push_to_talk: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            input_device: String::new(),
+            output_device: String::new(),
+            volume: 100,
+            push_to_talk: false,
+        }
+    }
+}
+
+/// The escape sequence used for desktop notifications. Terminals that
+/// don't know it ignore it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DesktopNotification {
+    Off,
+    /// `OSC 9`, understood by iTerm2, kitty, WezTerm and Windows Terminal.
+    Osc9,
+    /// `OSC 777;notify`, understood by urxvt, foot and VTE based terminals.
+    Osc777,
+}
+
+impl fmt::Display for DesktopNotification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Osc9 => "osc9",
+            Self::Osc777 => "osc777",
+        })
+    }
+}
+
+/// Which events alert, and how. Alerts always highlight the tab they
+/// happened in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationSettings {
+    /// Only highlight tabs, without bell or desktop notifications.
+    pub do_not_disturb: bool,
+    pub bell: bool,
+    pub desktop: DesktopNotification,
+    /// Someone wrote our name in a channel.
+    pub mention: bool,
+    pub private_message: bool,
+    /// Someone came into our channel.
+    pub user_joined: bool,
+    pub kicked: bool,
+    /// Someone else moved us to another channel.
+    pub moved: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            do_not_disturb: false,
+            bell: true,
+            desktop: DesktopNotification::Osc9,
+            mention: true,
+            private_message: true,
+            user_joined: true,
+            kicked: true,
+            moved: true,
+        }
+    }
+}
+
+/// The user-editable client settings file. Favourites and recent servers
+/// are kept apart in [`crate::server_book::ServerBook`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientSettings {
+    /// Empty to use the login name.
+    pub username: String,
+    pub keys: KeyBindings,
+    pub theme: Theme,
+    pub layout: LayoutSettings,
+    pub audio: AudioSettings,
+    pub notifications: NotificationSettings,
+}
+
+/// Parses key names like `q`, `Tab`, `F2` or `Space`.
+pub fn parse_key(name: &str) -> Result<KeyCode, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "enter" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        lower => lower
+            .strip_prefix('f')
+            .and_then(|n| n.parse().ok())
+            .filter(|n| (1..=12).contains(n))
+            .map(KeyCode::F)
+            .ok_or_else(|| format!("unknown key '{}'", name))?,
+    })
+}
+
+/// The name [`parse_key`] reads back.
+pub fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    Color::from_str(value).map_err(|_| format!("unknown colour '{}'", value))
+}
+
+fn parse_number(value: &str, range: RangeInclusive<u16>) -> Result<u16, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|n| range.contains(n))
+        .ok_or_else(|| format!("expected a number from {} to {}", range.start(), range.end()))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected true or false, not '{}'", value)),
+    }
+}
+
+fn parse_desktop(value: &str) -> Result<DesktopNotification, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "off" | "none" => Ok(DesktopNotification::Off),
+        "osc9" => Ok(DesktopNotification::Osc9),
+        "osc777" => Ok(DesktopNotification::Osc777),
+        _ => Err(format!("expected off, osc9 or osc777, not '{}'", value)),
+    }
+}
+
+impl ClientSettings {
+    pub fn default_path() -> PathBuf {
+        paths::config_dir().join(SETTINGS_FILE)
+    }
+
+    /// Every setting as `(section, key, value)`, in file order.
+    pub fn entries(&self) -> Vec<(&'static str, &'static str, String)> {
+        let keys = &self.keys;
+        let theme = &self.theme;
+        let layout = &self.layout;
+        let audio = &self.audio;
+        let notifications = &self.notifications;
+        vec![
+            ("general", "username", self.username.clone()),
+            ("keys", "quit", key_name(keys.quit)),
+            ("keys", "next_pane", key_name(keys.next_pane)),
+            ("keys", "toggle_server_log", key_name(keys.toggle_server_log)),
+            ("keys", "server_details", key_name(keys.server_details)),
+            ("keys", "settings", key_name(keys.settings)),
+            ("keys", "known_hosts", key_name(keys.known_hosts)),
+            ("keys", "previous_tab", key_name(keys.previous_tab)),
+            ("keys", "next_tab", key_name(keys.next_tab)),
+            ("keys", "close_tab", key_name(keys.close_tab)),
+            ("keys", "refresh_public_list", key_name(keys.refresh_public_list)),
+            ("keys", "edit_local_server", key_name(keys.edit_local_server)),
+            ("keys", "regenerate_certificate", key_name(keys.regenerate_certificate)),
+            ("keys", "save_server_log", key_name(keys.save_server_log)),
+            ("theme", "accent", theme.accent.to_string()),
+            ("theme", "highlight", theme.highlight.to_string()),
+            ("theme", "good", theme.good.to_string()),
+            ("theme", "bad", theme.bad.to_string()),
+            ("theme", "busy", theme.busy.to_string()),
+            ("theme", "muted", theme.muted.to_string()),
+            ("layout", "left_width", layout.left_width.to_string()),
+            ("layout", "local_server_height", layout.local_server_height.to_string()),
+            ("layout", "favourites_height", layout.favourites_height.to_string()),
+            ("layout", "lan_height", layout.lan_height.to_string()),
+            ("layout", "client_log_height", layout.client_log_height.to_string()),
+            ("layout", "tree_width", layout.tree_width.to_string()),
+            ("audio", "input_device", audio.input_device.clone()),
+            ("audio", "output_device", audio.output_device.clone()),
+            ("audio", "volume", audio.volume.to_string()),
+            ("audio", "push_to_talk", audio.push_to_talk.to_string()),
+            ("notifications", "do_not_disturb", notifications.do_not_disturb.to_string()),
+            ("notifications", "bell", notifications.bell.to_string()),
+            ("notifications", "desktop", notifications.desktop.to_string()),
+            ("notifications", "mention", notifications.mention.to_string()),
+            ("notifications", "private_message", notifications.private_message.to_string()),
+            ("notifications", "user_joined", notifications.user_joined.to_string()),
+            ("notifications", "kicked", notifications.kicked.to_string()),
+            ("notifications", "moved", notifications.moved.to_string()),
+        ]
+    }
+
+    /// Changes one setting from its text form, keeping the settings as they
+    /// were if the result would be invalid.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        let mut changed = self.clone();
+        changed.apply(section, key, value)?;
+        changed.layout.check()?;
+        *self = changed;
+        Ok(())
+    }
+
+    fn apply(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let keys = &mut self.keys;
+        let theme = &mut self.theme;
+        let layout = &mut self.layout;
+        let audio = &mut self.audio;
+        let notifications = &mut self.notifications;
+        match (section, key) {
+            ("general", "username") => self.username = value.to_string(),
+            ("keys", "quit") => keys.quit = parse_key(value)?,
+            ("keys", "next_pane") => keys.next_pane = parse_key(value)?,
+            ("keys", "toggle_server_log") => keys.toggle_server_log = parse_key(value)?,
+            ("keys", "server_details") => keys.server_details = parse_key(value)?,
+            ("keys", "settings") => keys.settings = parse_key(value)?,
+            ("keys", "known_hosts") => keys.known_hosts = parse_key(value)?,
+            ("keys", "previous_tab") => keys.previous_tab = parse_key(value)?,
+            ("keys", "next_tab") => keys.next_tab = parse_key(value)?,
+            ("keys", "close_tab") => keys.close_tab = parse_key(value)?,
+            ("keys", "refresh_public_list") => keys.refresh_public_list = parse_key(value)?,
+            ("keys", "edit_local_server") => keys.edit_local_server = parse_key(value)?,
+            ("keys", "regenerate_certificate") => keys.regenerate_certificate = parse_key(value)?,
+            ("keys", "save_server_log") => keys.save_server_log = parse_key(value)?,
+            ("theme", "accent") => theme.accent = parse_color(value)?,
+            ("theme", "highlight") => theme.highlight = parse_color(value)?,
+            ("theme", "good") => theme.good = parse_color(value)?,
+            ("theme", "bad") => theme.bad = parse_color(value)?,
+            ("theme", "busy") => theme.busy = parse_color(value)?,
+            ("theme", "muted") => theme.muted = parse_color(value)?,
+            ("layout", "left_width") => layout.left_width = parse_number(value, 10..=90)?,
+            ("layout", "local_server_height") => {
+                layout.local_server_height = parse_number(value, 3..=10)?
+            }
+            ("layout", "favourites_height") => {
+                layout.favourites_height = parse_number(value, 0..=80)?
+            }
+            ("layout", "lan_height") => layout.lan_height = parse_number(value, 0..=80)?,
+            ("layout", "client_log_height") => {
+                layout.client_log_height = parse_number(value, 3..=30)?
+            }
+            ("layout", "tree_width") => layout.tree_width = parse_number(value, 10..=90)?,
+            ("audio", "input_device") => audio.input_device = value.to_string(),
+            ("audio", "output_device") => audio.output_device = value.to_string(),
+            ("audio", "volume") => audio.volume = parse_number(value, 0..=200)?,
+            ("audio", "push_to_talk") => audio.push_to_talk = parse_bool(value)?,
+            ("notifications", "do_not_disturb") => {
+                notifications.do_not_disturb = parse_bool(value)?
+            }
+            ("notifications", "bell") => notifications.bell = parse_bool(value)?,
+            ("notifications", "desktop") => notifications.desktop = parse_desktop(value)?,
+            ("notifications", "mention") => notifications.mention = parse_bool(value)?,
+            ("notifications", "private_message") => {
+                notifications.private_message = parse_bool(value)?
+            }
+            ("notifications", "user_joined") => notifications.user_joined = parse_bool(value)?,
+            ("notifications", "kicked") => notifications.kicked = parse_bool(value)?,
+            ("notifications", "moved") => notifications.moved = parse_bool(value)?,
+            _ => return Err(format!("unknown setting {}.{}", section, key)),
+        }
+        Ok(())
+    }
+
+    /// Reads the settings from INI text; settings it doesn't set keep their
+    /// defaults and unknown ones are ignored.
+    ///
+    /// Only whole lines are comments: values like `#FF8800` or `;` are
+    /// colours and keys, which an INI library would cut off as inline
+    /// comments.
+    pub fn parse(text: &str) -> Result<Self> {
+        let known = Self::default().entries();
+        let mut settings = Self::default();
+        let mut errors = Vec::new();
+        let mut section = String::new();
+        for (number, line) in (1..).zip(text.lines()) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_ascii_lowercase();
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                errors.push(format!("line {}: expected 'key = value'", number));
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            if !known.iter().any(|(s, k, _)| *s == section && *k == key) {
+                continue;
+            }
+            if let Err(e) = settings.apply(&section, &key, value) {
+                errors.push(format!("{}.{}: {}", section, key, e));
+            }
+        }
+        if let Err(e) = settings.layout.check() {
+            errors.push(format!("layout: {}", e));
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!(errors.join("; ")));
+        }
+        Ok(settings)
+    }
+
+    /// The settings as INI text.
+    pub fn to_ini(&self) -> String {
+        let mut text = String::new();
+        let mut current = "";
+        for (section, key, value) in self.entries() {
+            if section != current {
+                if !current.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(&format!("[{}]\n", section));
+                current = section;
+            }
+            text.push_str(&format!("{} = {}\n", key, value));
+        }
+        text
+    }
+
+    /// Reads the settings file, falling back to defaults if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text)
+                .with_context(|| format!("Invalid client settings in {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_ini())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        for name in ["q", "\\", "Tab", "F2", "Space", "PageUp", "Esc"] {
+            assert_eq!(key_name(parse_key(name).unwrap()), name);
+        }
+        assert_eq!(parse_key("f12"), Ok(KeyCode::F(12)));
+        assert!(parse_key("F13").is_err());
+        assert!(parse_key("Hyper").is_err());
+    }
+
+    #[test]
+    fn test_parse_and_round_trip() {
+        let settings = ClientSettings::parse(
+            "[general]\nusername = alice\n\n[keys]\nquit = F10\n\n[theme]\naccent = #FF8800\n\n\
+             [layout]\nleft_width = 40\n\n[audio]\npush_to_talk = yes\n\n[unknown]\nkey = 1\n\
+             [notifications]\ndesktop = OSC777\nuser_joined = off\n\
+             ; comment\n[keys]\nsave_server_log = ;\n",
+        )
+        .unwrap();
+        assert_eq!(settings.username, "alice");
+        assert_eq!(settings.keys.quit, KeyCode::F(10));
+        assert_eq!(settings.keys.next_pane, KeyCode::Tab);
+        assert_eq!(settings.keys.save_server_log, KeyCode::Char(';'));
+        assert_eq!(settings.theme.accent, Color::Rgb(0xff, 0x88, 0));
+        assert_eq!(settings.layout.left_width, 40);
+        assert!(settings.audio.push_to_talk);
+        assert_eq!(settings.notifications.desktop, DesktopNotification::Osc777);
+        assert!(!settings.notifications.user_joined && settings.notifications.mention);
+        assert_eq!(ClientSettings::parse(&settings.to_ini()).unwrap(), settings);
+
+        let error = ClientSettings::parse("[layout]\nleft_width = 95\n[theme]\nbad = puce\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("theme.bad: unknown colour 'puce'"));
+        assert!(error.contains("layout.left_width"));
+
+        // Pane heights may be set in either order as long as they fit.
+        let settings =
+            ClientSettings::parse("[layout]\nfavourites_height = 70\nlan_height = 20\n").unwrap();
+        assert_eq!(settings.layout.favourites_height, 70);
+        let error = ClientSettings::parse("[layout]\nfavourites_height = 70\nlan_height = 40\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("add up to 110%"));
+    }
+
+    #[test]
+    fn test_set_rejects_overfull_left_column() {
+        let mut settings = ClientSettings::default();
+        settings.set("layout", "favourites_height", "70").unwrap();
+        assert!(settings.set("layout", "lan_height", "31").is_err());
+        assert_eq!(settings.layout.lan_height, 30);
+        settings.set("layout", "lan_height", "30").unwrap();
+        assert!(settings.set("layout", "favourites_height", "80").is_err());
+        assert_eq!(settings.layout.favourites_height, 70);
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        assert_eq!(ClientSettings::load_from(&path).unwrap(), ClientSettings::default());
+
+        let mut settings = ClientSettings::default();
+        settings.set("audio", "input_device", "USB Mic").unwrap();
+        settings.save_to(&path).unwrap();
+        assert_eq!(ClientSettings::load_from(&path).unwrap(), settings);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::ui::settings::ClientSettings;
+use crossterm::event::KeyCode;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// What the settings screen wants the app to do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SettingsAction {
+    None,
+    /// A setting changed and is already applied.
+    Changed,
+    Close,
+}
+
+/// Popup listing every client setting. Edits apply as soon as they are
+/// confirmed.
+#[derive(Default)]
+pub struct SettingsScreen {
+    selected: usize,
+    /// The value being typed, while editing the selected setting.
+    editing: Option<String>,
+    error: Option<String>,
+}
+
+impl SettingsScreen {
+    pub fn handle_key(&mut self, code: KeyCode, settings: &mut ClientSettings) -> SettingsAction {
+        let entries = settings.entries();
+        let (section, key, value) = &entries[self.selected];
+        if let Some(text) = &mut self.editing {
+            match code {
+                KeyCode::Esc => self.editing = None,
+                KeyCode::Enter => {
+                    let result = settings.set(section, key, text);
+                    self.editing = None;
+                    return match result {
+                        Ok(()) => {
+                            self.error = None;
+                            SettingsAction::Changed
+                        }
+                        Err(e) => {
+                            self.error = Some(format!("{}.{}: {}", section, key, e));
+                            SettingsAction::None
+                        }
+                    };
+                }
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => {}
+            }
+            return SettingsAction::None;
+        }
+
+        match code {
+            KeyCode::Esc => return SettingsAction::Close,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(entries.len() - 1),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = entries.len() - 1,
+            KeyCode::Enter => {
+                self.error = None;
+                self.editing = Some(value.clone());
+            }
+            KeyCode::Delete => {
+                let default = &ClientSettings::default().entries()[self.selected].2;
+                if default != value {
+                    settings.set(section, key, default).ok();
+                    return SettingsAction::Changed;
+                }
+            }
+            _ => {}
+        }
+        SettingsAction::None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, settings: &ClientSettings) {
+        let popup = super::centered_rect(area, 64, area.height.saturating_sub(4).max(10));
+        let theme = &settings.theme;
+
+        let mut lines = Vec::new();
+        let mut selected_line = 0;
+        let mut current = "";
+        for (i, (section, key, value)) in settings.entries().into_iter().enumerate() {
+            if section != current {
+                if !current.is_empty() {
+                    lines.push(Line::from(""));
+                }
+                lines.push(Line::from(format!("[{}]", section)).bold());
+                current = section;
+            }
+            let value = match &self.editing {
+                Some(text) if i == self.selected => format!("{}_", text),
+                _ => value,
+            };
+            let line = Line::from(format!("  {:<24}{}", key, value));
+            if i == self.selected {
+                selected_line = lines.len();
+                lines.push(line.style(theme.selected()));
+            } else {
+                lines.push(line);
+            }
+        }
+
+        let height = popup.height.saturating_sub(2) as usize;
+        let scroll = (selected_line + 1).saturating_sub(height);
+        let hint = match (&self.error, &self.editing) {
+            (Some(error), _) => Line::from(format!(" {} ", error)).style(theme.fg(theme.bad)),
+            (None, Some(_)) => Line::from(" Enter apply, Esc cancel ").style(theme.fg(theme.muted)),
+            (None, None) => Line::from(" Enter edit, Del reset, Esc close ")
+                .style(theme.fg(theme.muted)),
+        };
+
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).scroll((scroll as u16, 0)).block(
+                Block::default()
+                    .title(format!("Settings ({})", ClientSettings::default_path().display()))
+                    .title_bottom(hint)
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Double)
+                    .border_style(theme.border(true)),
+            ),
+            popup,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_and_reset() {
+        let mut settings = ClientSettings::default();
+        let mut screen = SettingsScreen::default();
+        // Username is the first setting, the quit key the second.
+        screen.handle_key(KeyCode::Down, &mut settings);
+        screen.handle_key(KeyCode::Enter, &mut settings);
+        screen.handle_key(KeyCode::Backspace, &mut settings);
+        screen.handle_key(KeyCode::Char('F'), &mut settings);
+        screen.handle_key(KeyCode::Char('9'), &mut settings);
+        assert_eq!(screen.handle_key(KeyCode::Enter, &mut settings), SettingsAction::Changed);
+        assert_eq!(settings.keys.quit, KeyCode::F(9));
+
+        screen.handle_key(KeyCode::Enter, &mut settings);
+        screen.handle_key(KeyCode::Char('x'), &mut settings);
+        assert_eq!(screen.handle_key(KeyCode::Enter, &mut settings), SettingsAction::None);
+        assert!(screen.error.is_some());
+        assert_eq!(settings.keys.quit, KeyCode::F(9));
+
+        assert_eq!(screen.handle_key(KeyCode::Delete, &mut settings), SettingsAction::Changed);
+        assert_eq!(settings.keys.quit, KeyCode::Char('q'));
+        assert_eq!(screen.handle_key(KeyCode::Esc, &mut settings), SettingsAction::Close);
+    }
+}
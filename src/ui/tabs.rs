@@ -0,0 +1,261 @@
+use crate::connection::{ServerModel, TlsInfo};
+use crate::proto::Message;
+use crate::ui::channel_tree::ChannelTreeState;
+use crate::ui::chat::ChatState;
+use crate::ui::client::{ConnectionInfo, ConnectionState};
+use crate::ui::connect_dialog::format_address;
+use crate::ui::reconnect::{self, SessionRestore};
+use crate::ui::settings::Theme;
+use ratatui::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Identifies a tab for as long as it is open, unlike its position.
+pub type TabId = u64;
+
+/// A log line and when it was written, to merge a tab's log with the
+/// app-wide one.
+pub type LogLine = (Instant, String);
+
+/// How many lines a tab's log and the client log keep.
+const LOG_LIMIT: usize = 1000;
+
+/// Appends to a tab's or the client log, dropping the oldest line once it
+/// is full.
+pub fn push_log(log: &mut Vec<LogLine>, message: String) {
+    log.push((Instant::now(), message));
+    if log.len() > LOG_LIMIT {
+        log.remove(0);
+    }
+}
+
+const SEPARATOR: &str = "│";
+
+/// One server session with its own channel tree, chat and log.
+pub struct SessionTab {
+    pub id: TabId,
+    /// What happened in this session; app-wide messages go to the client log.
+    pub log: Vec<LogLine>,
+    /// Whether something was logged since the tab was last shown or left.
+    pub activity: bool,
+    /// Whether an alert was raised since then.
+    pub alert: bool,
+    /// Model of the server this tab is connected to, if any.
+    pub connection: Option<Arc<Mutex<ServerModel>>>,
+    /// Host and port of the connected server.
+    pub server_address: Option<(String, u16)>,
+    /// What the TLS handshake with the connected server negotiated.
+    pub tls: Option<TlsInfo>,
+    /// Channel from an opened URL to join once connected.
+    pub pending_channel: Option<Vec<String>>,
+    pub channel_tree: ChannelTreeState,
+    pub chat: ChatState,
+    /// The connection being made, to retry after a certificate warning.
+    pub connecting: Option<ConnectionInfo>,
+    pub connection_state: ConnectionState,
+    /// How we connected to the current server, to reconnect the same way.
+    pub session_info: Option<ConnectionInfo>,
+    /// Our channel and settings from before the connection dropped.
+    pub restore: Option<SessionRestore>,
+    /// Failed attempts to reconnect since the connection dropped.
+    pub reconnect_attempts: u32,
+}
+
+impl SessionTab {
+    pub fn new(id: TabId) -> Self {
+        Self {
+            id,
+            log: Vec::new(),
+            activity: false,
+            alert: false,
+            connection: None,
+            server_address: None,
+            tls: None,
+            pending_channel: None,
+            channel_tree: ChannelTreeState::default(),
+            chat: ChatState::default(),
+            connecting: None,
+            connection_state: ConnectionState::Disconnected,
+            session_info: None,
+            restore: None,
+            reconnect_attempts: 0,
+        }
+    }
+
+    /// The server the tab is for, or that it isn't for any yet.
+    pub fn title(&self) -> String {
+        self.connecting
+            .as_ref()
+            .or(self.session_info.as_ref())
+            .map(|info| format_address(&info.host, info.port))
+            .unwrap_or_else(|| "Not connected".to_string())
+    }
+
+    /// Whether the tab is free for a new connection: neither connected nor
+    /// about to be.
+    pub fn idle(&self) -> bool {
+        self.connection.is_none() && self.connection_state == ConnectionState::Disconnected
+    }
+
+    pub fn log(&mut self, message: String) {
+        push_log(&mut self.log, message);
+        self.activity = true;
+    }
+
+    pub fn schedule_reconnect(&mut self) {
+        self.reconnect_attempts += 1;
+        let attempt = self.reconnect_attempts;
+        let delay = reconnect::delay(attempt);
+        self.connection_state = ConnectionState::Reconnecting {
+            attempt,
+            at: Instant::now() + delay,
+        };
+        self.log(format!(
+            "[INFO] Reconnecting in {}s (attempt {})...",
+            delay.as_secs(),
+            attempt
+        ));
+    }
+
+    pub fn stop_reconnecting(&mut self) {
+        self.connection_state = ConnectionState::Disconnected;
+        self.restore = None;
+        self.reconnect_attempts = 0;
+    }
+}
+
+/// Whether `message` may go to the session of `tab`: voice and the targets
+/// it is whispered to only go to the session of the `voice` tab, so that
+/// only one session transmits.
+pub fn may_send(voice: Option<TabId>, tab: TabId, message: &Message) -> bool {
+    !matches!(message, Message::UdpTunnel(_) | Message::VoiceTarget(_)) || voice == Some(tab)
+}
+
+/// Whether tab `index` has news to point out, which the shown one never has.
+fn has_activity(tabs: &[SessionTab], active: usize, index: usize) -> bool {
+    index != active && tabs[index].activity
+}
+
+fn has_alert(tabs: &[SessionTab], active: usize, index: usize) -> bool {
+    index != active && tabs[index].alert
+}
+
+/// The label of each tab: its number and server, "♪" on the tab voice is
+/// transmitted on, "!" on other tabs with alerts and "•" on those with news.
+fn labels(tabs: &[SessionTab], active: usize, voice: Option<TabId>) -> Vec<String> {
+    tabs.iter()
+        .enumerate()
+        .map(|(i, tab)| {
+            let marker = if voice == Some(tab.id) {
+                " ♪"
+            } else if has_alert(tabs, active, i) {
+                " !"
+            } else if has_activity(tabs, active, i) {
+                " •"
+            } else {
+                ""
+            };
+            format!(" {} {}{} ", i + 1, tab.title(), marker)
+        })
+        .collect()
+}
+
+pub fn render_tab_bar(
+    frame: &mut Frame,
+    area: Rect,
+    tabs: &[SessionTab],
+    active: usize,
+    voice: Option<TabId>,
+    theme: &Theme,
+) {
+    let mut spans = Vec::new();
+    for (i, label) in labels(tabs, active, voice).into_iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::styled(SEPARATOR, theme.fg(theme.muted)));
+        }
+        let style = if i == active {
+            theme.selected().bold()
+        } else if has_alert(tabs, active, i) {
+            theme.fg(theme.bad).bold().reversed()
+        } else if has_activity(tabs, active, i) {
+            theme.fg(theme.busy)
+        } else {
+            Style::default()
+        };
+        spans.push(Span::styled(label, style));
+    }
+    frame.render_widget(Line::from(spans), area);
+}
+
+/// The tab whose label is at `column` of a bar drawn from `start`.
+pub fn tab_at(
+    tabs: &[SessionTab],
+    active: usize,
+    voice: Option<TabId>,
+    start: u16,
+    column: u16,
+) -> Option<usize> {
+    let mut x = start;
+    for (i, label) in labels(tabs, active, voice).iter().enumerate() {
+        let end = x.saturating_add(Span::raw(label.as_str()).width() as u16);
+        if (x..end).contains(&column) {
+            return Some(i);
+        }
+        x = end.saturating_add(SEPARATOR.chars().count() as u16);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto;
+
+    #[test]
+    fn test_labels_and_clicks() {
+        let mut tabs = vec![SessionTab::new(1), SessionTab::new(2)];
+        tabs[1].connecting = Some(ConnectionInfo::new("voice.example".to_string(), 64738));
+        tabs[1].log("[INFO] Connected.".to_string());
+        assert!(tabs[0].idle());
+        assert_eq!(
+            labels(&tabs, 0, Some(1)),
+            [" 1 Not connected ♪ ", " 2 voice.example • "]
+        );
+        // The shown tab never points out its own news.
+        assert_eq!(labels(&tabs, 1, None)[1], " 2 voice.example ");
+        tabs[1].alert = true;
+        assert_eq!(labels(&tabs, 0, Some(1))[1], " 2 voice.example ! ");
+
+        // " 1 Not connected ♪ " is 19 columns, then the separator.
+        assert_eq!(tab_at(&tabs, 0, Some(1), 10, 10), Some(0));
+        assert_eq!(tab_at(&tabs, 0, Some(1), 10, 28), Some(0));
+        assert_eq!(tab_at(&tabs, 0, Some(1), 10, 29), None);
+        assert_eq!(tab_at(&tabs, 0, Some(1), 10, 30), Some(1));
+        assert_eq!(tab_at(&tabs, 0, Some(1), 10, 9), None);
+        assert_eq!(tab_at(&tabs, 0, Some(1), 10, 80), None);
+    }
+
+    #[test]
+    fn test_log_drops_oldest_lines() {
+        let mut tab = SessionTab::new(0);
+        for i in 0..=LOG_LIMIT {
+            tab.log(i.to_string());
+        }
+        assert_eq!(tab.log.len(), LOG_LIMIT);
+        assert_eq!(tab.log[0].1, "1");
+    }
+
+    #[test]
+    fn test_only_the_voice_tab_transmits() {
+        let voice: Message = proto::UdpTunnel::default().into();
+        let target: Message = proto::VoiceTarget::default().into();
+        let text: Message = proto::TextMessage::default().into();
+        assert!(may_send(Some(1), 1, &voice));
+        assert!(may_send(Some(1), 1, &target));
+        assert!(!may_send(Some(1), 2, &voice));
+        assert!(!may_send(Some(1), 2, &target));
+        assert!(!may_send(None, 1, &voice));
+        assert!(may_send(Some(1), 2, &text));
+        assert!(may_send(None, 2, &text));
+    }
+}
@@ -98,3 +98,68 @@ fn test_invalid_hash_error() {
     let error_msg_str = String::from_utf8_lossy(&output.stderr);
     assert!(error_msg_str.contains("Invalid character 'i' at position 0"));
 }
+
+#[test]
+#[allow(deprecated)]
+fn test_generate_cert_options() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cert_path = temp_dir.path().join("mumble-server.pem");
+    let key_path = temp_dir.path().join("mumble-server.key");
+
+    let mut cmd = Command::cargo_bin("mumble-server").unwrap();
+    let assert = cmd
+        .current_dir(temp_dir.path())
+        .args(["--generate-cert", "--key-algorithm", "rsa2048"])
+        .args(["--cert-san", "mumble.example.org", "--cert-san", "192.0.2.7"])
+        .args(["--cert-cn", "Example Mumble", "--cert-days", "365"])
+        .assert()
+        .success();
+
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    assert!(stderr.contains("RSA-2048"));
+    assert!(stderr.contains("SHA-1 fingerprint: "));
+    assert!(stderr.contains("SHA-256 fingerprint: "));
+    assert!(cert_path.exists());
+    let key = std::fs::read_to_string(&key_path).unwrap();
+    assert!(key.contains("BEGIN PRIVATE KEY"));
+
+    // A seed only makes sense for Ed25519.
+    let mut cmd = Command::cargo_bin("mumble-server").unwrap();
+    cmd.current_dir(temp_dir.path())
+        .args(["--generate-cert", "--key-algorithm", "rsa2048", "--key-from-hash"])
+        .arg("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        .assert()
+        .failure();
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_client_derived_identity_hash() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    let derive = || {
+        let mut cmd = Command::cargo_bin("mumble-client").unwrap();
+        let output = cmd
+            .env("XDG_CONFIG_HOME", temp_dir.path())
+            .args(["identity", "derive", "alice", "--print-only", "--key-from-hash", hash])
+            .assert()
+            .success();
+        String::from_utf8_lossy(&output.get_output().stdout).trim().to_string()
+    };
+
+    let first = derive();
+    assert_eq!(first.len(), 40);
+    assert_eq!(first, derive());
+    // --print-only must not store anything.
+    assert!(!temp_dir.path().join("mumble-rs").exists());
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_client_rejects_invalid_url() {
+    let mut cmd = Command::cargo_bin("mumble-client").unwrap();
+    let output = cmd.arg("https://example.com").assert().failure();
+    let stderr = String::from_utf8_lossy(&output.get_output().stderr);
+    assert!(stderr.contains("is not a mumble:// URL"));
+}
@@ -0,0 +1,221 @@
+use mumble::builder::ServerBuilder;
+use mumble::config::MetaParams;
+use mumble::connection::{
+    connect, CertificateChanged, ConnectOptions, ConnectionEvent, DisconnectReason, Rejected,
+    Trust,
+};
+use mumble::known_hosts::KnownHosts;
+use mumble::proto::{self, RejectType};
+use mumble::server::{ServerHandle, ServerStatus};
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::ServerConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+
+async fn start_server(password: &str) -> ServerHandle {
+    let params = MetaParams {
+        port: 0,
+        password: password.to_string(),
+        welcome_text: "Hello from the test server".to_string(),
+        ..Default::default()
+    };
+
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+        certified.signing_key.serialize_der(),
+    ));
+    let tls_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![certified.cert.der().clone()], key)
+        .unwrap();
+
+    ServerBuilder::new(params)
+        .db_connection(tokio_rusqlite::Connection::open_in_memory().await.unwrap())
+        .tls_acceptor(TlsAcceptor::from(Arc::new(tls_config)))
+        .start()
+        .await
+        .unwrap()
+}
+
+fn options(server: &ServerHandle, username: &str) -> ConnectOptions {
+    ConnectOptions {
+        host: "127.0.0.1".to_string(),
+        port: server.local_addr().port(),
+        username: username.to_string(),
+        password: None,
+        tokens: Vec::new(),
+        identity: None,
+        known_hosts: None,
+    }
+}
+
+async fn next_event(events: &mut mpsc::UnboundedReceiver<ConnectionEvent>) -> ConnectionEvent {
+    tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out waiting for a connection event")
+        .expect("event channel closed")
+}
+
+#[tokio::test]
+async fn test_handshake_builds_model() {
+    let server = start_server("").await;
+    let (session, mut events) = connect(options(&server, "alice")).await.unwrap();
+
+    match next_event(&mut events).await {
+        ConnectionEvent::Connected { welcome_text, .. } => {
+            assert_eq!(welcome_text, "Hello from the test server");
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    let model = session.model();
+    {
+        let model = model.lock().unwrap();
+        assert!(model.is_synced());
+        assert_eq!(model.channels[&0].name, "Root");
+        assert_eq!(model.own_user().unwrap().name, "alice");
+        assert_eq!(model.server_version.as_deref(), Some("1.5.0"));
+    }
+
+    // ServerConfig follows ServerSync and the first ping goes out right
+    // after the handshake.
+    for _ in 0..50 {
+        {
+            let model = model.lock().unwrap();
+            if model.config.is_some() && model.tcp_ping.is_some() {
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(model.lock().unwrap().config.is_some());
+    assert!(model.lock().unwrap().tcp_ping.is_some());
+
+    session.disconnect().await;
+    assert_eq!(
+        next_event(&mut events).await,
+        ConnectionEvent::Disconnected(DisconnectReason::Local)
+    );
+}
+
+#[tokio::test]
+async fn test_other_users_and_messages() {
+    let server = start_server("").await;
+    let (alice, mut alice_events) = connect(options(&server, "alice")).await.unwrap();
+    next_event(&mut alice_events).await;
+
+    let (bob, mut bob_events) = connect(options(&server, "bob")).await.unwrap();
+    next_event(&mut bob_events).await;
+    let bob_session = bob.model().lock().unwrap().session.unwrap();
+    assert_eq!(bob.model().lock().unwrap().users.len(), 2);
+
+    assert_eq!(
+        next_event(&mut alice_events).await,
+        ConnectionEvent::UserJoined(bob_session)
+    );
+
+    bob.send(proto::UserState {
+        self_mute: Some(true),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        next_event(&mut alice_events).await,
+        ConnectionEvent::UserChanged(bob_session)
+    );
+    assert!(alice.model().lock().unwrap().users[&bob_session].self_mute);
+
+    bob.send(proto::TextMessage {
+        channel_id: vec![0],
+        message: "hi alice".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+    match next_event(&mut alice_events).await {
+        ConnectionEvent::TextMessage(text) => {
+            assert_eq!(text.message, "hi alice");
+            assert_eq!(text.actor, Some(bob_session));
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    bob.disconnect().await;
+    match next_event(&mut alice_events).await {
+        ConnectionEvent::UserLeft { session, name, .. } => {
+            assert_eq!(session, bob_session);
+            assert_eq!(name, "bob");
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    alice.disconnect().await;
+}
+
+#[tokio::test]
+async fn test_rejections() {
+    let server = start_server("secret").await;
+
+    let err = connect(options(&server, "alice")).await.err().unwrap();
+    let rejected = err.downcast_ref::<Rejected>().unwrap();
+    assert_eq!(rejected.reject_type, RejectType::WrongServerPw);
+
+    let mut with_password = options(&server, "alice");
+    with_password.password = Some("secret".to_string());
+    let (alice, _events) = connect(with_password.clone()).await.unwrap();
+
+    let err = connect(with_password).await.err().unwrap();
+    let rejected = err.downcast_ref::<Rejected>().unwrap();
+    assert_eq!(rejected.reject_type, RejectType::UsernameInUse);
+
+    alice.disconnect().await;
+}
+
+#[tokio::test]
+async fn test_known_hosts() {
+    let server = start_server("").await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("known_hosts.json");
+    let mut options = options(&server, "alice");
+    options.known_hosts = Some(path.clone());
+
+    let (alice, _events) = connect(options.clone()).await.unwrap();
+    assert_eq!(alice.tls.trust, Trust::FirstUse);
+    let fingerprint = alice.tls.chain[0].sha256.clone();
+    alice.disconnect().await;
+    let known = KnownHosts::load_from(&path).unwrap();
+    assert_eq!(known.get("127.0.0.1", options.port).unwrap().fingerprint, fingerprint);
+
+    let (alice, _events) = connect(options.clone()).await.unwrap();
+    assert_eq!(alice.tls.trust, Trust::KnownHost);
+    alice.disconnect().await;
+
+    let mut known = KnownHosts::load_from(&path).unwrap();
+    known.trust("127.0.0.1", options.port, "00:11:22");
+    known.save_to(&path).unwrap();
+    let err = connect(options).await.err().unwrap();
+    let changed = err.downcast_ref::<CertificateChanged>().unwrap();
+    assert_eq!(changed.old, "00:11:22");
+    assert_eq!(changed.new, fingerprint);
+}
+
+#[tokio::test]
+async fn test_server_handle_stats_and_shutdown() {
+    let server = start_server("").await;
+    assert_eq!(server.status(), ServerStatus::Running);
+
+    let (_alice, mut events) = connect(options(&server, "alice")).await.unwrap();
+    next_event(&mut events).await;
+    let stats = server.stats();
+    assert_eq!(stats.users, 1);
+    assert_eq!(stats.channels, 1);
+    assert_eq!(stats.connections, 1);
+
+    let port = server.local_addr().port();
+    server.shutdown().await.unwrap();
+    match next_event(&mut events).await {
+        ConnectionEvent::Disconnected(_) => {}
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert!(tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_err());
+}
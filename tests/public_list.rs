@@ -0,0 +1,121 @@
+use mumble::public::{ListSource, PublicList};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const LIST_XML: &str = r#"<?xml version='1.0' standalone='yes'?>
+<servers>
+	<server name="Stub One" ca="1" continent_code="EU" country="Germany" country_code="DE" ip="one.example.org" port="64738" region="Berlin" url="https://one.example.org" />
+	<server name="Stub Two" ca="0" continent_code="NA" country="Canada" country_code="CA" ip="two.example.org" port="64739" region="Ontario" url="" />
+</servers>
+"#;
+
+/// Serves `body` with `status` to every request and counts the hits.
+async fn spawn_stub(status: u16, body: &'static str) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    let hits_clone = Arc::clone(&hits);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            hits_clone.fetch_add(1, Ordering::SeqCst);
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).await;
+            let response = format!(
+                "HTTP/1.1 {} Stub\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+    (format!("http://{}/v1/list", addr), hits)
+}
+
+#[tokio::test]
+async fn test_refresh_writes_cache() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_path = temp_dir.path().join("cache").join("public_servers.xml");
+    let (url, hits) = spawn_stub(200, LIST_XML).await;
+    let list = PublicList::new(url, &cache_path);
+
+    let fetched = list.refresh().await.unwrap();
+    assert_eq!(fetched.source, ListSource::Network);
+    assert_eq!(fetched.servers.len(), 2);
+    assert_eq!(fetched.servers[1].host, "two.example.org");
+    assert_eq!(fetched.servers[1].port, 64739);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    assert!(cache_path.exists());
+
+    // A fresh cache is used without another request.
+    let loaded = list.load().await;
+    assert!(matches!(loaded.source, ListSource::Cache { .. }));
+    assert_eq!(loaded.servers.len(), 2);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_stale_cache_is_refreshed() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_path = temp_dir.path().join("public_servers.xml");
+    std::fs::write(&cache_path, LIST_XML).unwrap();
+    let (url, hits) = spawn_stub(200, LIST_XML).await;
+    let list = PublicList::new(url, &cache_path).with_max_age(Duration::ZERO);
+
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(matches!(
+        list.load_cached().source,
+        ListSource::StaleCache { .. }
+    ));
+    let loaded = list.load().await;
+    assert_eq!(loaded.source, ListSource::Network);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_failed_fetch_keeps_stale_cache() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_path = temp_dir.path().join("public_servers.xml");
+    std::fs::write(&cache_path, LIST_XML).unwrap();
+    let (url, hits) = spawn_stub(503, "unavailable").await;
+    let list = PublicList::new(url, &cache_path).with_max_age(Duration::ZERO);
+
+    std::thread::sleep(Duration::from_millis(10));
+    let loaded = list.load().await;
+    assert!(matches!(loaded.source, ListSource::StaleCache { .. }));
+    assert_eq!(loaded.servers.len(), 2);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    assert_eq!(std::fs::read_to_string(&cache_path).unwrap(), LIST_XML);
+}
+
+#[tokio::test]
+async fn test_invalid_response_does_not_replace_cache() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_path = temp_dir.path().join("public_servers.xml");
+    std::fs::write(&cache_path, LIST_XML).unwrap();
+    let (url, _hits) = spawn_stub(200, "<servers><server name=").await;
+    let list = PublicList::new(url, &cache_path);
+
+    assert!(list.refresh().await.is_err());
+    assert_eq!(std::fs::read_to_string(&cache_path).unwrap(), LIST_XML);
+}
+
+#[tokio::test]
+async fn test_unreachable_without_cache_uses_bundled_list() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_path = temp_dir.path().join("public_servers.xml");
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/v1/list", listener.local_addr().unwrap());
+    drop(listener);
+    let list = PublicList::new(url, &cache_path);
+
+    let loaded = list.load().await;
+    assert_eq!(loaded.source, ListSource::Bundled);
+    assert!(!loaded.servers.is_empty());
+    assert!(!cache_path.exists());
+}